@@ -1,3 +1,5 @@
+#include <stdarg.h>
+
 int sub(int a, int b) {
     return a - b;
 }
@@ -12,4 +14,67 @@ int sum6(int a, int b, int c, int d, int e, int f) {
 
 int three() {
     return 3;
-}
\ No newline at end of file
+}
+int apply(int (*f)(int), int x) {
+    return f(x);
+}
+
+long vsum(int n, va_list ap) {
+    long sum = 0;
+    for (int i = 0; i < n; i++) {
+        sum += va_arg(ap, long);
+    }
+    return sum;
+}
+
+int call_variadic(int (*f)(int, ...)) {
+    return f(8, 1L, 2L, 3L, 4L, 5L, 6L, 7L, 8L);
+}
+
+// int is 64 bits wide in rust-9cc, which corresponds to long in gcc
+struct Pair {
+    long a;
+    long b;
+};
+
+struct Triple {
+    long a;
+    long b;
+    long c;
+};
+
+struct Pair make_pair(long a, long b) {
+    struct Pair p = {a, b};
+    return p;
+}
+
+long pair_diff(struct Pair p) {
+    return p.a - p.b;
+}
+
+struct Triple make_triple(long a, long b, long c) {
+    struct Triple t = {a, b, c};
+    return t;
+}
+
+long triple_weighted(struct Triple t) {
+    return t.a * 100 + t.b * 10 + t.c;
+}
+
+// p does not fit in the remaining registers and is passed on the stack
+long pair_after_five(long a, long b, long c, long d, long e, struct Pair p) {
+    return a + b + c + d + e + p.a * p.b;
+}
+
+long call_pair_callback(long (*f)(struct Pair, struct Triple)) {
+    struct Pair p = {3, 4};
+    struct Triple t = {5, 6, 7};
+    return f(p, t);
+}
+
+long call_triple_maker(struct Triple (*f)(long)) {
+    struct Triple t = f(2);
+    return t.a + t.b + t.c;
+}
+
+long extern_value = 42;
@@ -0,0 +1,5 @@
+#pragma once
+#include "nested.h"
+
+#define ANSWER 42
+#define DOUBLE_ANSWER ANSWER * 2
@@ -0,0 +1 @@
+#define FROM_SEARCH_PATH 9
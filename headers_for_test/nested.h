@@ -0,0 +1,3 @@
+#pragma once
+#define NESTED 7
+int nested_value = NESTED;
@@ -0,0 +1,2 @@
+// Refers to a variable which is not defined
+int f() { undefined_value; return 0; }
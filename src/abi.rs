@@ -0,0 +1,62 @@
+use crate::ast::*;
+use crate::layout::Layouts;
+
+// The classes of eightbytes defined by the System V AMD64 ABI.
+// SSE is not listed since there are no floating point types yet.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArgClass {
+    NoClass,
+    Integer,
+    Memory,
+}
+
+/// Classify a value into a list of eightbytes.
+/// A value classified as MEMORY is represented by a single `ArgClass::Memory`.
+pub fn classify(data_type: &DataType, layouts: &Layouts) -> Vec<ArgClass> {
+    let size = layouts.size_of(data_type);
+    if size > 16 {
+        return vec![ArgClass::Memory];
+    }
+    let mut classes = vec![ArgClass::NoClass; size.div_ceil(8).max(1) as usize];
+    classify_fields(data_type, 0, &mut classes, layouts);
+    if classes.contains(&ArgClass::Memory) {
+        vec![ArgClass::Memory]
+    } else {
+        classes
+    }
+}
+
+fn classify_fields(data_type: &DataType, offset: u32, classes: &mut [ArgClass], layouts: &Layouts) {
+    match data_type {
+        DataType::Struct(name) | DataType::Union(name) => {
+            if let Some(struct_info) = layouts.get_struct(name) {
+                for member in struct_info.members.iter() {
+                    classify_fields(&member.data_type, offset + member.offset, classes, layouts);
+                }
+            }
+        }
+        _ => {
+            let index = (offset / 8) as usize;
+            let class = if !offset.is_multiple_of(layouts.align_of(data_type)) {
+                // Unaligned fields make the whole value MEMORY
+                ArgClass::Memory
+            } else {
+                ArgClass::Integer
+            };
+            classes[index] = merge(classes[index], class);
+        }
+    }
+}
+
+fn merge(left: ArgClass, right: ArgClass) -> ArgClass {
+    match (left, right) {
+        (ArgClass::NoClass, class) | (class, ArgClass::NoClass) => class,
+        (ArgClass::Memory, _) | (_, ArgClass::Memory) => ArgClass::Memory,
+        (ArgClass::Integer, ArgClass::Integer) => ArgClass::Integer,
+    }
+}
+
+/// Returns true if the value is passed or returned in memory
+pub fn is_memory_class(classes: &[ArgClass]) -> bool {
+    classes.first() == Some(&ArgClass::Memory)
+}
@@ -1,17 +1,19 @@
 use std::fmt;
+use std::io::{self, Write};
 
 pub type Assembly = Vec<Instruction>;
 
-pub fn print_assembly_code(assembly: &Assembly) {
+pub fn write_assembly_code(out: &mut impl Write, assembly: &Assembly) -> io::Result<()> {
     for instruction in assembly {
-        print_single_instruction(instruction);
+        write_single_instruction(out, instruction)?;
     }
+    Ok(())
 }
 
-pub fn print_single_instruction(instruction: &Instruction) {
+pub fn write_single_instruction(out: &mut impl Write, instruction: &Instruction) -> io::Result<()> {
     match instruction {
-        Instruction::Label(_) => println!("{:?}", instruction),
-        _ => println!("  {:?}", instruction),
+        Instruction::Label(_) => writeln!(out, "{:?}", instruction),
+        _ => writeln!(out, "  {:?}", instruction),
     }
 }
 
@@ -22,23 +24,33 @@ pub enum Instruction {
     Ret,
     Add(Operand, Operand),
     Sub(Operand, Operand),
-    Mul(Operand),
     IMul(Operand, Operand),
     Idiv(Operand),
     Neg(Operand),
     Cqo,
     Movzb(Operand, Operand),
     Mov(Operand, Operand),
+    Movsx(Operand, Operand),
+    Movsd(Operand, Operand),
+    Lea(Operand, Operand),
     Cmp(Operand, Operand),
     Sete(Operand),
     Setne(Operand),
     Setl(Operand),
     Setle(Operand),
     Je(String),
+    Jne(String),
     Jmp(String),
     Label(String),
-    Comment(String),
-    Call(String),
+    Call(Operand),
+    // Directives to define data
+    Section(String),
+    Align(u32),
+    Bytes(Vec<u8>),
+    Quad(String),
+    Zero(u32),
+    // Make a symbol visible to other objects
+    Global(String),
 }
 
 impl fmt::Debug for Instruction {
@@ -49,23 +61,40 @@ impl fmt::Debug for Instruction {
             Instruction::Ret => write!(f, "ret"),
             Instruction::Add(o1, o2) => write!(f, "add {:?}, {:?}", o1, o2),
             Instruction::Sub(o1, o2) => write!(f, "sub {:?}, {:?}", o1, o2),
-            Instruction::Mul(o) => write!(f, "mul {:?}", o),
             Instruction::IMul(o1, o2) => write!(f, "imul {:?}, {:?}", o1, o2),
             Instruction::Idiv(o) => write!(f, "idiv {:?}", o),
             Instruction::Neg(o) => write!(f, "neg {:?}", o),
             Instruction::Cqo => write!(f, "cqo"),
             Instruction::Movzb(o1, o2) => write!(f, "movzb {:?}, {:?}", o1, o2),
             Instruction::Mov(o1, o2) => write!(f, "mov {:?}, {:?}", o1, o2),
+            Instruction::Movsx(o1, o2) => write!(f, "movsx {:?}, {:?}", o1, o2),
+            Instruction::Movsd(o1, o2) => write!(f, "movsd {:?}, {:?}", o1, o2),
+            Instruction::Lea(o1, o2) => write!(f, "lea {:?}, {:?}", o1, o2),
             Instruction::Cmp(o1, o2) => write!(f, "cmp {:?}, {:?}", o1, o2),
             Instruction::Sete(o) => write!(f, "sete {:?}", o),
             Instruction::Setne(o) => write!(f, "setne {:?}", o),
             Instruction::Setl(o) => write!(f, "setl {:?}", o),
             Instruction::Setle(o) => write!(f, "setle {:?}", o),
             Instruction::Je(label) => write!(f, "je {}", label),
+            Instruction::Jne(label) => write!(f, "jne {}", label),
             Instruction::Jmp(label) => write!(f, "jmp {}", label),
             Instruction::Label(label) => write!(f, "{}:", label),
-            Instruction::Comment(comment) => write!(f, "// {}", comment),
-            Instruction::Call(label) => write!(f, "call {}", label),
+            Instruction::Call(o) => write!(f, "call {:?}", o),
+            Instruction::Section(section) => write!(f, "{}", section),
+            Instruction::Align(align) => write!(f, ".align {}", align),
+            Instruction::Bytes(bytes) => {
+                write!(f, ".byte ")?;
+                for (index, byte) in bytes.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", byte)?;
+                }
+                Ok(())
+            }
+            Instruction::Quad(value) => write!(f, ".quad {}", value),
+            Instruction::Zero(size) => write!(f, ".zero {}", size),
+            Instruction::Global(symbol) => write!(f, ".global {}", symbol),
         }
     }
 }
@@ -90,10 +119,6 @@ pub fn sub(operand1: Operand, operand2: Operand) -> Instruction {
     Instruction::Sub(operand1, operand2)
 }
 
-pub fn mul(operand: Operand) -> Instruction {
-    Instruction::Mul(operand)
-}
-
 pub fn imul(operand1: Operand, operand2: Operand) -> Instruction {
     Instruction::IMul(operand1, operand2)
 }
@@ -118,6 +143,18 @@ pub fn mov(operand1: Operand, operand2: Operand) -> Instruction {
     Instruction::Mov(operand1, operand2)
 }
 
+pub fn movsx(operand1: Operand, operand2: Operand) -> Instruction {
+    Instruction::Movsx(operand1, operand2)
+}
+
+pub fn lea(operand1: Operand, operand2: Operand) -> Instruction {
+    Instruction::Lea(operand1, operand2)
+}
+
+pub fn movsd(operand1: Operand, operand2: Operand) -> Instruction {
+    Instruction::Movsd(operand1, operand2)
+}
+
 pub fn cmp(operand1: Operand, operand2: Operand) -> Instruction {
     Instruction::Cmp(operand1, operand2)
 }
@@ -142,6 +179,10 @@ pub fn je(label: String) -> Instruction {
     Instruction::Je(label)
 }
 
+pub fn jne(label: String) -> Instruction {
+    Instruction::Jne(label)
+}
+
 pub fn jmp(label: String) -> Instruction {
     Instruction::Jmp(label)
 }
@@ -150,63 +191,202 @@ pub fn label(label: String) -> Instruction {
     Instruction::Label(label)
 }
 
-pub fn comment(comment: &str) -> Instruction {
-    Instruction::Comment(comment.to_string())
+pub fn call(operand: Operand) -> Instruction {
+    Instruction::Call(operand)
 }
 
-pub fn call(label: String) -> Instruction {
-    Instruction::Call(label)
+pub fn section(name: &str) -> Instruction {
+    Instruction::Section(name.to_string())
 }
 
-#[derive(Clone)]
+pub fn align(align: u32) -> Instruction {
+    Instruction::Align(align)
+}
+
+pub fn bytes(bytes: Vec<u8>) -> Instruction {
+    Instruction::Bytes(bytes)
+}
+
+pub fn quad(value: String) -> Instruction {
+    Instruction::Quad(value)
+}
+
+pub fn zero(size: u32) -> Instruction {
+    Instruction::Zero(size)
+}
+
+pub fn global(symbol: String) -> Instruction {
+    Instruction::Global(symbol)
+}
+
+#[derive(Clone, PartialEq, Eq)]
 pub enum Operand {
     Register(Register),
-    Immediate(i32),
-    Memory(Register),
+    // The lowest byte and the lower 4 bytes of a register
+    ByteRegister(Register),
+    DwordRegister(Register),
+    Immediate(i64),
+    // 8 bytes in memory at the address in the register plus the displacement
+    Memory(Register, i32),
+    ByteMemory(Register, i32),
+    DwordMemory(Register, i32),
+    // The address of a label relative to rip
+    RipRelative(String),
+    Label(String),
+    // The GOT entry holding the address of a symbol, which works for both
+    // symbols in this object and symbols in shared libraries
+    GotEntry(String),
+}
+
+pub fn register(register: Register) -> Operand {
+    Operand::Register(register)
+}
+
+pub fn byte_register(register: Register) -> Operand {
+    Operand::ByteRegister(register)
 }
 
-pub fn immediate(value: i32) -> Operand {
+pub fn dword_register(register: Register) -> Operand {
+    Operand::DwordRegister(register)
+}
+
+pub fn immediate(value: i64) -> Operand {
     Operand::Immediate(value)
 }
 
+pub fn memory(register: Register, displacement: i32) -> Operand {
+    Operand::Memory(register, displacement)
+}
+
+pub fn byte_memory(register: Register, displacement: i32) -> Operand {
+    Operand::ByteMemory(register, displacement)
+}
+
+pub fn dword_memory(register: Register, displacement: i32) -> Operand {
+    Operand::DwordMemory(register, displacement)
+}
+
+pub fn rip_relative(label: String) -> Operand {
+    Operand::RipRelative(label)
+}
+
+pub fn label_operand(label: String) -> Operand {
+    Operand::Label(label)
+}
+
+pub fn got_entry(symbol: String) -> Operand {
+    Operand::GotEntry(symbol)
+}
+
+fn write_address(
+    f: &mut fmt::Formatter<'_>,
+    register: &Register,
+    displacement: i32,
+) -> fmt::Result {
+    match displacement {
+        0 => write!(f, "[{:?}]", register),
+        d if d < 0 => write!(f, "[{:?} - {}]", register, -(d as i64)),
+        d => write!(f, "[{:?} + {}]", register, d),
+    }
+}
+
 impl fmt::Debug for Operand {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Operand::Register(r) => write!(f, "{:?}", r),
+            Operand::ByteRegister(r) => write!(f, "{}", r.byte_name()),
+            Operand::DwordRegister(r) => write!(f, "{}", r.dword_name()),
             Operand::Immediate(i) => write!(f, "{}", i),
-            Operand::Memory(r) => write!(f, "[{:?}]", r),
+            Operand::Memory(r, d) => {
+                write!(f, "QWORD PTR ")?;
+                write_address(f, r, *d)
+            }
+            Operand::ByteMemory(r, d) => {
+                write!(f, "BYTE PTR ")?;
+                write_address(f, r, *d)
+            }
+            Operand::DwordMemory(r, d) => {
+                write!(f, "DWORD PTR ")?;
+                write_address(f, r, *d)
+            }
+            Operand::RipRelative(label) => write!(f, "[rip + {}]", label),
+            Operand::Label(label) => write!(f, "{}", label),
+            Operand::GotEntry(symbol) => write!(f, "QWORD PTR [rip + {}@GOTPCREL]", symbol),
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Register {
     RAX,
-    RBP,
-    RDI,
+    RBX,
+    RCX,
+    RDX,
     RSI,
+    RDI,
+    RBP,
     RSP,
-    RDX,
-    RCX,
     R8,
     R9,
-    AL,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+    XMM0,
+    XMM1,
+    XMM2,
+    XMM3,
+    XMM4,
+    XMM5,
+    XMM6,
+    XMM7,
+}
+
+impl Register {
+    // The names of the 64-bit, 32-bit and 8-bit general purpose registers
+    fn names(&self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Register::RAX => ("rax", "eax", "al"),
+            Register::RBX => ("rbx", "ebx", "bl"),
+            Register::RCX => ("rcx", "ecx", "cl"),
+            Register::RDX => ("rdx", "edx", "dl"),
+            Register::RSI => ("rsi", "esi", "sil"),
+            Register::RDI => ("rdi", "edi", "dil"),
+            Register::RBP => ("rbp", "ebp", "bpl"),
+            Register::RSP => ("rsp", "esp", "spl"),
+            Register::R8 => ("r8", "r8d", "r8b"),
+            Register::R9 => ("r9", "r9d", "r9b"),
+            Register::R10 => ("r10", "r10d", "r10b"),
+            Register::R11 => ("r11", "r11d", "r11b"),
+            Register::R12 => ("r12", "r12d", "r12b"),
+            Register::R13 => ("r13", "r13d", "r13b"),
+            Register::R14 => ("r14", "r14d", "r14b"),
+            Register::R15 => ("r15", "r15d", "r15b"),
+            Register::XMM0 => ("xmm0", "", ""),
+            Register::XMM1 => ("xmm1", "", ""),
+            Register::XMM2 => ("xmm2", "", ""),
+            Register::XMM3 => ("xmm3", "", ""),
+            Register::XMM4 => ("xmm4", "", ""),
+            Register::XMM5 => ("xmm5", "", ""),
+            Register::XMM6 => ("xmm6", "", ""),
+            Register::XMM7 => ("xmm7", "", ""),
+        }
+    }
+
+    fn dword_name(&self) -> &'static str {
+        self.names().1
+    }
+
+    fn byte_name(&self) -> &'static str {
+        self.names().2
+    }
 }
 
 impl fmt::Debug for Register {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Register::RAX => write!(f, "rax"),
-            Register::RBP => write!(f, "rbp"),
-            Register::RDI => write!(f, "rdi"),
-            Register::RSI => write!(f, "rsi"),
-            Register::RSP => write!(f, "rsp"),
-            Register::RDX => write!(f, "rdx"),
-            Register::RCX => write!(f, "rcx"),
-            Register::R8 => write!(f, "r8"),
-            Register::R9 => write!(f, "r9"),
-            Register::AL => write!(f, "al"),
-        }
+        write!(f, "{}", self.names().0)
     }
 }
 
@@ -214,16 +394,12 @@ pub fn rax() -> Operand {
     Operand::Register(Register::RAX)
 }
 
-pub fn m_rax() -> Operand {
-    Operand::Memory(Register::RAX)
-}
-
 pub fn rbp() -> Operand {
     Operand::Register(Register::RBP)
 }
 
-pub fn rdi() -> Operand {
-    Operand::Register(Register::RDI)
+pub fn rdx() -> Operand {
+    Operand::Register(Register::RDX)
 }
 
 pub fn rsp() -> Operand {
@@ -231,5 +407,13 @@ pub fn rsp() -> Operand {
 }
 
 pub fn al() -> Operand {
-    Operand::Register(Register::AL)
+    Operand::ByteRegister(Register::RAX)
+}
+
+pub fn r10() -> Operand {
+    Operand::Register(Register::R10)
+}
+
+pub fn r11() -> Operand {
+    Operand::Register(Register::R11)
 }
@@ -1,4 +1,6 @@
+use lalrpop_util::{ErrorRecovery, ParseError};
 use std::fmt;
+use std::str::FromStr;
 use std::vec::Vec;
 pub struct Program {
     pub program_units: Vec<ProgramUnit>,
@@ -6,15 +8,50 @@ pub struct Program {
 
 #[derive(Debug)]
 pub enum ProgramUnit {
-    FuncDef(DataType, String, Vec<(DataType, String)>, Box<Statement>),
+    // The parameters have their spans.
+    // The boolean is true if the function takes variable arguments (`...`)
+    FuncDef(
+        DataType,
+        String,
+        Vec<(DataType, String, Span)>,
+        bool,
+        Box<Statement>,
+        Option<StorageClass>,
+    ),
+    FuncDecl(DataType, String, Vec<DataType>, bool, Option<StorageClass>),
+    StructDef(String, Vec<(DataType, String)>),
+    UnionDef(String, Vec<(DataType, String)>),
     Statement(Box<Statement>),
 }
 
+/// Byte offsets of a node in the preprocessed source, from `start` up to but not including `end`.
+/// The file, the line and the column are resolved with the source map of the preprocessor.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// An error found by the actions of the parser rather than by the grammar
+#[derive(Clone, PartialEq, Debug)]
+pub struct SyntaxError {
+    pub message: String,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
 #[derive(Debug)]
-pub enum Statement {
+pub enum StatementKind {
     Expr(Box<Expr>),
     Assign(String, Box<Expr>),
     AssignPointer(u32, String, Box<Expr>),
+    // Assignment to an lvalue other than a plain variable, such as a member access
+    AssignLval(Box<Atom>, Box<Expr>),
     Return(Box<Expr>),
     If(Box<Expr>, Box<Statement>, Box<Option<Statement>>),
     Block(Vec<Statement>),
@@ -26,19 +63,53 @@ pub enum Statement {
         Box<Statement>,
     ),
     Break,
-    VarDef(DataType, String),
+    VarDef(DataType, String, Option<Initializer>, Option<StorageClass>),
+    // A placeholder for a statement with a syntax error, which is skipped by the analysis
+    // and never compiled
+    Error,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StorageClass {
+    // Internal linkage at the top level, or static storage duration in a function
+    Static,
+    // A declaration of a variable or a function defined elsewhere
+    Extern,
+}
+
+#[derive(Debug)]
+pub enum Initializer {
+    Expr(Box<Expr>),
+    // A brace enclosed list of initializers, each of which can have designators
+    List(Vec<(Vec<Designator>, Initializer)>),
+}
+
+#[derive(Debug)]
+pub enum Designator {
+    // [index] =
+    Index(u32),
+    // .member =
+    Member(String),
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum DataType {
     Primitive(PrimitiveType),
     Pointer(u32, Box<DataType>),
+    Function(Box<DataType>, Vec<DataType>, bool),
+    Struct(String),
+    Union(String),
+    // The number of elements is None if it is not specified, as in `int a[] = {1, 2};`
+    Array(Box<DataType>, Option<u32>),
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum PrimitiveType {
     Int,
+    Char,
     Void,
+    // The System V `__builtin_va_list`, an array of one `__va_list_tag`
+    VaList,
 }
 
 impl fmt::Display for DataType {
@@ -46,21 +117,59 @@ impl fmt::Display for DataType {
         match self {
             DataType::Primitive(primitive_type) => write!(f, "{}", primitive_type),
             DataType::Pointer(depth, data_type) => {
+                if let DataType::Function(return_type, parameter_types, variadic) = &**data_type {
+                    write!(f, "{} (", return_type)?;
+                    for _ in 0..*depth {
+                        write!(f, "*")?;
+                    }
+                    write!(f, ")")?;
+                    return write_parameter_types(f, parameter_types, *variadic);
+                }
                 write!(f, "{}", data_type)?;
                 for _ in 0..*depth {
                     write!(f, "*")?;
                 }
                 Ok(())
             }
+            DataType::Function(return_type, parameter_types, variadic) => {
+                write!(f, "{} ", return_type)?;
+                write_parameter_types(f, parameter_types, *variadic)
+            }
+            DataType::Struct(name) => write!(f, "struct {}", name),
+            DataType::Union(name) => write!(f, "union {}", name),
+            DataType::Array(data_type, Some(length)) => write!(f, "{}[{}]", data_type, length),
+            DataType::Array(data_type, None) => write!(f, "{}[]", data_type),
         }
     }
 }
 
+fn write_parameter_types(
+    f: &mut fmt::Formatter<'_>,
+    parameter_types: &[DataType],
+    variadic: bool,
+) -> fmt::Result {
+    write!(f, "(")?;
+    for (index, parameter_type) in parameter_types.iter().enumerate() {
+        if index > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", parameter_type)?;
+    }
+    if variadic && parameter_types.is_empty() {
+        write!(f, "...")?;
+    } else if variadic {
+        write!(f, ", ...")?;
+    }
+    write!(f, ")")
+}
+
 impl fmt::Display for PrimitiveType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             PrimitiveType::Int => write!(f, "int"),
+            PrimitiveType::Char => write!(f, "char"),
             PrimitiveType::Void => write!(f, "void"),
+            PrimitiveType::VaList => write!(f, "va_list"),
         }
     }
 }
@@ -69,16 +178,39 @@ pub fn int() -> DataType {
     DataType::Primitive(PrimitiveType::Int)
 }
 
+pub fn char() -> DataType {
+    DataType::Primitive(PrimitiveType::Char)
+}
+
 pub fn void() -> DataType {
     DataType::Primitive(PrimitiveType::Void)
 }
 
 pub fn pointer(depth: u32, data_type: DataType) -> DataType {
-    DataType::Pointer(depth, Box::new(data_type))
+    match data_type {
+        DataType::Pointer(inner_depth, base_type) => {
+            DataType::Pointer(depth + inner_depth, base_type)
+        }
+        _ => DataType::Pointer(depth, Box::new(data_type)),
+    }
+}
+
+pub fn array(data_type: DataType, length: Option<u32>) -> DataType {
+    DataType::Array(Box::new(data_type), length)
+}
+
+pub fn function(return_type: DataType, parameter_types: Vec<DataType>, variadic: bool) -> DataType {
+    DataType::Function(Box::new(return_type), parameter_types, variadic)
 }
 
 #[derive(Debug)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ExprKind {
     ArithExpr(Box<ArithExpr>),
     Equal(Box<ArithExpr>, Box<ArithExpr>),
     NotEqual(Box<ArithExpr>, Box<ArithExpr>),
@@ -105,13 +237,112 @@ pub enum Unary {
     Atom(Box<Atom>),
     Neg(Box<Atom>),
     PointerDeref(Box<Atom>),
+    // The operand of `&` is an lvalue or a function designator
+    AddressOf(Box<Atom>),
+}
+
+#[derive(Debug)]
+pub struct Atom {
+    pub kind: AtomKind,
+    pub span: Span,
 }
 
 #[derive(Debug)]
-pub enum Atom {
+pub enum AtomKind {
     Number(i32),
     Expr(Box<Expr>),
     Variable(String),
-    AddressOf(String),
     FunctionCall(String, Vec<Expr>),
+    // A call of an atom other than a name, as in `(*f)(x)`, `t[0](x)` and `o.f(x)`
+    IndirectCall(Box<Atom>, Vec<Expr>),
+    // The bytes of a string literal without the terminating null character
+    StringLiteral(Vec<u8>),
+    // atom[index]
+    Index(Box<Atom>, Box<Expr>),
+    // atom.member
+    Member(Box<Atom>, String),
+    // atom->member
+    PointerMember(Box<Atom>, String),
+    // va_start(ap, last_parameter)
+    VaStart(Box<Expr>, String),
+    // va_arg(ap, type)
+    VaArg(Box<Expr>, DataType),
+    // va_end(ap)
+    VaEnd(Box<Expr>),
+    // va_copy(destination, source)
+    VaCopy(Box<Expr>, Box<Expr>),
+}
+
+/// Convert the escape sequences in a string or character literal, given without quotes
+pub fn unescape(literal: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = literal.bytes().peekable();
+    while let Some(c) = chars.next() {
+        if c != b'\\' {
+            bytes.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(b'a') => bytes.push(7),
+            Some(b'b') => bytes.push(8),
+            Some(b'f') => bytes.push(12),
+            Some(b'v') => bytes.push(11),
+            Some(b'e') => bytes.push(27),
+            Some(b'x') => {
+                let mut value: u8 = 0;
+                while let Some(digit) = chars.peek().and_then(|c| (*c as char).to_digit(16)) {
+                    value = value.wrapping_mul(16).wrapping_add(digit as u8);
+                    chars.next();
+                }
+                bytes.push(value);
+            }
+            Some(c @ b'0'..=b'7') => {
+                let mut value = c - b'0';
+                for _ in 0..2 {
+                    match chars.peek() {
+                        Some(digit @ b'0'..=b'7') => {
+                            value = value.wrapping_mul(8).wrapping_add(digit - b'0');
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                bytes.push(value);
+            }
+            Some(c) => bytes.push(c),
+            None => bytes.push(b'\\'),
+        }
+    }
+    bytes
+}
+
+/// Convert an array type to a pointer to its element type, as done for parameters and values
+pub fn decay(data_type: &DataType) -> DataType {
+    match data_type {
+        DataType::Array(element_type, _) => pointer(1, *element_type.clone()),
+        _ => data_type.clone(),
+    }
+}
+
+/// Parse the digits of a number in the source. A number out of the range of `T` is recorded
+/// in `errors` and replaced with zero, so that parsing goes on.
+pub fn decimal<T: FromStr + Default, Token>(
+    digits: &str,
+    span: Span,
+    errors: &mut Vec<ErrorRecovery<usize, Token, SyntaxError>>,
+) -> T {
+    T::from_str(digits).unwrap_or_else(|_| {
+        let error = SyntaxError {
+            message: format!("the number {} is too large", digits),
+            span,
+        };
+        errors.push(ErrorRecovery {
+            error: ParseError::User { error },
+            dropped_tokens: Vec::new(),
+        });
+        T::default()
+    })
 }
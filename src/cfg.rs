@@ -0,0 +1,222 @@
+use crate::ir::*;
+
+// Analyses of the control flow of an IR function:
+// the control flow graph, the dominator tree, dominance frontiers and natural loops.
+// Blocks which cannot be reached from the entry have no dominators and belong to no loops.
+
+pub struct ControlFlowGraph {
+    // Indexed by `BlockId`
+    pub successors: Vec<Vec<BlockId>>,
+    pub predecessors: Vec<Vec<BlockId>>,
+    // The reachable blocks in reverse postorder of a depth-first search from the entry,
+    // where a block comes before its successors except along back edges
+    pub reverse_postorder: Vec<BlockId>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(function: &Function) -> ControlFlowGraph {
+        let successors: Vec<Vec<BlockId>> = function
+            .blocks
+            .iter()
+            .map(|block| block.terminator.successors())
+            .collect();
+        let mut predecessors = vec![Vec::new(); successors.len()];
+        for (index, targets) in successors.iter().enumerate() {
+            for target in targets.iter() {
+                if !predecessors[target.0].contains(&BlockId(index)) {
+                    predecessors[target.0].push(BlockId(index));
+                }
+            }
+        }
+
+        // The stack holds a block with the number of its successors visited so far
+        let mut postorder = Vec::new();
+        let mut visited = vec![false; successors.len()];
+        let mut stack = vec![(BlockId(0), 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.pop() {
+            match successors[block.0].get(next) {
+                Some(successor) => {
+                    stack.push((block, next + 1));
+                    if !visited[successor.0] {
+                        visited[successor.0] = true;
+                        stack.push((*successor, 0));
+                    }
+                }
+                None => postorder.push(block),
+            }
+        }
+        postorder.reverse();
+        ControlFlowGraph {
+            successors,
+            predecessors,
+            reverse_postorder: postorder,
+        }
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.reverse_postorder.contains(&block)
+    }
+}
+
+pub struct DominatorTree {
+    // The immediate dominator of each block, which is None for the entry
+    // and for unreachable blocks
+    pub immediate_dominators: Vec<Option<BlockId>>,
+    // The blocks immediately dominated by each block
+    pub children: Vec<Vec<BlockId>>,
+}
+
+impl DominatorTree {
+    /// Compute the dominators with the iterative algorithm of Cooper, Harvey and Kennedy,
+    /// which intersects the dominators of the predecessors in reverse postorder
+    pub fn new(cfg: &ControlFlowGraph) -> DominatorTree {
+        let count = cfg.successors.len();
+        let mut order = vec![usize::MAX; count];
+        for (index, block) in cfg.reverse_postorder.iter().enumerate() {
+            order[block.0] = index;
+        }
+        let mut immediate_dominators: Vec<Option<BlockId>> = vec![None; count];
+        immediate_dominators[0] = Some(BlockId(0));
+        let intersect = |dominators: &[Option<BlockId>], mut left: BlockId, mut right: BlockId| {
+            while left != right {
+                while order[left.0] > order[right.0] {
+                    left = dominators[left.0].unwrap();
+                }
+                while order[right.0] > order[left.0] {
+                    right = dominators[right.0].unwrap();
+                }
+            }
+            left
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in cfg.reverse_postorder.iter().skip(1) {
+                let mut new_dominator = None;
+                for predecessor in cfg.predecessors[block.0].iter() {
+                    if immediate_dominators[predecessor.0].is_none() {
+                        continue;
+                    }
+                    new_dominator = Some(match new_dominator {
+                        None => *predecessor,
+                        Some(dominator) => {
+                            intersect(&immediate_dominators, *predecessor, dominator)
+                        }
+                    });
+                }
+                if immediate_dominators[block.0] != new_dominator {
+                    immediate_dominators[block.0] = new_dominator;
+                    changed = true;
+                }
+            }
+        }
+        immediate_dominators[0] = None;
+
+        let mut children = vec![Vec::new(); count];
+        for block in cfg.reverse_postorder.iter() {
+            if let Some(dominator) = immediate_dominators[block.0] {
+                children[dominator.0].push(*block);
+            }
+        }
+        DominatorTree {
+            immediate_dominators,
+            children,
+        }
+    }
+
+    /// Returns true if every path from the entry to `block` goes through `dominator`.
+    /// A block dominates itself.
+    pub fn dominates(&self, dominator: BlockId, block: BlockId) -> bool {
+        let mut current = Some(block);
+        while let Some(ancestor) = current {
+            if ancestor == dominator {
+                return true;
+            }
+            current = self.immediate_dominators[ancestor.0];
+        }
+        false
+    }
+
+    /// The dominance frontier of each block: the blocks where its dominance ends,
+    /// which are the join points where definitions in the block meet others
+    pub fn dominance_frontiers(&self, cfg: &ControlFlowGraph) -> Vec<Vec<BlockId>> {
+        let mut frontiers: Vec<Vec<BlockId>> = vec![Vec::new(); cfg.successors.len()];
+        for block in cfg.reverse_postorder.iter() {
+            let predecessors: Vec<&BlockId> = cfg.predecessors[block.0]
+                .iter()
+                .filter(|predecessor| cfg.is_reachable(**predecessor))
+                .collect();
+            if predecessors.len() < 2 {
+                continue;
+            }
+            for predecessor in predecessors {
+                // Walk up from the predecessor to the immediate dominator of the join point
+                let mut runner = Some(*predecessor);
+                while let Some(current) = runner {
+                    if Some(current) == self.immediate_dominators[block.0] {
+                        break;
+                    }
+                    if !frontiers[current.0].contains(block) {
+                        frontiers[current.0].push(*block);
+                    }
+                    runner = self.immediate_dominators[current.0];
+                }
+            }
+        }
+        frontiers
+    }
+}
+
+/// A natural loop, which is entered only through its header
+pub struct Loop {
+    pub header: BlockId,
+    // The sources of the back edges to the header
+    pub latches: Vec<BlockId>,
+    // The blocks of the loop including the header, in the order of their ids
+    pub blocks: Vec<BlockId>,
+}
+
+/// Find the natural loops given by back edges, which go from a block to one dominating it.
+/// Loops sharing a header are merged, and they are ordered by their headers.
+pub fn natural_loops(cfg: &ControlFlowGraph, dominators: &DominatorTree) -> Vec<Loop> {
+    let mut loops: Vec<Loop> = Vec::new();
+    for block in cfg.reverse_postorder.iter() {
+        for successor in cfg.successors[block.0].iter() {
+            if !dominators.dominates(*successor, *block) {
+                continue;
+            }
+            let index = match loops.iter().position(|l| l.header == *successor) {
+                Some(index) => index,
+                None => {
+                    loops.push(Loop {
+                        header: *successor,
+                        latches: Vec::new(),
+                        blocks: vec![*successor],
+                    });
+                    loops.len() - 1
+                }
+            };
+            let current = &mut loops[index];
+            current.latches.push(*block);
+            // The body consists of the blocks reaching the latch without going through the header
+            let mut stack = vec![*block];
+            while let Some(member) = stack.pop() {
+                if current.blocks.contains(&member) {
+                    continue;
+                }
+                current.blocks.push(member);
+                stack.extend(
+                    cfg.predecessors[member.0]
+                        .iter()
+                        .filter(|predecessor| cfg.is_reachable(**predecessor)),
+                );
+            }
+        }
+    }
+    for current in loops.iter_mut() {
+        current.blocks.sort();
+    }
+    loops.sort_by_key(|l| l.header);
+    loops
+}
@@ -1,15 +1,226 @@
+use crate::ast::{DataType, Span};
+use crate::diagnostic::{Diagnostic, Label};
+use crate::preprocessor::SourceLocation;
 use std::fmt;
+use std::sync::Arc;
 
 pub enum CompilerError {
     UndefinedVariable(String),
-    TypeMismatch(String),
+    // The types of the left and the right operands of a binary operator
+    MismatchedOperands(DataType, DataType),
+    // The types of the if branch and the else branch
+    MismatchedBranches(DataType, DataType),
+    // The operator and the type of its operand
+    InvalidOperand(String, DataType),
+    // The type of the operand of `*`
+    InvalidDereference(DataType),
+    // The type of the operand of `[]`
+    NotSubscriptable(DataType),
+    // The type of the operand of `.`
+    NotAStruct(DataType),
+    // The type of the operand of `->`
+    NotAStructPointer(DataType),
+    NoMember(DataType, String),
+    // A call of a variable which is not a function pointer,
+    // with the type of the variable and the span of its declaration
+    NotAFunction(String, DataType, Span),
+    // A call of an expression which is not a function pointer, with its type
+    NotCallable(DataType),
+    NotAnLvalue,
+    // The number of parameters, true if the function is variadic, and the number of arguments
+    WrongArgumentCount(usize, bool, usize),
+    IncompleteType(DataType),
+    // A type whose size does not fit in 32 bits
+    TypeTooLarge(DataType),
+    // The type of the destination and the type of the value assigned, passed or returned
+    IncompatibleTypes(DataType, DataType),
+    // The type of a condition or an operand of a comparison
+    NotAScalar(DataType),
+    VaStartOutsideVariadic,
+    // A statement at the top level, which would be a part of the synthesized main,
+    // in a program defining its own main
+    TopLevelStatementWithMain,
+    // The type of the operand of va_start, va_arg, va_end or va_copy
+    NotAVaList(DataType),
+    // The type given to va_arg, which is not of the INTEGER class
+    UnsupportedVaArgType(DataType),
+    InvalidInitializer(String),
+    // Where the comment starts
+    UnterminatedComment(SourceLocation),
+    PreprocessError(SourceLocation, String),
+    // An invalid option or a wrong number of arguments given to the compiler
+    InvalidCommandLine(String),
+    // A failure to read or write a file, or to run the assembler or the linker
+    DriverError(String),
+    // An inconsistency found in the compiler itself, such as invalid IR
+    InternalError(String),
+    // An error with the span of the innermost node where it occurred
+    Located(Span, Box<CompilerError>),
+}
+
+impl CompilerError {
+    /// Attach the span of a node unless the error already has a location
+    pub fn at(self, span: Span) -> CompilerError {
+        match self {
+            CompilerError::Located(_, _)
+            | CompilerError::UnterminatedComment(_)
+            | CompilerError::PreprocessError(_, _) => self,
+            _ => CompilerError::Located(span, Box::new(self)),
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let code = match self {
+            CompilerError::Located(span, error) => {
+                let mut diagnostic = error.to_diagnostic();
+                diagnostic.primary = Some(Label {
+                    span: *span,
+                    message: String::new(),
+                });
+                return diagnostic;
+            }
+            CompilerError::UndefinedVariable(_) => "E0002",
+            CompilerError::MismatchedOperands(_, _) => "E0003",
+            CompilerError::InvalidInitializer(_) => "E0004",
+            CompilerError::UnterminatedComment(_) => "E0005",
+            CompilerError::PreprocessError(_, _) => "E0006",
+            CompilerError::NotAFunction(_, _, _) => "E0007",
+            CompilerError::MismatchedBranches(_, _) => "E0008",
+            CompilerError::InvalidOperand(_, _) => "E0009",
+            CompilerError::InvalidDereference(_) => "E0010",
+            CompilerError::NotSubscriptable(_) => "E0011",
+            CompilerError::NotAStruct(_) => "E0012",
+            CompilerError::NotAStructPointer(_) => "E0013",
+            CompilerError::NoMember(_, _) => "E0014",
+            CompilerError::NotCallable(_) => "E0015",
+            CompilerError::NotAnLvalue => "E0016",
+            CompilerError::WrongArgumentCount(_, _, _) => "E0017",
+            CompilerError::IncompleteType(_) => "E0018",
+            CompilerError::VaStartOutsideVariadic => "E0019",
+            CompilerError::InvalidCommandLine(_) => "E0020",
+            CompilerError::DriverError(_) => "E0021",
+            CompilerError::InternalError(_) => "E0022",
+            CompilerError::IncompatibleTypes(_, _) => "E0023",
+            CompilerError::NotAScalar(_) => "E0024",
+            CompilerError::NotAVaList(_) => "E0025",
+            CompilerError::UnsupportedVaArgType(_) => "E0026",
+            CompilerError::TypeTooLarge(_) => "E0027",
+            CompilerError::TopLevelStatementWithMain => "E0028",
+        };
+        let mut diagnostic = Diagnostic::error(code, self.to_string());
+        match self {
+            CompilerError::UndefinedVariable(name) => {
+                diagnostic
+                    .help
+                    .push(format!("declare `{}` before using it", name));
+            }
+            CompilerError::TopLevelStatementWithMain => {
+                diagnostic
+                    .help
+                    .push("move the statement into `main`".to_string());
+            }
+            // The location in the original files is rendered with a source of its line
+            CompilerError::UnterminatedComment(location)
+            | CompilerError::PreprocessError(location, _) => {
+                let (source, span) = location.to_source();
+                diagnostic.primary = Some(Label {
+                    span,
+                    message: String::new(),
+                });
+                diagnostic.source = Some(Arc::new(source));
+            }
+            CompilerError::NotAFunction(name, data_type, declared_at) => {
+                diagnostic
+                    .notes
+                    .push(format!("`{}` has type {}", name, data_type));
+                diagnostic.secondary.push(Label {
+                    span: *declared_at,
+                    message: format!("`{}` declared here", name),
+                });
+            }
+            _ => (),
+        }
+        diagnostic
+    }
 }
 
 impl fmt::Display for CompilerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CompilerError::UndefinedVariable(name) => write!(f, "Undefined variable: {}", name),
-            CompilerError::TypeMismatch(msg) => write!(f, "Type mismatch: {}", msg),
+            CompilerError::MismatchedOperands(left, right) => {
+                write!(f, "Mismatched types: left is {}, right is {}", left, right)
+            }
+            CompilerError::MismatchedBranches(if_branch, else_branch) => write!(
+                f,
+                "Mismatched types: if branch is {}, else branch is {}",
+                if_branch, else_branch
+            ),
+            CompilerError::InvalidOperand(operator, data_type) => {
+                write!(f, "Invalid operand of {}: {}", operator, data_type)
+            }
+            CompilerError::InvalidDereference(data_type) => {
+                write!(f, "{} cannot be dereferenced", data_type)
+            }
+            CompilerError::NotSubscriptable(data_type) => {
+                write!(f, "{} is not an array or a pointer", data_type)
+            }
+            CompilerError::NotAStruct(data_type) => {
+                write!(f, "{} is not a struct or union", data_type)
+            }
+            CompilerError::NotAStructPointer(data_type) => {
+                write!(f, "{} is not a pointer to a struct or union", data_type)
+            }
+            CompilerError::NoMember(data_type, member) => {
+                write!(f, "{} has no member {}", data_type, member)
+            }
+            CompilerError::NotAFunction(name, _, _) => {
+                write!(f, "Called object {} is not a function", name)
+            }
+            CompilerError::NotCallable(data_type) => {
+                write!(f, "Called object of type {} is not a function", data_type)
+            }
+            CompilerError::NotAnLvalue => write!(f, "The expression is not an lvalue"),
+            CompilerError::WrongArgumentCount(expected, variadic, found) => write!(
+                f,
+                "Wrong number of arguments: expected {}{}, found {}",
+                expected,
+                if *variadic { " or more" } else { "" },
+                found
+            ),
+            CompilerError::IncompleteType(data_type) => {
+                write!(f, "{} is an incomplete type", data_type)
+            }
+            CompilerError::TypeTooLarge(data_type) => write!(f, "{} is too large", data_type),
+            CompilerError::IncompatibleTypes(target, source) => write!(
+                f,
+                "Incompatible types: {} cannot be converted to {}",
+                source, target
+            ),
+            CompilerError::NotAScalar(data_type) => {
+                write!(f, "{} is used where a scalar is required", data_type)
+            }
+            CompilerError::NotAVaList(data_type) => write!(f, "{} is not a va_list", data_type),
+            CompilerError::UnsupportedVaArgType(data_type) => write!(
+                f,
+                "va_arg of {} is not supported, which only fetches integers and pointers",
+                data_type
+            ),
+            CompilerError::VaStartOutsideVariadic => {
+                write!(f, "va_start used in function with fixed arguments")
+            }
+            CompilerError::TopLevelStatementWithMain => {
+                write!(f, "Top level statement in a program defining main")
+            }
+            CompilerError::InvalidInitializer(msg) => write!(f, "Invalid initializer: {}", msg),
+            CompilerError::UnterminatedComment(_) => write!(f, "Unterminated comment"),
+            CompilerError::PreprocessError(_, msg) => write!(f, "Preprocess error: {}", msg),
+            CompilerError::InvalidCommandLine(msg) | CompilerError::DriverError(msg) => {
+                write!(f, "{}", msg)
+            }
+            CompilerError::InternalError(msg) => write!(f, "Internal compiler error: {}", msg),
+            // The span is resolved to a file, a line and a column by the caller
+            CompilerError::Located(_, error) => write!(f, "{}", error),
         }
     }
 }
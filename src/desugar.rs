@@ -0,0 +1,72 @@
+use crate::ast::*;
+use crate::visit::{
+    walk_program_fold, walk_program_mut, walk_statement_fold, walk_statement_mut, Fold, VisitorMut,
+};
+
+// Rewrite the assignments with a dedicated syntax into the general form, where the left side
+// is any lvalue, so that semantic analysis checks all assignments in one place.
+
+/// Desugar the program before semantic analysis
+pub fn desugar(program: Program) -> Program {
+    let mut program = walk_program_fold(&mut DereferencedAssignments, program);
+    walk_program_mut(&mut VariableAssignments, &mut program);
+    program
+}
+
+// `**p = e` is rebuilt as `(*(*p)) = e`, whose left side is a dereference
+struct DereferencedAssignments;
+
+impl Fold for DereferencedAssignments {
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        let statement = walk_statement_fold(self, statement);
+        let StatementKind::AssignPointer(depth, name, expr) = statement.kind else {
+            return statement;
+        };
+        // The nodes made up for the left side have the span of the statement
+        let span = statement.span;
+        let mut lvalue = Atom {
+            kind: AtomKind::Variable(name),
+            span,
+        };
+        for _ in 0..depth {
+            let unary = Unary::PointerDeref(Box::new(lvalue));
+            let expr = Expr {
+                kind: ExprKind::ArithExpr(Box::new(ArithExpr::Factor(Box::new(Factor::Unary(
+                    Box::new(unary),
+                ))))),
+                span,
+            };
+            lvalue = Atom {
+                kind: AtomKind::Expr(Box::new(expr)),
+                span,
+            };
+        }
+        Statement {
+            kind: StatementKind::AssignLval(Box::new(lvalue), expr),
+            span,
+        }
+    }
+}
+
+// `x = e` assigns to the variable as an lvalue, which is located at the start of the statement
+struct VariableAssignments;
+
+impl VisitorMut for VariableAssignments {
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        walk_statement_mut(self, statement);
+        statement.kind = match std::mem::replace(&mut statement.kind, StatementKind::Break) {
+            StatementKind::Assign(name, expr) => {
+                let span = Span {
+                    start: statement.span.start,
+                    end: statement.span.start + name.len(),
+                };
+                let lvalue = Atom {
+                    kind: AtomKind::Variable(name),
+                    span,
+                };
+                StatementKind::AssignLval(Box::new(lvalue), expr)
+            }
+            kind => kind,
+        };
+    }
+}
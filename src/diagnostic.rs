@@ -0,0 +1,348 @@
+use crate::ast::{Span, SyntaxError};
+use crate::preprocessor::PreprocessedSource;
+use lalrpop_util::lexer::Token;
+use lalrpop_util::ParseError;
+use std::fmt::Write;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// A span with a message shown under its underline, which can be empty
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+// A fix-it: replacing the text of the span with `replacement` fixes the problem.
+// An empty span inserts the replacement.
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+    pub message: String,
+}
+
+/// A message to the user, rendered in the style of rustc with the source lines it refers to
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    // The location of the problem, underlined with carets
+    pub primary: Option<Label>,
+    // Related locations, underlined with hyphens
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+    // The source the spans refer to, which is shared by the diagnostics of a compilation.
+    // Diagnostics without it are rendered without source lines.
+    pub source: Option<Arc<PreprocessedSource>>,
+}
+
+// ANSI escape sequences used when the output is colored
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const GREEN: &str = "\x1b[1;32m";
+const CYAN: &str = "\x1b[1;36m";
+const RESET: &str = "\x1b[0m";
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message,
+            primary: None,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            help: Vec::new(),
+            suggestions: Vec::new(),
+            source: None,
+        }
+    }
+
+    /// A warning, whose code is the option enabling it
+    pub fn warning(code: &'static str, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(code, message)
+        }
+    }
+
+    /// Convert an error of the parser. The offsets are those in the preprocessed source.
+    pub fn from_parse_error(error: &ParseError<usize, Token<'_>, SyntaxError>) -> Diagnostic {
+        let (message, span, expected) = match error {
+            ParseError::InvalidToken { location } => (
+                "invalid token".to_string(),
+                Some(Span {
+                    start: *location,
+                    end: *location + 1,
+                }),
+                &[][..],
+            ),
+            ParseError::UnrecognizedEOF { location, expected } => (
+                "unexpected end of input".to_string(),
+                Some(Span {
+                    start: *location,
+                    end: *location,
+                }),
+                &expected[..],
+            ),
+            ParseError::UnrecognizedToken {
+                token: (start, token, end),
+                expected,
+            } => (
+                format!("unexpected token `{}`", token),
+                Some(Span {
+                    start: *start,
+                    end: *end,
+                }),
+                &expected[..],
+            ),
+            ParseError::ExtraToken {
+                token: (start, token, end),
+            } => (
+                format!("extra token `{}`", token),
+                Some(Span {
+                    start: *start,
+                    end: *end,
+                }),
+                &[][..],
+            ),
+            ParseError::User { error } => (error.message.clone(), Some(error.span), &[][..]),
+        };
+        let mut diagnostic = Diagnostic::error("E0001", message);
+        diagnostic.primary = span.map(|span| Label {
+            span,
+            message: String::new(),
+        });
+        if !expected.is_empty() {
+            let expected: Vec<&str> = expected.iter().map(|t| describe_terminal(t)).collect();
+            diagnostic
+                .notes
+                .push(format!("expected one of {}", expected.join(", ")));
+        }
+        // A missing semicolon is inserted before the unexpected token
+        if let (Some(span), true) = (span, expected.iter().any(|t| t == "\";\"")) {
+            diagnostic.suggestions.push(Suggestion {
+                span: Span {
+                    start: span.start,
+                    end: span.start,
+                },
+                replacement: ";".to_string(),
+                message: "insert `;`".to_string(),
+            });
+        }
+        diagnostic
+    }
+
+    /// Render the diagnostic. Spans are shown with their source lines
+    /// if the preprocessed source is available.
+    pub fn render(&self, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+        let (severity, severity_style) = match self.severity {
+            Severity::Error => ("error", RED),
+            Severity::Warning => ("warning", YELLOW),
+        };
+        let mut result = format!(
+            "{}{}\n",
+            paint(severity_style, &format!("{}[{}]", severity, self.code)),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+
+        let labels: Vec<(&Label, bool)> = self
+            .primary
+            .iter()
+            .map(|label| (label, true))
+            .chain(self.secondary.iter().map(|label| (label, false)))
+            .collect();
+        let snippets: Vec<Snippet> = match self.source.as_deref() {
+            Some(source) => labels
+                .iter()
+                .map(|(label, is_primary)| Snippet::new(source, label, *is_primary))
+                .collect(),
+            None => Vec::new(),
+        };
+        let width = snippets
+            .iter()
+            .map(|snippet| snippet.line.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let gutter = paint(BLUE, &format!("{} |", " ".repeat(width)));
+
+        for (index, snippet) in snippets.iter().enumerate() {
+            let arrow = if index == 0 { "-->" } else { ":::" };
+            let _ = writeln!(
+                result,
+                "{}{} {}:{}:{}",
+                " ".repeat(width),
+                paint(BLUE, arrow),
+                snippet.file,
+                snippet.line,
+                snippet.column
+            );
+            let _ = writeln!(result, "{}", gutter);
+            let line_number = format!("{:>width$} |", snippet.line, width = width);
+            let _ = writeln!(result, "{} {}", paint(BLUE, &line_number), snippet.text);
+            let (marker, style) = if snippet.is_primary {
+                ('^', severity_style)
+            } else {
+                ('-', BLUE)
+            };
+            let mut underline = format!(
+                "{}{}",
+                snippet.indent,
+                marker.to_string().repeat(snippet.length)
+            );
+            if !snippet.message.is_empty() {
+                underline = format!("{} {}", underline, snippet.message);
+            }
+            let _ = writeln!(result, "{} {}", gutter, paint(style, &underline));
+        }
+
+        let suggestions: Vec<String> = self
+            .suggestions
+            .iter()
+            .map(|suggestion| suggestion.message.clone())
+            .collect();
+        if !snippets.is_empty()
+            && (!self.notes.is_empty() || !self.help.is_empty() || !suggestions.is_empty())
+        {
+            let _ = writeln!(result, "{}", gutter);
+        }
+        let notes = self.notes.iter().map(|note| ("note", GREEN, note));
+        let help = self
+            .help
+            .iter()
+            .chain(suggestions.iter())
+            .map(|help| ("help", CYAN, help));
+        for (kind, style, text) in notes.chain(help) {
+            let _ = writeln!(
+                result,
+                "{}{} {}: {}",
+                " ".repeat(width + 1),
+                paint(BLUE, "="),
+                paint(style, kind),
+                text
+            );
+        }
+        result
+    }
+}
+
+/// Render the lines following the diagnostics, which tell the number of warnings,
+/// that there were several errors, or that the rest were omitted because of `limit`
+pub fn render_summary(
+    count: usize,
+    limit: Option<usize>,
+    warning_count: usize,
+    color: bool,
+) -> String {
+    let paint = |style: &str, text: &str| {
+        if color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    };
+    let error = paint(RED, "error");
+    let mut result = String::new();
+    if warning_count > 0 {
+        let _ = writeln!(
+            result,
+            "\n{}: {} warning{} emitted",
+            paint(YELLOW, "warning"),
+            warning_count,
+            if warning_count > 1 { "s" } else { "" }
+        );
+    }
+    if let Some(limit) = limit {
+        let _ = writeln!(
+            result,
+            "\n{}: too many errors emitted, stopping now [-ferror-limit={}]",
+            error, limit
+        );
+    }
+    if count > 1 {
+        let _ = writeln!(
+            result,
+            "\n{}: aborting due to {} previous errors",
+            error, count
+        );
+    }
+    result
+}
+
+// Terminals given by regular expressions are described by what they match
+fn describe_terminal(terminal: &str) -> &str {
+    if !terminal.starts_with("r#") {
+        terminal
+    } else if terminal.contains("[0-9]+") {
+        "number"
+    } else if terminal.contains("[a-zA-Z_]") {
+        "identifier"
+    } else if terminal.starts_with("r#\"'") {
+        "character literal"
+    } else {
+        "string literal"
+    }
+}
+
+// A source line with the location of a label in it
+struct Snippet<'a> {
+    file: &'a str,
+    line: u32,
+    column: u32,
+    text: &'a str,
+    // Whitespace up to the start of the label, keeping tabs to align the underline
+    indent: String,
+    length: usize,
+    message: &'a str,
+    is_primary: bool,
+}
+
+impl<'a> Snippet<'a> {
+    // The line is shown as it is in the original file, before macro expansion
+    fn new(source: &'a PreprocessedSource, label: &'a Label, is_primary: bool) -> Snippet<'a> {
+        let source_map = &source.source_map;
+        let (file, line, column) = source_map.locate(label.span.start);
+        let (end_file, end_line, end_column) = source_map.locate_end(label.span);
+        let text = source_map.original_line(label.span.start);
+        let start = text
+            .char_indices()
+            .nth(column as usize - 1)
+            .map_or(text.len(), |(index, _)| index);
+        let indent = text[..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        // A span continuing to the following lines is underlined up to the end of the line
+        let rest = text[start..].chars().count();
+        let length = if (end_file, end_line) == (file, line) {
+            end_column.saturating_sub(column) as usize
+        } else {
+            rest
+        };
+        Snippet {
+            file,
+            line,
+            column,
+            text,
+            indent,
+            length: length.min(rest).max(1),
+            message: &label.message,
+            is_primary,
+        }
+    }
+}
@@ -0,0 +1,342 @@
+use crate::ast::Span;
+use crate::diagnostic::{render_summary, Diagnostic, Label, Severity};
+use crate::preprocessor::PreprocessedSource;
+use std::fmt;
+
+/// How diagnostics are written, given by `--diagnostics-format`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiagnosticFormat {
+    // Rendered for humans with source snippets
+    Text,
+    // One JSON object per line for each diagnostic
+    Json,
+    // A SARIF 2.1.0 log of all the diagnostics
+    Sarif,
+}
+
+impl DiagnosticFormat {
+    pub fn from_name(name: &str) -> Option<DiagnosticFormat> {
+        match name {
+            "text" => Some(DiagnosticFormat::Text),
+            "json" => Some(DiagnosticFormat::Json),
+            "sarif" => Some(DiagnosticFormat::Sarif),
+            _ => None,
+        }
+    }
+}
+
+/// Render all the diagnostics of a compilation. Spans are resolved with the preprocessed source
+/// of each diagnostic if it is available. `limit` is the error limit if errors were omitted
+/// because of it, which is only told in the text format.
+pub fn render_diagnostics(
+    diagnostics: &[Diagnostic],
+    format: DiagnosticFormat,
+    color: bool,
+    limit: Option<usize>,
+) -> String {
+    match format {
+        DiagnosticFormat::Text => {
+            let rendered: Vec<String> = diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.render(color))
+                .collect();
+            let count = |severity| {
+                diagnostics
+                    .iter()
+                    .filter(|diagnostic| diagnostic.severity == severity)
+                    .count()
+            };
+            format!(
+                "{}{}",
+                rendered.join("\n"),
+                render_summary(
+                    count(Severity::Error),
+                    limit,
+                    count(Severity::Warning),
+                    color
+                )
+            )
+        }
+        DiagnosticFormat::Json => diagnostics
+            .iter()
+            .map(|diagnostic| format!("{}\n", to_json(diagnostic)))
+            .collect(),
+        DiagnosticFormat::Sarif => format!("{}\n", to_sarif(diagnostics)),
+    }
+}
+
+// A JSON value, written without whitespace
+enum Json {
+    Null,
+    Bool(bool),
+    Number(u64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(value) => {
+                write!(f, "\"")?;
+                for c in value.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", Json::String(key.to_string()), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn string(text: &str) -> Json {
+    Json::String(text.to_string())
+}
+
+fn strings(texts: &[String]) -> Json {
+    Json::Array(texts.iter().map(|text| string(text)).collect())
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    }
+}
+
+// The file and the start and end lines and columns of a span
+struct Region<'a> {
+    file: &'a str,
+    start: (u32, u32),
+    end: (u32, u32),
+}
+
+impl<'a> Region<'a> {
+    fn new(source: &'a PreprocessedSource, span: Span) -> Region<'a> {
+        let (file, start_line, start_column) = source.source_map.locate(span.start);
+        let (_, end_line, end_column) = source.source_map.locate_end(span);
+        Region {
+            file,
+            start: (start_line, start_column),
+            end: (end_line, end_column),
+        }
+    }
+}
+
+// The labels of a diagnostic with true for the primary one
+fn labels(diagnostic: &Diagnostic) -> impl Iterator<Item = (&Label, bool)> {
+    diagnostic
+        .primary
+        .iter()
+        .map(|label| (label, true))
+        .chain(diagnostic.secondary.iter().map(|label| (label, false)))
+}
+
+// The members of a span in the JSON format, whose columns are 1-based and whose end is exclusive
+fn span_members(region: &Region) -> Vec<(&'static str, Json)> {
+    let position = |(line, column): (u32, u32)| {
+        Json::Object(vec![
+            ("line", Json::Number(line as u64)),
+            ("column", Json::Number(column as u64)),
+        ])
+    };
+    vec![
+        ("file", string(region.file)),
+        ("start", position(region.start)),
+        ("end", position(region.end)),
+    ]
+}
+
+fn to_json(diagnostic: &Diagnostic) -> Json {
+    let (spans, fixits) = match diagnostic.source.as_deref() {
+        Some(source) => (
+            labels(diagnostic)
+                .map(|(label, is_primary)| {
+                    let mut members = span_members(&Region::new(source, label.span));
+                    members.push(("primary", Json::Bool(is_primary)));
+                    members.push((
+                        "label",
+                        if label.message.is_empty() {
+                            Json::Null
+                        } else {
+                            string(&label.message)
+                        },
+                    ));
+                    Json::Object(members)
+                })
+                .collect(),
+            diagnostic
+                .suggestions
+                .iter()
+                .map(|suggestion| {
+                    Json::Object(vec![
+                        (
+                            "span",
+                            Json::Object(span_members(&Region::new(source, suggestion.span))),
+                        ),
+                        ("replacement", string(&suggestion.replacement)),
+                        ("message", string(&suggestion.message)),
+                    ])
+                })
+                .collect(),
+        ),
+        None => (Vec::new(), Vec::new()),
+    };
+    Json::Object(vec![
+        ("severity", string(severity_name(diagnostic.severity))),
+        ("code", string(diagnostic.code)),
+        ("message", string(&diagnostic.message)),
+        ("spans", Json::Array(spans)),
+        ("notes", strings(&diagnostic.notes)),
+        ("help", strings(&diagnostic.help)),
+        ("fixits", Json::Array(fixits)),
+    ])
+}
+
+// A physicalLocation of SARIF
+fn physical_location(region: &Region) -> Json {
+    Json::Object(vec![
+        (
+            "artifactLocation",
+            Json::Object(vec![("uri", string(region.file))]),
+        ),
+        ("region", sarif_region(region)),
+    ])
+}
+
+fn sarif_region(region: &Region) -> Json {
+    Json::Object(vec![
+        ("startLine", Json::Number(region.start.0 as u64)),
+        ("startColumn", Json::Number(region.start.1 as u64)),
+        ("endLine", Json::Number(region.end.0 as u64)),
+        ("endColumn", Json::Number(region.end.1 as u64)),
+    ])
+}
+
+fn to_sarif(diagnostics: &[Diagnostic]) -> Json {
+    let mut rule_ids: Vec<&str> = diagnostics.iter().map(|d| d.code).collect();
+    rule_ids.sort_unstable();
+    rule_ids.dedup();
+    let rules = rule_ids
+        .iter()
+        .map(|id| Json::Object(vec![("id", string(id))]))
+        .collect();
+    let results = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let mut locations = Vec::new();
+            let mut related_locations = Vec::new();
+            let mut fixes = Vec::new();
+            if let Some(source) = diagnostic.source.as_deref() {
+                for (label, is_primary) in labels(diagnostic) {
+                    let location = physical_location(&Region::new(source, label.span));
+                    if is_primary {
+                        locations.push(Json::Object(vec![("physicalLocation", location)]));
+                    } else {
+                        related_locations.push(Json::Object(vec![
+                            ("id", Json::Number(related_locations.len() as u64)),
+                            ("physicalLocation", location),
+                            (
+                                "message",
+                                Json::Object(vec![("text", string(&label.message))]),
+                            ),
+                        ]));
+                    }
+                }
+                for suggestion in diagnostic.suggestions.iter() {
+                    let region = Region::new(source, suggestion.span);
+                    let replacement = Json::Object(vec![
+                        ("deletedRegion", sarif_region(&region)),
+                        (
+                            "insertedContent",
+                            Json::Object(vec![("text", string(&suggestion.replacement))]),
+                        ),
+                    ]);
+                    let change = Json::Object(vec![
+                        (
+                            "artifactLocation",
+                            Json::Object(vec![("uri", string(region.file))]),
+                        ),
+                        ("replacements", Json::Array(vec![replacement])),
+                    ]);
+                    fixes.push(Json::Object(vec![
+                        (
+                            "description",
+                            Json::Object(vec![("text", string(&suggestion.message))]),
+                        ),
+                        ("artifactChanges", Json::Array(vec![change])),
+                    ]));
+                }
+            }
+            // Notes and help have no counterpart in SARIF and are kept as properties
+            Json::Object(vec![
+                ("ruleId", string(diagnostic.code)),
+                ("level", string(severity_name(diagnostic.severity))),
+                (
+                    "message",
+                    Json::Object(vec![("text", string(&diagnostic.message))]),
+                ),
+                ("locations", Json::Array(locations)),
+                ("relatedLocations", Json::Array(related_locations)),
+                ("fixes", Json::Array(fixes)),
+                (
+                    "properties",
+                    Json::Object(vec![
+                        ("notes", strings(&diagnostic.notes)),
+                        ("help", strings(&diagnostic.help)),
+                    ]),
+                ),
+            ])
+        })
+        .collect();
+    let driver = Json::Object(vec![
+        ("name", string(env!("CARGO_PKG_NAME"))),
+        ("version", string(env!("CARGO_PKG_VERSION"))),
+        ("rules", Json::Array(rules)),
+    ]);
+    Json::Object(vec![
+        (
+            "$schema",
+            string("https://json.schemastore.org/sarif-2.1.0.json"),
+        ),
+        ("version", string("2.1.0")),
+        (
+            "runs",
+            Json::Array(vec![Json::Object(vec![
+                ("tool", Json::Object(vec![("driver", driver)])),
+                ("results", Json::Array(results)),
+            ])]),
+        ),
+    ])
+}
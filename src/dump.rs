@@ -0,0 +1,590 @@
+use crate::ast::*;
+use crate::cfg::{natural_loops, ControlFlowGraph, DominatorTree};
+use crate::initializer::InitValue;
+use crate::ir::{BlockId, Module, Terminator};
+use crate::preprocessor::PreprocessedSource;
+use crate::typed_ast::*;
+use std::fmt::Write;
+
+/// Dump the tokens after macro expansion, one per line with its kind, its text and its location
+pub fn dump_tokens(source: &PreprocessedSource) -> String {
+    let mut result = String::new();
+    for token in source.tokens.iter() {
+        let location = token.location;
+        let _ = writeln!(
+            result,
+            "{:?} {:?} {}:{}:{}",
+            token.kind,
+            token.text,
+            source.source_map.files[location.file],
+            location.line,
+            location.column
+        );
+    }
+    result
+}
+
+/// Dump the AST as a tree, one node per line indented by its depth
+pub fn dump_ast(program: &Program) -> String {
+    let mut dumper = AstDumper {
+        result: String::new(),
+    };
+    for program_unit in program.program_units.iter() {
+        dumper.program_unit(program_unit, 0);
+    }
+    dumper.result
+}
+
+struct AstDumper {
+    result: String,
+}
+
+fn storage_class_prefix(storage_class: Option<StorageClass>) -> &'static str {
+    match storage_class {
+        Some(StorageClass::Static) => "static ",
+        Some(StorageClass::Extern) => "extern ",
+        None => "",
+    }
+}
+
+impl AstDumper {
+    fn line(&mut self, depth: usize, text: String) {
+        let _ = writeln!(self.result, "{}{}", "  ".repeat(depth), text);
+    }
+
+    fn program_unit(&mut self, program_unit: &ProgramUnit, depth: usize) {
+        match program_unit {
+            ProgramUnit::FuncDef(return_type, name, parameters, variadic, body, storage_class) => {
+                let parameter_types = parameters.iter().map(|(t, _, _)| t.clone()).collect();
+                self.line(
+                    depth,
+                    format!(
+                        "FuncDef {}{}: {}",
+                        storage_class_prefix(*storage_class),
+                        name,
+                        function(return_type.clone(), parameter_types, *variadic)
+                    ),
+                );
+                for (data_type, name, _) in parameters.iter() {
+                    self.line(depth + 1, format!("Parameter {}: {}", name, data_type));
+                }
+                self.statement(body, depth + 1);
+            }
+            ProgramUnit::FuncDecl(return_type, name, parameter_types, variadic, storage_class) => {
+                self.line(
+                    depth,
+                    format!(
+                        "FuncDecl {}{}: {}",
+                        storage_class_prefix(*storage_class),
+                        name,
+                        function(return_type.clone(), parameter_types.clone(), *variadic)
+                    ),
+                );
+            }
+            ProgramUnit::StructDef(name, members) | ProgramUnit::UnionDef(name, members) => {
+                let kind = match program_unit {
+                    ProgramUnit::StructDef(_, _) => "StructDef",
+                    _ => "UnionDef",
+                };
+                self.line(depth, format!("{} {}", kind, name));
+                for (data_type, name) in members.iter() {
+                    self.line(depth + 1, format!("Member {}: {}", name, data_type));
+                }
+            }
+            ProgramUnit::Statement(statement) => self.statement(statement, depth),
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, depth: usize) {
+        match &statement.kind {
+            StatementKind::Expr(expr) => {
+                self.line(depth, "ExprStatement".to_string());
+                self.expr(expr, depth + 1);
+            }
+            StatementKind::Assign(name, expr) => {
+                self.line(depth, format!("Assign {}", name));
+                self.expr(expr, depth + 1);
+            }
+            StatementKind::AssignPointer(pointer_depth, name, expr) => {
+                self.line(
+                    depth,
+                    format!(
+                        "AssignPointer {}{}",
+                        "*".repeat(*pointer_depth as usize),
+                        name
+                    ),
+                );
+                self.expr(expr, depth + 1);
+            }
+            StatementKind::AssignLval(atom, expr) => {
+                self.line(depth, "AssignLval".to_string());
+                self.atom(atom, depth + 1);
+                self.expr(expr, depth + 1);
+            }
+            StatementKind::Return(expr) => {
+                self.line(depth, "Return".to_string());
+                self.expr(expr, depth + 1);
+            }
+            StatementKind::If(condition, if_statement, else_statement) => {
+                self.line(depth, "If".to_string());
+                self.expr(condition, depth + 1);
+                self.statement(if_statement, depth + 1);
+                if let Some(ref else_statement) = **else_statement {
+                    self.statement(else_statement, depth + 1);
+                }
+            }
+            StatementKind::Block(statements) => {
+                self.line(depth, "Block".to_string());
+                for statement in statements.iter() {
+                    self.statement(statement, depth + 1);
+                }
+            }
+            StatementKind::While(condition, body) => {
+                self.line(depth, "While".to_string());
+                self.expr(condition, depth + 1);
+                self.statement(body, depth + 1);
+            }
+            StatementKind::For(init, condition, step, body) => {
+                self.line(depth, "For".to_string());
+                // Omitted clauses are shown as Empty to tell which clause each node is
+                match **init {
+                    Some(ref init) => self.statement(init, depth + 1),
+                    None => self.line(depth + 1, "Empty".to_string()),
+                }
+                match **condition {
+                    Some(ref condition) => self.expr(condition, depth + 1),
+                    None => self.line(depth + 1, "Empty".to_string()),
+                }
+                match **step {
+                    Some(ref step) => self.statement(step, depth + 1),
+                    None => self.line(depth + 1, "Empty".to_string()),
+                }
+                self.statement(body, depth + 1);
+            }
+            StatementKind::Break => self.line(depth, "Break".to_string()),
+            StatementKind::Error => self.line(depth, "Error".to_string()),
+            StatementKind::VarDef(data_type, name, initializer, storage_class) => {
+                self.line(
+                    depth,
+                    format!(
+                        "VarDef {}{}: {}",
+                        storage_class_prefix(*storage_class),
+                        name,
+                        data_type
+                    ),
+                );
+                if let Some(initializer) = initializer {
+                    self.initializer(initializer, depth + 1);
+                }
+            }
+        }
+    }
+
+    fn initializer(&mut self, initializer: &Initializer, depth: usize) {
+        match initializer {
+            Initializer::Expr(expr) => self.expr(expr, depth),
+            Initializer::List(items) => {
+                self.line(depth, "InitializerList".to_string());
+                for (designators, initializer) in items.iter() {
+                    if designators.is_empty() {
+                        self.initializer(initializer, depth + 1);
+                        continue;
+                    }
+                    let designators: Vec<String> = designators
+                        .iter()
+                        .map(|designator| match designator {
+                            Designator::Index(index) => format!("[{}]", index),
+                            Designator::Member(member) => format!(".{}", member),
+                        })
+                        .collect();
+                    self.line(depth + 1, format!("Designated {}", designators.concat()));
+                    self.initializer(initializer, depth + 2);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, depth: usize) {
+        match &expr.kind {
+            ExprKind::ArithExpr(arith_expr) => self.arith_expr(arith_expr, depth),
+            ExprKind::Equal(left, right)
+            | ExprKind::NotEqual(left, right)
+            | ExprKind::Less(left, right)
+            | ExprKind::LessOrEqual(left, right) => {
+                let operator = match &expr.kind {
+                    ExprKind::Equal(_, _) => "Equal",
+                    ExprKind::NotEqual(_, _) => "NotEqual",
+                    ExprKind::Less(_, _) => "Less",
+                    _ => "LessOrEqual",
+                };
+                self.line(depth, operator.to_string());
+                self.arith_expr(left, depth + 1);
+                self.arith_expr(right, depth + 1);
+            }
+        }
+    }
+
+    fn arith_expr(&mut self, arith_expr: &ArithExpr, depth: usize) {
+        match arith_expr {
+            ArithExpr::Factor(factor) => self.factor(factor, depth),
+            ArithExpr::Add(left, right) | ArithExpr::Sub(left, right) => {
+                let operator = match arith_expr {
+                    ArithExpr::Add(_, _) => "Add",
+                    _ => "Sub",
+                };
+                self.line(depth, operator.to_string());
+                self.arith_expr(left, depth + 1);
+                self.factor(right, depth + 1);
+            }
+        }
+    }
+
+    fn factor(&mut self, factor: &Factor, depth: usize) {
+        match factor {
+            Factor::Unary(unary) => self.unary(unary, depth),
+            Factor::Mul(left, right) | Factor::Div(left, right) => {
+                let operator = match factor {
+                    Factor::Mul(_, _) => "Mul",
+                    _ => "Div",
+                };
+                self.line(depth, operator.to_string());
+                self.factor(left, depth + 1);
+                self.unary(right, depth + 1);
+            }
+        }
+    }
+
+    fn unary(&mut self, unary: &Unary, depth: usize) {
+        match unary {
+            Unary::Atom(atom) => self.atom(atom, depth),
+            Unary::Neg(atom) => {
+                self.line(depth, "Neg".to_string());
+                self.atom(atom, depth + 1);
+            }
+            Unary::PointerDeref(atom) => {
+                self.line(depth, "Deref".to_string());
+                self.atom(atom, depth + 1);
+            }
+            Unary::AddressOf(atom) => {
+                self.line(depth, "AddressOf".to_string());
+                self.atom(atom, depth + 1);
+            }
+        }
+    }
+
+    fn atom(&mut self, atom: &Atom, depth: usize) {
+        match &atom.kind {
+            AtomKind::Number(value) => self.line(depth, format!("Number {}", value)),
+            AtomKind::Expr(expr) => {
+                self.line(depth, "Paren".to_string());
+                self.expr(expr, depth + 1);
+            }
+            AtomKind::Variable(name) => self.line(depth, format!("Variable {}", name)),
+            AtomKind::FunctionCall(name, arguments) => {
+                self.line(depth, format!("Call {}", name));
+                for argument in arguments.iter() {
+                    self.expr(argument, depth + 1);
+                }
+            }
+            AtomKind::IndirectCall(callee, arguments) => {
+                self.line(depth, "IndirectCall".to_string());
+                self.atom(callee, depth + 1);
+                for argument in arguments.iter() {
+                    self.expr(argument, depth + 1);
+                }
+            }
+            AtomKind::StringLiteral(bytes) => {
+                self.line(depth, format!("StringLiteral \"{}\"", bytes.escape_ascii()))
+            }
+            AtomKind::Index(inner, index) => {
+                self.line(depth, "Index".to_string());
+                self.atom(inner, depth + 1);
+                self.expr(index, depth + 1);
+            }
+            AtomKind::Member(inner, member) => {
+                self.line(depth, format!("Member .{}", member));
+                self.atom(inner, depth + 1);
+            }
+            AtomKind::PointerMember(inner, member) => {
+                self.line(depth, format!("PointerMember ->{}", member));
+                self.atom(inner, depth + 1);
+            }
+            AtomKind::VaStart(ap, last_parameter) => {
+                self.line(depth, format!("VaStart {}", last_parameter));
+                self.expr(ap, depth + 1);
+            }
+            AtomKind::VaArg(ap, data_type) => {
+                self.line(depth, format!("VaArg {}", data_type));
+                self.expr(ap, depth + 1);
+            }
+            AtomKind::VaEnd(ap) => {
+                self.line(depth, "VaEnd".to_string());
+                self.expr(ap, depth + 1);
+            }
+            AtomKind::VaCopy(destination, source) => {
+                self.line(depth, "VaCopy".to_string());
+                self.expr(destination, depth + 1);
+                self.expr(source, depth + 1);
+            }
+        }
+    }
+}
+
+/// Dump the typed AST as a tree in the same way as the AST. Names are followed by their
+/// symbols as `#0`, and expressions are annotated with their types as `: int`.
+pub fn dump_typed_program(program: &TypedProgram) -> String {
+    let mut dumper = TypedAstDumper {
+        result: String::new(),
+        symbols: &program.symbols,
+    };
+    for unit in program.units.iter() {
+        match unit {
+            TypedUnit::Function(function) => dumper.function(function),
+            TypedUnit::Statement(statement) => dumper.statement(statement, 0),
+        }
+    }
+    dumper.result
+}
+
+struct TypedAstDumper<'a> {
+    result: String,
+    symbols: &'a [Symbol],
+}
+
+impl TypedAstDumper<'_> {
+    fn line(&mut self, depth: usize, text: String) {
+        let _ = writeln!(self.result, "{}{}", "  ".repeat(depth), text);
+    }
+
+    fn symbol(&self, symbol: SymbolId) -> String {
+        format!("{} #{}", self.symbols[symbol.0].name, symbol.0)
+    }
+
+    fn function(&mut self, function: &TypedFunction) {
+        self.line(
+            0,
+            format!(
+                "FuncDef {}{}: {}",
+                storage_class_prefix(function.storage_class),
+                self.symbol(function.symbol),
+                self.symbols[function.symbol.0].data_type
+            ),
+        );
+        for parameter in function.parameters.iter() {
+            let text = format!(
+                "Parameter {}: {}",
+                self.symbol(*parameter),
+                self.symbols[parameter.0].data_type
+            );
+            self.line(1, text);
+        }
+        self.statement(&function.body, 1);
+    }
+
+    fn statement(&mut self, statement: &TypedStatement, depth: usize) {
+        match &statement.kind {
+            TypedStatementKind::Expr(expr) => {
+                self.line(depth, "ExprStatement".to_string());
+                self.expr(expr, depth + 1);
+            }
+            TypedStatementKind::Assign(lvalue, value) => {
+                self.line(depth, "Assign".to_string());
+                self.expr(lvalue, depth + 1);
+                self.expr(value, depth + 1);
+            }
+            TypedStatementKind::Return(expr) => {
+                self.line(depth, "Return".to_string());
+                self.expr(expr, depth + 1);
+            }
+            TypedStatementKind::If(condition, if_statement, else_statement) => {
+                self.line(depth, "If".to_string());
+                self.expr(condition, depth + 1);
+                self.statement(if_statement, depth + 1);
+                if let Some(else_statement) = else_statement {
+                    self.statement(else_statement, depth + 1);
+                }
+            }
+            TypedStatementKind::Block(statements) => {
+                self.line(depth, "Block".to_string());
+                for statement in statements.iter() {
+                    self.statement(statement, depth + 1);
+                }
+            }
+            TypedStatementKind::While(condition, body) => {
+                self.line(depth, "While".to_string());
+                self.expr(condition, depth + 1);
+                self.statement(body, depth + 1);
+            }
+            TypedStatementKind::For(init, condition, step, body) => {
+                self.line(depth, "For".to_string());
+                match init {
+                    Some(init) => self.statement(init, depth + 1),
+                    None => self.line(depth + 1, "Empty".to_string()),
+                }
+                match condition {
+                    Some(condition) => self.expr(condition, depth + 1),
+                    None => self.line(depth + 1, "Empty".to_string()),
+                }
+                match step {
+                    Some(step) => self.statement(step, depth + 1),
+                    None => self.line(depth + 1, "Empty".to_string()),
+                }
+                self.statement(body, depth + 1);
+            }
+            TypedStatementKind::Break => self.line(depth, "Break".to_string()),
+            TypedStatementKind::VarDef(symbol, initializer) => {
+                let prefix = match self.symbols[symbol.0].kind {
+                    SymbolKind::StaticLocal | SymbolKind::Global { is_static: true } => "static ",
+                    SymbolKind::Extern => "extern ",
+                    _ => "",
+                };
+                let text = format!(
+                    "VarDef {}{}: {}",
+                    prefix,
+                    self.symbol(*symbol),
+                    self.symbols[symbol.0].data_type
+                );
+                self.line(depth, text);
+                // The initializer is shown as the flattened values stored at their offsets
+                for item in initializer.iter().flat_map(|i| i.items.iter()) {
+                    match &item.value {
+                        InitValue::Expr(expr) => {
+                            let text = format!("Initialize +{}: {}", item.offset, item.data_type);
+                            self.line(depth + 1, text);
+                            self.expr(expr, depth + 2);
+                        }
+                        InitValue::Byte(byte) => {
+                            self.line(depth + 1, format!("Initialize +{} = {}", item.offset, byte))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &TypedExpr, depth: usize) {
+        let text = match &expr.kind {
+            TypedExprKind::Number(value) => format!("Number {}", value),
+            TypedExprKind::StringLiteral(bytes) => {
+                format!("StringLiteral \"{}\"", bytes.escape_ascii())
+            }
+            TypedExprKind::Variable(symbol) => format!("Variable {}", self.symbol(*symbol)),
+            TypedExprKind::AddressOf(_) => "AddressOf".to_string(),
+            TypedExprKind::Binary(operator, _, _) => format!("{:?}", operator),
+            TypedExprKind::Neg(_) => "Neg".to_string(),
+            TypedExprKind::Deref(_) => "Deref".to_string(),
+            TypedExprKind::Call(_, _) => "Call".to_string(),
+            TypedExprKind::Index(_, _) => "Index".to_string(),
+            TypedExprKind::Member(_, member, _) => format!("Member .{}", member),
+            TypedExprKind::PointerMember(_, member, _) => format!("PointerMember ->{}", member),
+            TypedExprKind::VaStart(_) => "VaStart".to_string(),
+            TypedExprKind::VaArg(_) => "VaArg".to_string(),
+            TypedExprKind::VaEnd(_) => "VaEnd".to_string(),
+            TypedExprKind::VaCopy(_, _) => "VaCopy".to_string(),
+            TypedExprKind::Convert(conversion, _) => format!("ImplicitConversion {:?}", conversion),
+        };
+        self.line(depth, format!("{} : {}", text, expr.data_type));
+        match &expr.kind {
+            TypedExprKind::Binary(_, left, right)
+            | TypedExprKind::Index(left, right)
+            | TypedExprKind::VaCopy(left, right) => {
+                self.expr(left, depth + 1);
+                self.expr(right, depth + 1);
+            }
+            TypedExprKind::Neg(operand)
+            | TypedExprKind::Deref(operand)
+            | TypedExprKind::AddressOf(operand)
+            | TypedExprKind::Member(operand, _, _)
+            | TypedExprKind::PointerMember(operand, _, _)
+            | TypedExprKind::VaStart(operand)
+            | TypedExprKind::VaArg(operand)
+            | TypedExprKind::VaEnd(operand)
+            | TypedExprKind::Convert(_, operand) => self.expr(operand, depth + 1),
+            TypedExprKind::Call(callee, arguments) => {
+                self.expr(callee, depth + 1);
+                for argument in arguments.iter() {
+                    self.expr(argument, depth + 1);
+                }
+            }
+            TypedExprKind::Number(_)
+            | TypedExprKind::StringLiteral(_)
+            | TypedExprKind::Variable(_) => (),
+        }
+    }
+}
+
+/// Dump the control flow graph of each function as a Graphviz digraph. Blocks are boxes listing
+/// their instructions, branches are labelled with the outcome of the condition,
+/// back edges of natural loops are dashed and loop headers list the blocks of their loop.
+/// A block with a non-empty dominance frontier lists it after the loop.
+pub fn dump_cfg_dot(module: &Module) -> String {
+    let mut result = String::new();
+    for (index, function) in module.functions.iter().enumerate() {
+        if index > 0 {
+            result.push('\n');
+        }
+        let cfg = ControlFlowGraph::new(function);
+        let dominators = DominatorTree::new(&cfg);
+        let loops = natural_loops(&cfg, &dominators);
+        let frontiers = dominators.dominance_frontiers(&cfg);
+        let _ = writeln!(result, "digraph \"{}\" {{", function.name);
+        let _ = writeln!(result, "  node [shape=box, fontname=monospace];");
+        for (id, block) in function.blocks.iter().enumerate() {
+            let mut label = format!("bb{}:", id);
+            if let Some(natural_loop) = loops.iter().find(|l| l.header == BlockId(id)) {
+                let blocks: Vec<String> =
+                    natural_loop.blocks.iter().map(|b| b.to_string()).collect();
+                let _ = write!(label, " loop {}", blocks.join(", "));
+            }
+            let mut frontier = frontiers[id].clone();
+            if !frontier.is_empty() {
+                frontier.sort();
+                let blocks: Vec<String> = frontier.iter().map(|b| b.to_string()).collect();
+                let _ = write!(label, " frontier {}", blocks.join(", "));
+            }
+            label.push_str("\\l");
+            for instruction in block.instructions.iter() {
+                label.push_str(&escape_dot(&instruction.to_string()));
+                label.push_str("\\l");
+            }
+            label.push_str(&escape_dot(&block.terminator.to_string()));
+            label.push_str("\\l");
+            let _ = writeln!(result, "  bb{} [label=\"{}\"];", id, label);
+        }
+        for (id, block) in function.blocks.iter().enumerate() {
+            let edges = match block.terminator {
+                Terminator::Branch(_, then_block, else_block) => {
+                    vec![(then_block, Some("true")), (else_block, Some("false"))]
+                }
+                Terminator::Jump(target) => vec![(target, None)],
+                Terminator::Return(_) => vec![],
+            };
+            for (target, condition) in edges {
+                let mut attributes = Vec::new();
+                if let Some(condition) = condition {
+                    attributes.push(format!("label=\"{}\"", condition));
+                }
+                if dominators.dominates(target, BlockId(id)) {
+                    attributes.push("style=dashed".to_string());
+                }
+                if attributes.is_empty() {
+                    let _ = writeln!(result, "  bb{} -> {};", id, target);
+                } else {
+                    let _ = writeln!(
+                        result,
+                        "  bb{} -> {} [{}];",
+                        id,
+                        target,
+                        attributes.join(", ")
+                    );
+                }
+            }
+        }
+        let _ = writeln!(result, "}}");
+    }
+    result
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -1,526 +1,943 @@
+use crate::abi::*;
 use crate::assembly::*;
-use crate::ast::*;
-use crate::compile_error::CompilerError;
-use crate::infer_type::*;
-use std::collections::HashMap;
-
-pub struct VarInfo {
-    pub id: u32,
-    // TODO: remove the following annotation
-    #[allow(dead_code)]
-    pub data_type: DataType,
+use crate::ast::DataType;
+use crate::ir::{
+    self, Address, Argument, BinaryOp, CallResult, Callee, Function, Module, Parameter, ReturnType,
+    Terminator, Type, Value, VirtualRegister,
+};
+use crate::layout::Layouts;
+use crate::regalloc::{Allocation, Location};
+
+// Information about the variadic function being compiled, used by va_start
+struct VariadicInfo {
+    // The register save area is located at rbp - register_save_area_offset
+    register_save_area_offset: u32,
+    // The offset of the first variable argument in the register save area
+    gp_offset: u32,
+    // The first variable argument passed on the stack is located at rbp + overflow_arg_area_offset
+    overflow_arg_area_offset: u32,
 }
 
-pub struct MetaInfo {
-    scopes: Vec<HashMap<String, VarInfo>>,
-    label_count: u64,
-    label_stack_for_break: Vec<String>,
+// Where an argument is passed
+enum ArgumentLocation {
+    // The index of the first register and the number of registers
+    Registers(usize, usize),
+    // The offset in the arguments passed on the stack
+    Stack(u32),
 }
 
-impl MetaInfo {
-    pub fn new() -> MetaInfo {
-        MetaInfo {
-            scopes: vec![HashMap::new()],
-            label_count: 0,
-            label_stack_for_break: Vec::new(),
+// A source of a move to a register, which reads a register
+#[derive(Clone, Copy)]
+enum MoveSource {
+    Register(Register),
+    // The 8 bytes at the address in the register plus the displacement
+    Memory(Register, i32),
+}
+
+impl MoveSource {
+    fn register(&self) -> Register {
+        match self {
+            MoveSource::Register(register) | MoveSource::Memory(register, _) => *register,
         }
     }
+}
+
+// The function being compiled. Its stack frame holds the callee-saved registers it uses,
+// the hidden pointer for the return value, the register save area, stack slots and spill slots,
+// all of which are located below rbp.
+struct FunctionGenerator<'a> {
+    function: &'a Function,
+    layouts: &'a Layouts,
+    allocation: Allocation,
+    // The offsets from rbp of the memory below it
+    callee_saved_offsets: Vec<(Register, u32)>,
+    return_pointer_offset: Option<u32>,
+    slot_offsets: Vec<u32>,
+    spill_offsets: Vec<u32>,
+    frame_size: u32,
+    parameter_locations: Vec<ArgumentLocation>,
+    variadic_info: Option<VariadicInfo>,
+    // The label of each block is .L followed by the first block number plus its id
+    first_block_number: u64,
+    label_count: &'a mut u64,
+    assembly: Assembly,
+}
 
-    pub fn push_scope(&mut self) {
-        self.scopes.push(HashMap::new());
+const ARGUMENT_REGISTERS: &[Register] = &[
+    Register::RDI,
+    Register::RSI,
+    Register::RDX,
+    Register::RCX,
+    Register::R8,
+    Register::R9,
+];
+
+const VECTOR_ARGUMENT_REGISTERS: &[Register] = &[
+    Register::XMM0,
+    Register::XMM1,
+    Register::XMM2,
+    Register::XMM3,
+    Register::XMM4,
+    Register::XMM5,
+    Register::XMM6,
+    Register::XMM7,
+];
+
+// The register save area of System V consists of 6 general purpose registers
+// followed by 8 vector registers of 16 bytes each
+const REGISTER_SAVE_AREA_SIZE: u32 = 6 * 8 + 8 * 16;
+const FP_OFFSET: u32 = 6 * 8;
+
+// Arguments passed on the stack begin above the return address and the saved rbp
+const STACK_ARGUMENTS_OFFSET: u32 = 16;
+
+/// Compile the IR to assembly. Virtual registers are allocated to machine registers,
+/// or spilled to the stack frame if there are not enough of them.
+pub fn get_assembly(module: &Module, layouts: &Layouts) -> Assembly {
+    let mut label_count = 0;
+    let mut assembly = vec![section(".intel_syntax noprefix")];
+    for function in module.functions.iter() {
+        let mut generator = FunctionGenerator::new(function, layouts, &mut label_count);
+        generator.function();
+        assembly.append(&mut generator.assembly);
     }
+    assembly.append(&mut get_assembly_data(module));
+    // Mark the stack as non-executable as gcc does, which the linker otherwise warns about
+    assembly.push(section(".section .note.GNU-stack,\"\",@progbits"));
+    assembly
+}
 
-    pub fn pop_scope(&mut self) {
-        self.scopes.pop();
+// Emit global variables and string literals
+fn get_assembly_data(module: &Module) -> Assembly {
+    let mut assembly: Assembly = Vec::new();
+    // Emitted in the reverse order so that the variables are laid out
+    // in the same way as local variables on the stack
+    for data in module.globals.iter().rev() {
+        if data.is_global {
+            assembly.push(global(data.label.clone()));
+        }
+        match &data.data {
+            Some(image) => {
+                assembly.append(&mut vec![section(".data"), align(data.align)]);
+                assembly.push(label(data.label.clone()));
+                let mut offset = 0;
+                let mut relocations = image.relocations.clone();
+                relocations.sort_by_key(|(o, _, _)| *o);
+                for (relocation_offset, base, addend) in relocations.into_iter() {
+                    if relocation_offset > offset {
+                        assembly.push(bytes(
+                            image.bytes[offset as usize..relocation_offset as usize].to_vec(),
+                        ));
+                    }
+                    let base_label = match base {
+                        Address::Symbol(label) => label,
+                        Address::StringLiteral(index) => format!(".LC{}", index),
+                    };
+                    assembly.push(quad(format!("{} + {}", base_label, addend)));
+                    offset = relocation_offset + 8;
+                }
+                if (offset as usize) < image.bytes.len() {
+                    assembly.push(bytes(image.bytes[offset as usize..].to_vec()));
+                }
+            }
+            None => {
+                assembly.append(&mut vec![section(".bss"), align(data.align)]);
+                assembly.push(label(data.label.clone()));
+                assembly.push(zero(data.size.max(1)));
+            }
+        }
     }
+    for (index, literal) in module.string_literals.iter().enumerate() {
+        assembly.append(&mut vec![
+            section(".section .rodata"),
+            label(format!(".LC{}", index)),
+        ]);
+        let mut literal = literal.clone();
+        literal.push(0);
+        assembly.push(bytes(literal));
+    }
+    assembly
+}
 
-    pub fn register_variables(&mut self, variables: &[(DataType, String)]) {
-        let mut id = self.scopes.last().unwrap().len() as u32;
-        let current_scope = self.scopes.last_mut().unwrap();
-        for (data_type, var_name) in variables.iter() {
-            current_scope.insert(
-                var_name.clone(),
-                VarInfo {
-                    id,
-                    data_type: data_type.clone(),
-                },
-            );
-            id += 1;
+// Reserve memory in the stack frame and return its offset below rbp
+fn allocate_frame(frame_size: &mut u32, size: u32, align: u32) -> u32 {
+    *frame_size = (*frame_size + size).next_multiple_of(align.max(1));
+    *frame_size
+}
+
+// Decide where each argument is passed, where `None` is a scalar and `Some` is an aggregate.
+// An aggregate is passed on the stack if it does not fit in the remaining registers.
+// Returns the locations, the number of registers used and the size of the stack arguments.
+fn argument_locations<'t>(
+    aggregate_types: impl Iterator<Item = Option<&'t DataType>>,
+    first_register: usize,
+    layouts: &Layouts,
+) -> (Vec<ArgumentLocation>, usize, u32) {
+    let mut gp = first_register;
+    let mut stack_size = 0;
+    let mut locations = Vec::new();
+    for aggregate_type in aggregate_types {
+        let (classes, size) = match aggregate_type {
+            Some(data_type) => (classify(data_type, layouts), layouts.size_of(data_type)),
+            None => (vec![ArgClass::Integer], 8),
+        };
+        if !is_memory_class(&classes) && gp + classes.len() <= ARGUMENT_REGISTERS.len() {
+            locations.push(ArgumentLocation::Registers(gp, classes.len()));
+            gp += classes.len();
+        } else {
+            locations.push(ArgumentLocation::Stack(stack_size));
+            stack_size += size.div_ceil(8) * 8;
         }
     }
+    (locations, gp, stack_size)
+}
 
-    pub fn register_variable(&mut self, variable: &str, data_type: &DataType) {
-        let current_scope = self.scopes.last_mut().unwrap();
-        let var_info = VarInfo {
-            id: current_scope.len() as u32,
-            data_type: data_type.clone(),
+// True if an aggregate of the type is returned in the memory pointed by the hidden first argument
+fn is_returned_in_memory(return_type: &DataType, layouts: &Layouts) -> bool {
+    is_memory_class(&classify(return_type, layouts))
+}
+
+impl<'a> FunctionGenerator<'a> {
+    fn new(
+        function: &'a Function,
+        layouts: &'a Layouts,
+        label_count: &'a mut u64,
+    ) -> FunctionGenerator<'a> {
+        let allocation = Allocation::new(function);
+        let mut frame_size = 0;
+        let callee_saved_offsets = allocation
+            .callee_saved_registers
+            .iter()
+            .map(|register| (*register, allocate_frame(&mut frame_size, 8, 8)))
+            .collect();
+        let return_pointer_offset = match &function.return_type {
+            ReturnType::Aggregate(data_type) if is_returned_in_memory(data_type, layouts) => {
+                Some(allocate_frame(&mut frame_size, 8, 8))
+            }
+            _ => None,
+        };
+        let parameter_types = function.parameters.iter().map(|parameter| match parameter {
+            Parameter::Scalar(_) => None,
+            Parameter::Aggregate(_, data_type) => Some(data_type),
+        });
+        let first_register = if return_pointer_offset.is_some() {
+            1
+        } else {
+            0
+        };
+        let (parameter_locations, gp, stack_size) =
+            argument_locations(parameter_types, first_register, layouts);
+        // Reserve the register save area referred by va_start
+        let variadic_info = if function.variadic {
+            Some(VariadicInfo {
+                register_save_area_offset: allocate_frame(
+                    &mut frame_size,
+                    REGISTER_SAVE_AREA_SIZE,
+                    16,
+                ),
+                gp_offset: gp as u32 * 8,
+                overflow_arg_area_offset: STACK_ARGUMENTS_OFFSET + stack_size,
+            })
+        } else {
+            None
         };
-        current_scope.insert(variable.to_string(), var_info);
+        // A slot is a multiple of 8 bytes, which aggregates returned in registers are stored as
+        let slot_offsets = function
+            .slots
+            .iter()
+            .map(|slot| {
+                allocate_frame(
+                    &mut frame_size,
+                    slot.size.next_multiple_of(8).max(8),
+                    slot.align,
+                )
+            })
+            .collect();
+        let spill_offsets = (0..allocation.spill_count)
+            .map(|_| allocate_frame(&mut frame_size, 8, 8))
+            .collect();
+        // rsp stays aligned to 16 bytes as required by the ABI at calls
+        let frame_size = frame_size.next_multiple_of(16);
+        let first_block_number = *label_count + 1;
+        *label_count += function.blocks.len() as u64;
+        FunctionGenerator {
+            function,
+            layouts,
+            allocation,
+            callee_saved_offsets,
+            return_pointer_offset,
+            slot_offsets,
+            spill_offsets,
+            frame_size,
+            parameter_locations,
+            variadic_info,
+            first_block_number,
+            label_count,
+            assembly: Vec::new(),
+        }
     }
 
-    pub fn get_variable(&self, lval: &String) -> Option<&VarInfo> {
-        self.scopes.last().unwrap().get(lval)
+    fn push(&mut self, instruction: Instruction) {
+        self.assembly.push(instruction);
     }
 
-    pub fn get_number_of_variables(&self) -> u32 {
-        self.scopes.last().unwrap().len() as u32
+    fn block_label(&self, block: ir::BlockId) -> String {
+        format!(".L{}", self.first_block_number + block.0 as u64)
     }
 
-    pub fn get_new_label(&mut self) -> String {
-        self.label_count += 1;
+    fn new_label(&mut self) -> String {
+        *self.label_count += 1;
         format!(".L{}", self.label_count)
     }
 
-    pub fn push_label_for_break(&mut self, label: String) {
-        self.label_stack_for_break.push(label);
+    fn frame_memory(offset: u32) -> Operand {
+        memory(Register::RBP, -(offset as i32))
     }
 
-    pub fn pop_label_for_break(&mut self) -> String {
-        self.label_stack_for_break.pop().unwrap()
+    fn location_operand(&self, location: Location) -> Operand {
+        match location {
+            Location::Register(register) => Operand::Register(register),
+            Location::Spill(index) => Self::frame_memory(self.spill_offsets[index]),
+        }
     }
 
-    pub fn get_label_for_break(&mut self) -> String {
-        self.label_stack_for_break.last().unwrap().clone()
+    fn register_operand(&self, register: VirtualRegister) -> Operand {
+        let location = self.allocation.locations[register.0]
+            .expect("a register which is assigned or read has a location");
+        self.location_operand(location)
     }
-}
 
-const ARGUMENT_REGISTERS: &[Operand] = &[
-    Operand::Register(Register::RDI),
-    Operand::Register(Register::RSI),
-    Operand::Register(Register::RDX),
-    Operand::Register(Register::RCX),
-    Operand::Register(Register::R8),
-    Operand::Register(Register::R9),
-];
+    fn value_operand(&self, value: Value) -> Operand {
+        match value {
+            Value::Register(register) => self.register_operand(register),
+            Value::Constant(n) => immediate(n),
+        }
+    }
+
+    // Move an operand to a register or memory, through rax if both are in memory
+    // or the immediate does not fit in 32 bits
+    fn move_operand(&mut self, destination: Operand, source: Operand) {
+        if destination == source {
+            return;
+        }
+        let needs_register = match source {
+            Operand::Memory(_, _) => true,
+            Operand::Immediate(n) => i32::try_from(n).is_err(),
+            _ => false,
+        };
+        if needs_register && !matches!(destination, Operand::Register(_)) {
+            self.push(mov(rax(), source));
+            self.push(mov(destination, rax()));
+        } else {
+            self.push(mov(destination, source));
+        }
+    }
 
-impl Default for MetaInfo {
-    fn default() -> Self {
-        Self::new()
+    fn move_value(&mut self, destination: Operand, value: Value) {
+        let source = self.value_operand(value);
+        self.move_operand(destination, source);
     }
-}
 
-pub fn print_assembly(program: &Program) -> Result<(), CompilerError> {
-    let mut meta_info = MetaInfo::default();
-    print_assembly_internal(program, &mut meta_info)
-}
+    // The operand of a value read by an arithmetic instruction,
+    // which takes an immediate of 32 bits at most
+    fn source_operand(&mut self, value: Value, scratch: Register) -> Operand {
+        match self.value_operand(value) {
+            Operand::Immediate(n) if i32::try_from(n).is_err() => {
+                self.push(mov(register(scratch), immediate(n)));
+                register(scratch)
+            }
+            operand => operand,
+        }
+    }
 
-fn print_assembly_internal(
-    program: &Program,
-    meta_info: &mut MetaInfo,
-) -> Result<(), CompilerError> {
-    let main_label = label("main".to_string());
-    let header_code = vec![push(rbp()), mov(rbp(), rsp())];
-    let footer_code = vec![mov(rsp(), rbp()), pop(rbp()), ret()];
-    let mut main_code: Assembly = Vec::new();
-    let mut func_def_code: Assembly = Vec::new();
-    for program_unit in program.program_units.iter() {
-        match program_unit {
-            ProgramUnit::FuncDef(_, func_name, parameters, statement) => {
-                // Enter a new scope and register parameters of functions
-                meta_info.push_scope();
-                meta_info.register_variables(parameters);
-
-                // Function label
-                func_def_code.push(label(func_name.clone()));
-
-                // Prelude code
-                func_def_code.append(&mut header_code.clone());
-                func_def_code.push(sub(rsp(), immediate(8 * parameters.len() as i32)));
-
-                // Copy arguments to local variables
-                for (index, (register, _)) in
-                    ARGUMENT_REGISTERS.iter().zip(parameters.iter()).enumerate()
-                {
-                    func_def_code.append(&mut vec![
-                        comment("copy arguments to local variables"),
-                        mov(rax(), rbp()),
-                        sub(rax(), immediate((index + 1) as i32 * 8)),
-                        mov(m_rax(), register.clone()),
-                        comment("copy arguments to local variables end"),
-                    ]);
-                }
+    // The register holding a value, which is moved to the scratch register unless it is in one
+    fn value_register(&mut self, value: Value, scratch: Register) -> Register {
+        match self.value_operand(value) {
+            Operand::Register(register) => register,
+            operand => {
+                self.push(mov(register(scratch), operand));
+                scratch
+            }
+        }
+    }
 
-                // Function body
-                func_def_code.append(&mut get_assembly_statement(statement, meta_info)?);
+    // The register to compute the value of the destination in, which is the register of
+    // the destination unless it holds one of the values read after it is written
+    fn target(&self, destination: VirtualRegister, read_later: &[Value]) -> Register {
+        match self.allocation.locations[destination.0] {
+            Some(Location::Register(register))
+                if read_later
+                    .iter()
+                    .all(|value| self.value_operand(*value) != Operand::Register(register)) =>
+            {
+                register
+            }
+            _ => Register::RAX,
+        }
+    }
 
-                // Postlude code
-                func_def_code.append(&mut footer_code.clone());
+    fn assign(&mut self, destination: VirtualRegister, source: Register) {
+        let destination = self.register_operand(destination);
+        self.move_operand(destination, register(source));
+    }
+
+    // Copy `size` bytes between the addresses in registers plus displacements using rdx
+    fn copy_memory(&mut self, destination: (Register, i32), source: (Register, i32), size: u32) {
+        let words = size / 8;
+        for index in 0..words {
+            let offset = index as i32 * 8;
+            self.push(mov(rdx(), memory(source.0, source.1 + offset)));
+            self.push(mov(memory(destination.0, destination.1 + offset), rdx()));
+        }
+        for offset in words * 8..size {
+            let offset = offset as i32;
+            self.push(mov(
+                byte_register(Register::RDX),
+                byte_memory(source.0, source.1 + offset),
+            ));
+            self.push(mov(
+                byte_memory(destination.0, destination.1 + offset),
+                byte_register(Register::RDX),
+            ));
+        }
+    }
 
-                // Leave the scope
-                meta_info.pop_scope();
+    fn function(&mut self) {
+        let function = self.function;
+        // The function label, which is visible to other objects unless it is static
+        if function.is_global {
+            self.push(global(function.name.clone()));
+        }
+        self.push(label(function.name.clone()));
+        self.push(push(rbp()));
+        self.push(mov(rbp(), rsp()));
+        if self.frame_size > 0 {
+            self.push(sub(rsp(), immediate(self.frame_size as i64)));
+        }
+        for (saved, offset) in self.callee_saved_offsets.clone() {
+            self.push(mov(Self::frame_memory(offset), register(saved)));
+        }
+        // Save all argument registers to the register save area
+        if let Some(variadic_info) = &self.variadic_info {
+            let base = -(variadic_info.register_save_area_offset as i32);
+            for (index, saved) in ARGUMENT_REGISTERS.iter().enumerate() {
+                let offset = base + index as i32 * 8;
+                self.push(mov(memory(Register::RBP, offset), register(*saved)));
             }
-            ProgramUnit::Statement(statement) => {
-                main_code.append(&mut get_assembly_statement(statement, meta_info)?);
-                main_code.push(pop(rax()));
+            for (index, saved) in VECTOR_ARGUMENT_REGISTERS.iter().enumerate() {
+                let offset = base + (FP_OFFSET + index as u32 * 16) as i32;
+                self.push(movsd(memory(Register::RBP, offset), register(*saved)));
+            }
+        }
+        self.parameters();
+        for (index, block) in function.blocks.iter().enumerate() {
+            self.push(label(self.block_label(ir::BlockId(index))));
+            for instruction in block.instructions.iter() {
+                self.instruction(instruction);
             }
+            let next = ir::BlockId(index + 1);
+            self.terminator(&block.terminator, next);
         }
     }
-    let number_of_variables = meta_info.get_number_of_variables();
-    let sub_rsp_code = sub(rsp(), immediate(8 * number_of_variables as i32));
-
-    println!(".intel_syntax noprefix");
-    println!(".global main\n");
-    print_single_instruction(&main_label);
-    print_assembly_code(&header_code);
-    print_single_instruction(&sub_rsp_code);
-    print_assembly_code(&main_code);
-    print_assembly_code(&footer_code);
-    print_assembly_code(&func_def_code);
-    Ok(())
-}
 
-pub fn get_assembly_statement(
-    statement: &Statement,
-    meta_info: &mut MetaInfo,
-) -> Result<Assembly, CompilerError> {
-    match statement {
-        Statement::Expr(expr) => get_assembly_expr(expr, meta_info),
-        Statement::Assign(left, expr) => {
-            let mut assembly: Assembly = Vec::new();
-            assembly.push(comment("assign"));
-            assembly.append(&mut get_assembly_lval(left, meta_info)?);
-            assembly.append(&mut get_assembly_expr(expr, meta_info)?);
-            assembly.append(&mut vec![
-                pop(rdi()),
-                pop(rax()),
-                mov(m_rax(), rdi()),
-                push(rdi()),
-            ]);
-            assembly.push(comment("assign end"));
-            Ok(assembly)
-        }
-        Statement::AssignPointer(depth, left, expr) => {
-            let mut assembly: Assembly = Vec::new();
-            assembly.push(comment("assign pointer"));
-            assembly.append(&mut get_assembly_lval(left, meta_info)?);
-            assembly.append(&mut get_assembly_expr(expr, meta_info)?);
-            assembly.append(&mut vec![pop(rdi()), pop(rax())]);
-            for _ in 0..*depth {
-                assembly.push(mov(rax(), m_rax()));
-            }
-            assembly.append(&mut vec![mov(m_rax(), rdi()), push(rdi())]);
-            assembly.push(comment("assign pointer end"));
-            Ok(assembly)
-        }
-        Statement::Return(expr) => {
-            let mut assembly: Assembly = get_assembly_expr(expr, meta_info)?;
-            assembly.append(&mut vec![pop(rax()), mov(rsp(), rbp()), pop(rbp()), ret()]);
-            Ok(assembly)
-        }
-        Statement::Block(statements) => {
-            let mut assembly: Assembly = Vec::new();
-            for (index, statement) in statements.iter().enumerate() {
-                assembly.append(&mut get_assembly_statement(statement, meta_info)?);
-                if index != statements.len() - 1 {
-                    assembly.push(pop(rax()));
+    // Move the arguments to the locations of the parameters
+    fn parameters(&mut self) {
+        if let Some(offset) = self.return_pointer_offset {
+            self.push(mov(
+                Self::frame_memory(offset),
+                register(ARGUMENT_REGISTERS[0]),
+            ));
+        }
+        let function = self.function;
+        let mut moves = Vec::new();
+        let mut stack_parameters = Vec::new();
+        let parameter_locations = std::mem::take(&mut self.parameter_locations);
+        for (parameter, location) in function.parameters.iter().zip(&parameter_locations) {
+            match (parameter, location) {
+                (Parameter::Scalar(parameter), ArgumentLocation::Registers(first, _)) => {
+                    let argument = ARGUMENT_REGISTERS[*first];
+                    match self.allocation.locations[parameter.0] {
+                        Some(Location::Register(destination)) => {
+                            moves.push((destination, MoveSource::Register(argument)))
+                        }
+                        Some(location @ Location::Spill(_)) => {
+                            let destination = self.location_operand(location);
+                            self.push(mov(destination, register(argument)));
+                        }
+                        // The parameter is never read
+                        None => (),
+                    }
+                }
+                (Parameter::Aggregate(slot, _), ArgumentLocation::Registers(first, count)) => {
+                    let base = -(self.slot_offsets[slot.0] as i32);
+                    for (index, argument) in
+                        ARGUMENT_REGISTERS[*first..first + count].iter().enumerate()
+                    {
+                        let offset = base + index as i32 * 8;
+                        self.push(mov(memory(Register::RBP, offset), register(*argument)));
+                    }
+                }
+                (_, ArgumentLocation::Stack(offset)) => {
+                    stack_parameters.push((parameter, STACK_ARGUMENTS_OFFSET + offset))
                 }
             }
-            Ok(assembly)
-        }
-        Statement::If(expr, if_statement, else_statement) => {
-            let mut assembly: Assembly = Vec::new();
-            assembly.push(comment("if"));
-            assembly.append(&mut get_assembly_expr(expr, meta_info)?);
-            assembly.append(&mut vec![pop(rax()), cmp(rax(), immediate(0))]);
-            match **else_statement {
-                Some(ref else_statement) => {
-                    let else_label = meta_info.get_new_label();
-                    let end_label = meta_info.get_new_label();
-                    assembly.push(je(else_label.clone()));
-                    assembly.append(&mut get_assembly_statement(if_statement, meta_info)?);
-                    assembly.push(jmp(end_label.clone()));
-                    assembly.push(comment("else"));
-                    assembly.push(label(else_label));
-                    assembly.append(&mut get_assembly_statement(else_statement, meta_info)?);
-                    assembly.push(label(end_label));
+        }
+        self.parallel_move(moves);
+        // Parameters passed on the stack are copied after the argument registers are read
+        for (parameter, offset) in stack_parameters {
+            let source = memory(Register::RBP, offset as i32);
+            match parameter {
+                Parameter::Scalar(parameter) => {
+                    if self.allocation.locations[parameter.0].is_some() {
+                        let destination = self.register_operand(*parameter);
+                        self.move_operand(destination, source);
+                    }
                 }
-                None => {
-                    let end_label = meta_info.get_new_label();
-                    assembly.push(je(end_label.clone()));
-                    assembly.append(&mut get_assembly_statement(if_statement, meta_info)?);
-                    assembly.push(pop(rax()));
-                    assembly.push(label(end_label));
+                Parameter::Aggregate(slot, data_type) => {
+                    let destination = (Register::RBP, -(self.slot_offsets[slot.0] as i32));
+                    let size = self.layouts.size_of(data_type);
+                    self.copy_memory(destination, (Register::RBP, offset as i32), size);
                 }
             }
-            assembly.push(push(immediate(0)));
-            assembly.push(comment("if end"));
-            Ok(assembly)
-        }
-        Statement::While(expr, statement) => {
-            let mut assembly: Assembly = Vec::new();
-            let start_label = meta_info.get_new_label();
-            let end_label = meta_info.get_new_label();
-
-            meta_info.push_label_for_break(end_label.clone());
-
-            assembly.push(label(start_label.clone()));
-            assembly.push(comment("while"));
-            assembly.append(&mut get_assembly_expr(expr, meta_info)?);
-            assembly.append(&mut vec![
-                pop(rax()),
-                cmp(rax(), immediate(0)),
-                je(end_label.clone()),
-            ]);
-            assembly.append(&mut get_assembly_statement(statement, meta_info)?);
-            assembly.push(comment("while content pop"));
-            assembly.push(pop(rax()));
-            assembly.push(jmp(start_label));
-            assembly.push(label(end_label));
-            assembly.push(push(immediate(0)));
-            assembly.push(comment("while end"));
-
-            meta_info.pop_label_for_break();
-
-            Ok(assembly)
-        }
-        Statement::For(init, cond, update, statement) => {
-            let mut assembly: Assembly = Vec::new();
-            if let Some(ref init) = **init {
-                assembly.append(&mut get_assembly_statement(init, meta_info)?);
-                assembly.push(pop(rax()));
-            }
-            let start_label = meta_info.get_new_label();
-            let end_label = meta_info.get_new_label();
-
-            meta_info.push_label_for_break(end_label.clone());
-
-            assembly.push(label(start_label.clone()));
-            if let Some(ref cond) = **cond {
-                assembly.append(&mut get_assembly_expr(cond, meta_info)?);
-                assembly.append(&mut vec![
-                    pop(rax()),
-                    cmp(rax(), immediate(0)),
-                    je(end_label.clone()),
-                ]);
-            }
-            assembly.append(&mut get_assembly_statement(statement, meta_info)?);
-            assembly.push(pop(rax()));
-            if let Some(ref update) = **update {
-                assembly.append(&mut get_assembly_statement(update, meta_info)?);
-                assembly.push(pop(rax()));
-            }
-            assembly.push(jmp(start_label));
-            assembly.push(label(end_label));
-
-            meta_info.pop_label_for_break();
-
-            Ok(assembly)
-        }
-        Statement::Break => {
-            let mut assembly: Assembly = Vec::new();
-            let label = meta_info.get_label_for_break();
-            assembly.push(comment("break"));
-            assembly.push(jmp(label.clone()));
-            Ok(assembly)
-        }
-
-        Statement::VarDef(data_type, var_name) => {
-            meta_info.register_variable(var_name, data_type);
-            Ok(vec![push(immediate(0))])
         }
     }
-}
 
-fn get_assembly_lval(lval: &String, meta_info: &mut MetaInfo) -> Result<Assembly, CompilerError> {
-    let id = match meta_info.get_variable(lval) {
-        Some(var_info) => var_info.id,
-        None => return Err(CompilerError::UndefinedVariable(lval.clone())),
-    };
-    Ok(vec![
-        mov(rax(), rbp()),
-        sub(rax(), immediate((id + 1) as i32 * 8)),
-        push(rax()),
-    ])
-}
+    // Move the sources to the registers as if all sources were read before any register
+    // is written. A move is done once no other move reads its destination,
+    // and a cycle of moves is broken by saving a register read by another move to r11.
+    fn parallel_move(&mut self, mut moves: Vec<(Register, MoveSource)>) {
+        while !moves.is_empty() {
+            let ready = (0..moves.len()).find(|index| {
+                moves.iter().enumerate().all(|(other, (_, source))| {
+                    other == *index || source.register() != moves[*index].0
+                })
+            });
+            match ready {
+                Some(index) => {
+                    let (destination, source) = moves.remove(index);
+                    match source {
+                        MoveSource::Register(source) => {
+                            self.move_operand(register(destination), register(source))
+                        }
+                        MoveSource::Memory(base, displacement) => {
+                            self.push(mov(register(destination), memory(base, displacement)))
+                        }
+                    }
+                }
+                None => {
+                    let saved = moves[0].0;
+                    self.push(mov(r11(), register(saved)));
+                    for (_, source) in moves.iter_mut() {
+                        *source = match *source {
+                            MoveSource::Register(register) if register == saved => {
+                                MoveSource::Register(Register::R11)
+                            }
+                            MoveSource::Memory(register, displacement) if register == saved => {
+                                MoveSource::Memory(Register::R11, displacement)
+                            }
+                            source => source,
+                        };
+                    }
+                }
+            }
+        }
+    }
 
-fn get_assembly_expr(expr: &Expr, meta_info: &mut MetaInfo) -> Result<Assembly, CompilerError> {
-    match expr {
-        Expr::ArithExpr(arith_expr) => get_assembly_arith_expr(arith_expr, meta_info),
-        Expr::Equal(left, right) => {
-            get_compare_instruction(&|o: Operand| sete(o), left, right, meta_info)
+    fn instruction(&mut self, instruction: &ir::Instruction) {
+        match instruction {
+            ir::Instruction::Copy(destination, value) => {
+                let destination = self.register_operand(*destination);
+                self.move_value(destination, *value);
+            }
+            ir::Instruction::Binary(op, destination, left, right) => {
+                self.binary(*op, *destination, *left, *right)
+            }
+            ir::Instruction::Neg(destination, value) => {
+                let target = self.target(*destination, &[]);
+                self.move_value(register(target), *value);
+                self.push(neg(register(target)));
+                self.assign(*destination, target);
+            }
+            // A char is kept sign-extended to 64 bits, so only a conversion to a char needs code
+            ir::Instruction::Cast(destination, value) => {
+                match (self.function.register_type(*destination), value) {
+                    (Type::I8, Value::Constant(n)) => {
+                        let destination = self.register_operand(*destination);
+                        self.move_value(destination, Value::Constant(*n as i8 as i64));
+                    }
+                    (Type::I8, Value::Register(_)) => {
+                        let source = self.value_register(*value, Register::RAX);
+                        let target = self.target(*destination, &[]);
+                        self.push(movsx(register(target), byte_register(source)));
+                        self.assign(*destination, target);
+                    }
+                    _ => {
+                        let destination = self.register_operand(*destination);
+                        self.move_value(destination, *value);
+                    }
+                }
+            }
+            ir::Instruction::Load(destination, address) => {
+                let address = self.value_register(*address, Register::R11);
+                let target = self.target(*destination, &[]);
+                if self.function.register_type(*destination) == Type::I8 {
+                    self.push(movsx(register(target), byte_memory(address, 0)));
+                } else {
+                    self.push(mov(register(target), memory(address, 0)));
+                }
+                self.assign(*destination, target);
+            }
+            ir::Instruction::Store(store_type, address, value) => {
+                let address = self.value_register(*address, Register::R11);
+                match (store_type, value) {
+                    (Type::I8, Value::Constant(n)) => {
+                        self.push(mov(byte_memory(address, 0), immediate(*n as i8 as i64)))
+                    }
+                    (Type::I8, Value::Register(_)) => {
+                        let value = self.value_register(*value, Register::RAX);
+                        self.push(mov(byte_memory(address, 0), byte_register(value)));
+                    }
+                    (_, Value::Constant(n)) if i32::try_from(*n).is_ok() => {
+                        self.push(mov(memory(address, 0), immediate(*n)))
+                    }
+                    _ => {
+                        let value = self.value_register(*value, Register::RAX);
+                        self.push(mov(memory(address, 0), register(value)));
+                    }
+                }
+            }
+            ir::Instruction::SlotAddress(destination, slot) => {
+                let target = self.target(*destination, &[]);
+                let offset = self.slot_offsets[slot.0];
+                self.push(lea(register(target), Self::frame_memory(offset)));
+                self.assign(*destination, target);
+            }
+            // The address of a symbol which can be defined elsewhere is loaded from the GOT
+            ir::Instruction::GlobalAddress {
+                destination,
+                name,
+                external,
+            } => {
+                let target = self.target(*destination, &[]);
+                if *external {
+                    self.push(mov(register(target), got_entry(name.clone())));
+                } else {
+                    self.push(lea(register(target), rip_relative(name.clone())));
+                }
+                self.assign(*destination, target);
+            }
+            ir::Instruction::StringAddress(destination, index) => {
+                let target = self.target(*destination, &[]);
+                self.push(lea(register(target), rip_relative(format!(".LC{}", index))));
+                self.assign(*destination, target);
+            }
+            ir::Instruction::Call {
+                result,
+                callee,
+                arguments,
+            } => self.call(result, callee, arguments),
+            ir::Instruction::MemCopy(destination, source, size) => {
+                self.move_value(rax(), *destination);
+                self.move_value(r11(), *source);
+                self.copy_memory((Register::RAX, 0), (Register::R11, 0), *size);
+            }
+            ir::Instruction::MemZero(address, size) => {
+                self.move_value(rax(), *address);
+                let words = size / 8;
+                for index in 0..words {
+                    self.push(mov(memory(Register::RAX, index as i32 * 8), immediate(0)));
+                }
+                for offset in words * 8..*size {
+                    self.push(mov(byte_memory(Register::RAX, offset as i32), immediate(0)));
+                }
+            }
+            ir::Instruction::VaStart(ap) => self.va_start(*ap),
+            ir::Instruction::VaArg(destination, ap) => self.va_arg(*destination, *ap),
         }
-        Expr::NotEqual(left, right) => {
-            get_compare_instruction(&|o: Operand| setne(o), left, right, meta_info)
+    }
+
+    fn binary(&mut self, op: BinaryOp, destination: VirtualRegister, left: Value, right: Value) {
+        match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => {
+                let target = self.target(destination, &[right]);
+                self.move_value(register(target), left);
+                let right = self.source_operand(right, Register::R11);
+                self.push(match op {
+                    BinaryOp::Add => add(register(target), right),
+                    BinaryOp::Sub => sub(register(target), right),
+                    _ => imul(register(target), right),
+                });
+                self.assign(destination, target);
+            }
+            // idiv takes the dividend in rdx:rax and leaves the quotient in rax
+            BinaryOp::Div => {
+                self.move_value(rax(), left);
+                self.push(cqo());
+                let right = self.value_register(right, Register::R11);
+                self.push(idiv(register(right)));
+                self.assign(destination, Register::RAX);
+            }
+            BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Less | BinaryOp::LessOrEqual => {
+                let set = match op {
+                    BinaryOp::Equal => sete,
+                    BinaryOp::NotEqual => setne,
+                    BinaryOp::Less => setl,
+                    _ => setle,
+                };
+                self.move_value(rax(), left);
+                let right = self.source_operand(right, Register::R11);
+                self.push(cmp(rax(), right));
+                self.push(set(al()));
+                self.push(movzb(rax(), al()));
+                self.assign(destination, Register::RAX);
+            }
         }
-        Expr::Less(left, right) => {
-            get_compare_instruction(&|o: Operand| setl(o), left, right, meta_info)
+    }
+
+    fn va_start(&mut self, ap: Value) {
+        let variadic_info = self
+            .variadic_info
+            .as_ref()
+            .expect("va_start is checked to be in a variadic function");
+        let (register_save_area_offset, gp_offset, overflow_arg_area_offset) = (
+            variadic_info.register_save_area_offset,
+            variadic_info.gp_offset,
+            variadic_info.overflow_arg_area_offset,
+        );
+        self.move_value(rax(), ap);
+        self.push(mov(
+            dword_memory(Register::RAX, 0),
+            immediate(gp_offset as i64),
+        ));
+        self.push(mov(
+            dword_memory(Register::RAX, 4),
+            immediate(FP_OFFSET as i64),
+        ));
+        self.push(lea(
+            r11(),
+            memory(Register::RBP, overflow_arg_area_offset as i32),
+        ));
+        self.push(mov(memory(Register::RAX, 8), r11()));
+        self.push(lea(r11(), Self::frame_memory(register_save_area_offset)));
+        self.push(mov(memory(Register::RAX, 16), r11()));
+    }
+
+    // Only arguments of the INTEGER class are supported
+    fn va_arg(&mut self, destination: VirtualRegister, ap: Value) {
+        let overflow_label = self.new_label();
+        let end_label = self.new_label();
+        self.move_value(rdx(), ap);
+        self.push(mov(
+            dword_register(Register::RAX),
+            dword_memory(Register::RDX, 0),
+        ));
+        // All general purpose registers are consumed if gp_offset reaches 48
+        self.push(cmp(rax(), immediate(FP_OFFSET as i64)));
+        self.push(je(overflow_label.clone()));
+        // The argument is at reg_save_area + gp_offset
+        self.push(mov(r11(), memory(Register::RDX, 16)));
+        self.push(add(r11(), rax()));
+        self.push(add(rax(), immediate(8)));
+        self.push(mov(
+            dword_memory(Register::RDX, 0),
+            dword_register(Register::RAX),
+        ));
+        self.push(jmp(end_label.clone()));
+        // The argument is at overflow_arg_area
+        self.push(label(overflow_label));
+        self.push(mov(r11(), memory(Register::RDX, 8)));
+        self.push(lea(rax(), memory(Register::R11, 8)));
+        self.push(mov(memory(Register::RDX, 8), rax()));
+        self.push(label(end_label));
+        let target = self.target(destination, &[]);
+        self.push(mov(register(target), memory(Register::R11, 0)));
+        self.assign(destination, target);
+    }
+
+    // Call a function following the System V AMD64 calling convention.
+    // A function designator is called directly, and a function pointer is called through r10.
+    // Values live across the call are in callee-saved registers or in memory.
+    fn call(&mut self, result: &CallResult, callee: &Callee, arguments: &[Argument]) {
+        let return_classes = match result {
+            CallResult::Aggregate(_, data_type) => classify(data_type, self.layouts),
+            _ => Vec::new(),
+        };
+        let return_in_memory = is_memory_class(&return_classes);
+        let argument_types = arguments.iter().map(|argument| match argument {
+            Argument::Scalar(_) => None,
+            Argument::Aggregate(_, data_type) => Some(data_type),
+        });
+        let first_register = if return_in_memory { 1 } else { 0 };
+        let (locations, _, stack_size) =
+            argument_locations(argument_types, first_register, self.layouts);
+        let stack_size = stack_size.next_multiple_of(16);
+
+        // Copy arguments passed on the stack, which leaves allocated registers unchanged
+        if stack_size > 0 {
+            self.push(sub(rsp(), immediate(stack_size as i64)));
         }
-        Expr::LessOrEqual(left, right) => {
-            get_compare_instruction(&|o: Operand| setle(o), left, right, meta_info)
+        for (argument, location) in arguments.iter().zip(locations.iter()) {
+            let ArgumentLocation::Stack(offset) = location else {
+                continue;
+            };
+            match argument {
+                Argument::Scalar(value) => {
+                    self.move_value(memory(Register::RSP, *offset as i32), *value)
+                }
+                Argument::Aggregate(address, data_type) => {
+                    self.move_value(rax(), *address);
+                    let size = self.layouts.size_of(data_type);
+                    self.copy_memory((Register::RSP, *offset as i32), (Register::RAX, 0), size);
+                }
+            }
         }
-    }
-}
 
-fn get_compare_instruction(
-    gen_instruction: &dyn Fn(Operand) -> Instruction,
-    left: &ArithExpr,
-    right: &ArithExpr,
-    meta_info: &mut MetaInfo,
-) -> Result<Assembly, CompilerError> {
-    let mut assembly: Assembly = Vec::new();
-    assembly.append(&mut get_assembly_arith_expr(left, meta_info)?);
-    assembly.append(&mut get_assembly_arith_expr(right, meta_info)?);
-    assembly.append(&mut vec![
-        pop(rdi()),
-        pop(rax()),
-        cmp(rax(), rdi()),
-        gen_instruction(al()),
-        movzb(rax(), al()),
-        push(rax()),
-    ]);
-    Ok(assembly)
-}
+        let target = match callee {
+            Callee::Direct(name) => label_operand(name.clone()),
+            Callee::Indirect(value) => {
+                self.move_value(r10(), *value);
+                r10()
+            }
+        };
 
-fn get_assembly_sub_add(
-    left: &ArithExpr,
-    right: &Factor,
-    operator_str: &str,
-    add_sub_instruction: &dyn Fn(Operand, Operand) -> Instruction,
-    meta_info: &mut MetaInfo,
-) -> Result<Assembly, CompilerError> {
-    let mut assembly: Assembly = Vec::new();
-    assembly.append(&mut get_assembly_arith_expr(left, meta_info)?);
-    assembly.append(&mut get_assembly_factor(right, meta_info)?);
-    assembly.append(&mut vec![pop(rdi()), pop(rax())]);
-    let left_type = match infer_type_arith_expr(left, meta_info) {
-        Ok(data_type) => data_type,
-        Err(e) => return Err(CompilerError::TypeMismatch(e)),
-    };
-    match left_type {
-        DataType::Primitive(PrimitiveType::Int) => assembly.push(add_sub_instruction(rax(), rdi())),
-        DataType::Pointer(_, base_type) => {
-            let size = match *base_type {
-                DataType::Primitive(PrimitiveType::Int) => 8,
-                _ => 8,
+        // Move arguments to the argument registers. The ones read from allocated registers
+        // are moved at once, and then the others are moved.
+        let mut sources = Vec::new();
+        if let (true, CallResult::Aggregate(address, _)) = (return_in_memory, result) {
+            // The pointer to the memory for the return value is the hidden first argument
+            sources.push((ARGUMENT_REGISTERS[0], *address, None));
+        }
+        for (argument, location) in arguments.iter().zip(locations.iter()) {
+            let ArgumentLocation::Registers(first, count) = location else {
+                continue;
             };
-            assembly.append(&mut vec![
-                imul(rdi(), immediate(size)),
-                add_sub_instruction(rax(), rdi()),
-            ]);
+            match argument {
+                Argument::Scalar(value) => sources.push((ARGUMENT_REGISTERS[*first], *value, None)),
+                Argument::Aggregate(address, _) => {
+                    for index in 0..*count {
+                        let displacement = index as i32 * 8;
+                        sources.push((
+                            ARGUMENT_REGISTERS[first + index],
+                            *address,
+                            Some(displacement),
+                        ));
+                    }
+                }
+            }
+        }
+        let mut moves = Vec::new();
+        let mut other_moves = Vec::new();
+        for (destination, value, displacement) in sources {
+            match (self.value_operand(value), displacement) {
+                (Operand::Register(source), None) => {
+                    moves.push((destination, MoveSource::Register(source)))
+                }
+                (Operand::Register(source), Some(displacement)) => {
+                    moves.push((destination, MoveSource::Memory(source, displacement)))
+                }
+                _ => other_moves.push((destination, value, displacement)),
+            }
         }
-        _ => {
-            return Err(CompilerError::TypeMismatch(format!(
-                "left side of {} is not int or pointer",
-                operator_str
-            )))
+        self.parallel_move(moves);
+        for (destination, value, displacement) in other_moves {
+            match displacement {
+                None => self.move_value(register(destination), value),
+                Some(displacement) => {
+                    self.move_value(rax(), value);
+                    self.push(mov(
+                        register(destination),
+                        memory(Register::RAX, displacement),
+                    ));
+                }
+            }
+        }
+
+        // al holds the number of vector registers used by a call to a variadic function
+        self.push(mov(rax(), immediate(0)));
+        self.push(call(target));
+        if stack_size > 0 {
+            self.push(add(rsp(), immediate(stack_size as i64)));
         }
-    }
-    assembly.append(&mut vec![push(rax())]);
-    Ok(assembly)
-}
 
-fn get_assembly_arith_expr(
-    expr: &ArithExpr,
-    meta_info: &mut MetaInfo,
-) -> Result<Assembly, CompilerError> {
-    match expr {
-        ArithExpr::Factor(factor) => get_assembly_factor(factor, meta_info),
-        ArithExpr::Add(expr, factor) => get_assembly_sub_add(
-            expr,
-            factor,
-            "+",
-            &|o1: Operand, o2: Operand| add(o1, o2),
-            meta_info,
-        ),
-        ArithExpr::Sub(expr, factor) => get_assembly_sub_add(
-            expr,
-            factor,
-            "-",
-            &|o1: Operand, o2: Operand| sub(o1, o2),
-            meta_info,
-        ),
+        match result {
+            CallResult::None => (),
+            // A char returned by a function compiled elsewhere is only in al
+            CallResult::Scalar(destination) => {
+                if self.function.register_type(*destination) == Type::I8 {
+                    self.push(movsx(rax(), al()));
+                }
+                self.assign(*destination, Register::RAX);
+            }
+            // An aggregate returned in registers is stored to its address
+            CallResult::Aggregate(address, _) if !return_in_memory => {
+                let address = self.value_register(*address, Register::R11);
+                self.push(mov(memory(address, 0), rax()));
+                if return_classes.len() > 1 {
+                    self.push(mov(memory(address, 8), rdx()));
+                }
+            }
+            CallResult::Aggregate(_, _) => (),
+        }
     }
-}
 
-fn get_assembly_factor(
-    factor: &Factor,
-    meta_info: &mut MetaInfo,
-) -> Result<Assembly, CompilerError> {
-    match factor {
-        Factor::Unary(unary) => get_assembly_unary(unary, meta_info),
-        Factor::Mul(factor, unary) => {
-            let mut assembly: Assembly = Vec::new();
-            assembly.append(&mut get_assembly_factor(factor, meta_info)?);
-            assembly.append(&mut get_assembly_unary(unary, meta_info)?);
-            assembly.append(&mut vec![pop(rdi()), pop(rax()), mul(rdi()), push(rax())]);
-            Ok(assembly)
-        }
-        Factor::Div(factor, unary) => {
-            let mut assembly: Assembly = Vec::new();
-            assembly.append(&mut get_assembly_factor(factor, meta_info)?);
-            assembly.append(&mut get_assembly_unary(unary, meta_info)?);
-            assembly.append(&mut vec![
-                pop(rdi()),
-                pop(rax()),
-                cqo(),
-                idiv(rdi()),
-                push(rax()),
-            ]);
-            Ok(assembly)
+    fn jump(&mut self, target: ir::BlockId, next: ir::BlockId) {
+        if target != next {
+            self.push(jmp(self.block_label(target)));
         }
     }
-}
 
-fn get_assembly_unary(unary: &Unary, meta_info: &mut MetaInfo) -> Result<Assembly, CompilerError> {
-    match unary {
-        Unary::Atom(atom) => get_assembly_atom(atom, meta_info),
-        Unary::Neg(atom) => {
-            let mut assembly: Assembly = Vec::new();
-            assembly.append(&mut get_assembly_atom(atom, meta_info)?);
-            assembly.append(&mut vec![pop(rax()), neg(rax()), push(rax())]);
-            Ok(assembly)
-        }
-        Unary::PointerDeref(atom) => {
-            let mut assembly: Assembly = Vec::new();
-            assembly.append(&mut get_assembly_atom(atom, meta_info)?);
-            assembly.append(&mut vec![pop(rax()), mov(rax(), m_rax()), push(rax())]);
-            Ok(assembly)
+    // The next block follows the current one, so jumps to it are omitted
+    fn terminator(&mut self, terminator: &Terminator, next: ir::BlockId) {
+        match terminator {
+            Terminator::Jump(target) => self.jump(*target, next),
+            Terminator::Branch(Value::Constant(n), then_block, else_block) => {
+                let target = if *n != 0 { then_block } else { else_block };
+                self.jump(*target, next);
+            }
+            Terminator::Branch(condition, then_block, else_block) => {
+                let condition = self.source_operand(*condition, Register::R11);
+                self.push(cmp(condition, immediate(0)));
+                if *then_block == next {
+                    self.push(je(self.block_label(*else_block)));
+                } else {
+                    self.push(jne(self.block_label(*then_block)));
+                    self.jump(*else_block, next);
+                }
+            }
+            Terminator::Return(value) => {
+                match (&self.function.return_type, value) {
+                    (ReturnType::Scalar(_), Some(value)) => self.move_value(rax(), *value),
+                    (ReturnType::Aggregate(data_type), Some(value)) => {
+                        self.return_aggregate(data_type, *value)
+                    }
+                    _ => (),
+                }
+                for (saved, offset) in self.callee_saved_offsets.clone() {
+                    self.push(mov(register(saved), Self::frame_memory(offset)));
+                }
+                self.push(mov(rsp(), rbp()));
+                self.push(pop(rbp()));
+                self.push(ret());
+            }
         }
     }
-}
 
-fn get_assembly_atom(atom: &Atom, meta_info: &mut MetaInfo) -> Result<Assembly, CompilerError> {
-    match atom {
-        Atom::Number(n) => Ok(vec![push(Operand::Immediate(*n))]),
-        Atom::Expr(expr) => get_assembly_expr(expr, meta_info),
-        Atom::Variable(lval) => {
-            //let id = meta_info.get_variable_id_and_register_it(lval);
-            let id = match meta_info.get_variable(lval) {
-                Some(var_info) => var_info.id,
-                None => return Err(CompilerError::UndefinedVariable(lval.clone())),
-            };
-            Ok(vec![
-                mov(rax(), rbp()),
-                sub(rax(), immediate((id + 1) as i32 * 8)),
-                push(m_rax()),
-            ])
-        }
-        // 7 or more arguments are not supported
-        Atom::FunctionCall(func_name, arguments) => {
-            let mut assembly: Assembly = Vec::new();
-            for argument in arguments.iter() {
-                assembly.append(&mut get_assembly_expr(argument, meta_info)?);
-            }
-            for (_, register) in arguments.iter().zip(ARGUMENT_REGISTERS.iter()).rev() {
-                assembly.push(pop(register.clone()));
-            }
-
-            assembly.push(call(func_name.clone()));
-            assembly.push(push(rax()));
-            Ok(assembly)
-        }
-        Atom::AddressOf(lval) => {
-            let id = match meta_info.get_variable(lval) {
-                Some(var_info) => var_info.id,
-                None => return Err(CompilerError::UndefinedVariable(lval.clone())),
-            };
-            Ok(vec![
-                comment("address of"),
-                mov(rax(), rbp()),
-                sub(rax(), immediate((id + 1) as i32 * 8)),
-                push(rax()),
-                comment("address of end"),
-            ])
+    fn return_aggregate(&mut self, data_type: &DataType, address: Value) {
+        self.move_value(r11(), address);
+        match self.return_pointer_offset {
+            // Copy the value to the memory pointed by the hidden argument, and return the pointer
+            Some(offset) => {
+                self.push(mov(rax(), Self::frame_memory(offset)));
+                let size = self.layouts.size_of(data_type);
+                self.copy_memory((Register::RAX, 0), (Register::R11, 0), size);
+            }
+            // Return eightbytes in rax and rdx
+            None => {
+                self.push(mov(rax(), memory(Register::R11, 0)));
+                if classify(data_type, self.layouts).len() > 1 {
+                    self.push(mov(rdx(), memory(Register::R11, 8)));
+                }
+            }
         }
     }
 }
@@ -0,0 +1,403 @@
+use crate::ast::*;
+use crate::compile_error::CompilerError;
+use crate::layout::Layouts;
+use crate::typed_ast::*;
+use std::iter;
+
+// A scalar value stored at an offset from the beginning of an initialized object.
+// The value is an expression of the AST or of the typed AST.
+#[derive(Debug)]
+pub struct InitItem<E> {
+    pub offset: u32,
+    pub data_type: DataType,
+    pub value: InitValue<E>,
+}
+
+#[derive(Debug)]
+pub enum InitValue<E> {
+    Expr(E),
+    Byte(u8),
+}
+
+// What an address in a constant expression points to
+#[derive(Clone, Debug)]
+pub enum AddressBase {
+    // A variable with static storage duration or a function
+    Symbol(SymbolId),
+    StringLiteral(Vec<u8>),
+}
+
+// The value of a constant expression
+pub enum ConstValue {
+    Int(i64),
+    // An address plus an offset in bytes
+    Address(AddressBase, i64),
+}
+
+// The initial content of a global variable
+#[derive(Clone, Debug)]
+pub struct DataImage {
+    pub bytes: Vec<u8>,
+    // The offsets where an address plus an offset is stored
+    pub relocations: Vec<(u32, AddressBase, i64)>,
+}
+
+/// Returns the string literal if the expression is a single one
+pub fn string_literal(expr: &Expr) -> Option<&Vec<u8>> {
+    match expr_to_atom(expr).map(|atom| &atom.kind) {
+        Some(AtomKind::StringLiteral(bytes)) => Some(bytes),
+        Some(AtomKind::Expr(expr)) => string_literal(expr),
+        _ => None,
+    }
+}
+
+fn expr_to_atom(expr: &Expr) -> Option<&Atom> {
+    match &expr.kind {
+        ExprKind::ArithExpr(arith_expr) => match &**arith_expr {
+            ArithExpr::Factor(factor) => match &**factor {
+                Factor::Unary(unary) => match &**unary {
+                    Unary::Atom(atom) => Some(atom),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Complete the length of an array declared without it from its initializer,
+/// as in `int a[] = {1, 2, 3};` or `char s[] = "abc";`
+pub fn complete_type(data_type: &DataType, initializer: &Initializer) -> DataType {
+    match (data_type, initializer) {
+        (DataType::Array(element_type, None), Initializer::List(items)) => {
+            let mut index = 0;
+            let mut length = 0;
+            for (designators, _) in items.iter() {
+                if let Some(Designator::Index(designated_index)) = designators.first() {
+                    index = *designated_index;
+                }
+                index += 1;
+                length = length.max(index);
+            }
+            array(*element_type.clone(), Some(length))
+        }
+        (DataType::Array(element_type, None), Initializer::Expr(expr)) => {
+            match string_literal(expr) {
+                Some(bytes) => array(*element_type.clone(), Some(bytes.len() as u32 + 1)),
+                None => data_type.clone(),
+            }
+        }
+        _ => data_type.clone(),
+    }
+}
+
+/// Flatten an initializer into the scalar values to store.
+/// The remaining bytes of the object are zero.
+pub fn flatten_initializer<'a>(
+    data_type: &DataType,
+    initializer: &'a Initializer,
+    offset: u32,
+    layouts: &Layouts,
+    items: &mut Vec<InitItem<&'a Expr>>,
+) -> Result<(), CompilerError> {
+    match (data_type, initializer) {
+        (DataType::Array(element_type, Some(length)), Initializer::Expr(expr))
+            if **element_type == char() && string_literal(expr).is_some() =>
+        {
+            let bytes = string_literal(expr).unwrap();
+            if bytes.len() > *length as usize {
+                return Err(CompilerError::InvalidInitializer(format!(
+                    "string of length {} is too long for {}",
+                    bytes.len(),
+                    data_type
+                )));
+            }
+            for (index, byte) in bytes.iter().chain(iter::once(&0)).enumerate() {
+                if index < *length as usize {
+                    items.push(InitItem {
+                        offset: offset + index as u32,
+                        data_type: char(),
+                        value: InitValue::Byte(*byte),
+                    });
+                }
+            }
+            Ok(())
+        }
+        (DataType::Array(element_type, Some(length)), Initializer::List(list)) => {
+            let size = layouts.size_of(element_type);
+            let mut index = 0;
+            for (designators, initializer) in list.iter() {
+                let rest = match designators.split_first() {
+                    Some((Designator::Index(designated_index), rest)) => {
+                        index = *designated_index;
+                        rest
+                    }
+                    Some((Designator::Member(member), _)) => {
+                        return Err(CompilerError::InvalidInitializer(format!(
+                            "member designator .{} for {}",
+                            member, data_type
+                        )))
+                    }
+                    None => &[],
+                };
+                if index >= *length {
+                    return Err(CompilerError::InvalidInitializer(format!(
+                        "excess elements in initializer of {}",
+                        data_type
+                    )));
+                }
+                flatten_designated(
+                    element_type,
+                    rest,
+                    initializer,
+                    offset + index * size,
+                    layouts,
+                    items,
+                )?;
+                index += 1;
+            }
+            Ok(())
+        }
+        (DataType::Struct(name) | DataType::Union(name), Initializer::List(list)) => {
+            let is_union = matches!(data_type, DataType::Union(_));
+            let members = match layouts.get_struct(name) {
+                Some(struct_info) => &struct_info.members,
+                None => return Err(CompilerError::IncompleteType(data_type.clone())),
+            };
+            let mut index = 0;
+            for (position, (designators, initializer)) in list.iter().enumerate() {
+                let rest = match designators.split_first() {
+                    Some((Designator::Member(member), rest)) => {
+                        match members.iter().position(|m| &m.name == member) {
+                            Some(member_index) => index = member_index,
+                            None => {
+                                return Err(CompilerError::InvalidInitializer(format!(
+                                    "{} has no member {}",
+                                    data_type, member
+                                )))
+                            }
+                        }
+                        rest
+                    }
+                    Some((Designator::Index(designated_index), _)) => {
+                        return Err(CompilerError::InvalidInitializer(format!(
+                            "index designator [{}] for {}",
+                            designated_index, data_type
+                        )))
+                    }
+                    None => &[],
+                };
+                // Only one member of a union is initialized
+                if index >= members.len() || (is_union && position > 0 && designators.is_empty()) {
+                    return Err(CompilerError::InvalidInitializer(format!(
+                        "excess elements in initializer of {}",
+                        data_type
+                    )));
+                }
+                let member = &members[index];
+                flatten_designated(
+                    &member.data_type,
+                    rest,
+                    initializer,
+                    offset + member.offset,
+                    layouts,
+                    items,
+                )?;
+                index += 1;
+            }
+            Ok(())
+        }
+        (DataType::Array(_, None), _) => Err(CompilerError::IncompleteType(data_type.clone())),
+        (DataType::Array(_, _), Initializer::Expr(_)) => Err(CompilerError::InvalidInitializer(
+            format!("{} must be initialized by a brace-enclosed list", data_type),
+        )),
+        // Braces around a scalar initializer
+        (_, Initializer::List(list)) => match list.as_slice() {
+            [] => Ok(()),
+            [(designators, initializer)] if designators.is_empty() => {
+                flatten_initializer(data_type, initializer, offset, layouts, items)
+            }
+            _ => Err(CompilerError::InvalidInitializer(format!(
+                "excess elements in initializer of {}",
+                data_type
+            ))),
+        },
+        (_, Initializer::Expr(expr)) => {
+            items.push(InitItem {
+                offset,
+                data_type: data_type.clone(),
+                value: InitValue::Expr(expr),
+            });
+            Ok(())
+        }
+    }
+}
+
+fn flatten_designated<'a>(
+    data_type: &DataType,
+    designators: &[Designator],
+    initializer: &'a Initializer,
+    offset: u32,
+    layouts: &Layouts,
+    items: &mut Vec<InitItem<&'a Expr>>,
+) -> Result<(), CompilerError> {
+    match designators.split_first() {
+        None => flatten_initializer(data_type, initializer, offset, layouts, items),
+        Some((Designator::Index(index), rest)) => match data_type {
+            DataType::Array(element_type, Some(length)) if index < length => flatten_designated(
+                element_type,
+                rest,
+                initializer,
+                offset + index * layouts.size_of(element_type),
+                layouts,
+                items,
+            ),
+            _ => Err(CompilerError::InvalidInitializer(format!(
+                "index designator [{}] for {}",
+                index, data_type
+            ))),
+        },
+        Some((Designator::Member(member), rest)) => {
+            let member_info = layouts.get_member(data_type, member)?;
+            flatten_designated(
+                &member_info.data_type,
+                rest,
+                initializer,
+                offset + member_info.offset,
+                layouts,
+                items,
+            )
+        }
+    }
+}
+
+/// Build the initial content of a variable with static storage duration from the values
+/// of its initializer. Returns None if any of them is not a constant expression.
+pub fn build_data_image(
+    data_type: &DataType,
+    items: &[InitItem<TypedExpr>],
+    layouts: &Layouts,
+    symbols: &[Symbol],
+) -> Option<DataImage> {
+    let mut image = DataImage {
+        bytes: vec![0; layouts.size_of(data_type) as usize],
+        relocations: Vec::new(),
+    };
+    for item in items.iter() {
+        let offset = item.offset as usize;
+        let size = layouts.size_of(&item.data_type) as usize;
+        let value = match &item.value {
+            InitValue::Byte(byte) => ConstValue::Int(*byte as i64),
+            InitValue::Expr(expr) => eval_constant(expr, layouts, symbols)?,
+        };
+        match value {
+            ConstValue::Int(value) => {
+                image.bytes[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+            }
+            ConstValue::Address(base, addend) if size == 8 => {
+                image.relocations.retain(|(o, _, _)| *o != item.offset);
+                image.relocations.push((item.offset, base, addend));
+            }
+            ConstValue::Address(_, _) => return None,
+        }
+    }
+    Some(image)
+}
+
+/// Evaluate a constant expression at compile time.
+/// Returns None if the expression is not a constant.
+pub fn eval_constant(
+    expr: &TypedExpr,
+    layouts: &Layouts,
+    symbols: &[Symbol],
+) -> Option<ConstValue> {
+    match &expr.kind {
+        TypedExprKind::Number(n) => Some(ConstValue::Int(*n as i64)),
+        TypedExprKind::StringLiteral(bytes) => Some(ConstValue::Address(
+            AddressBase::StringLiteral(bytes.clone()),
+            0,
+        )),
+        TypedExprKind::AddressOf(lvalue) => constant_address(lvalue, layouts, symbols),
+        // An array is converted to the address of its first element
+        TypedExprKind::Convert(
+            Conversion::ArrayToPointer | Conversion::FunctionToPointer,
+            operand,
+        ) => match operand.kind {
+            TypedExprKind::Variable(id) => static_address(id, symbols),
+            _ => eval_constant(operand, layouts, symbols),
+        },
+        TypedExprKind::Convert(_, operand) => eval_constant(operand, layouts, symbols),
+        TypedExprKind::Neg(operand) => match eval_constant(operand, layouts, symbols)? {
+            ConstValue::Int(value) => Some(ConstValue::Int(value.wrapping_neg())),
+            _ => None,
+        },
+        TypedExprKind::Binary(operator, left, right) => {
+            let (ConstValue::Int(left), ConstValue::Int(right)) = (
+                eval_constant(left, layouts, symbols)?,
+                eval_constant(right, layouts, symbols)?,
+            ) else {
+                return None;
+            };
+            let value = match operator {
+                BinaryOperator::Add => left.wrapping_add(right),
+                BinaryOperator::Sub => left.wrapping_sub(right),
+                BinaryOperator::Mul => left.wrapping_mul(right),
+                // Division by zero is not a constant expression
+                BinaryOperator::Div if right == 0 => return None,
+                BinaryOperator::Div => left.wrapping_div(right),
+                BinaryOperator::Equal => (left == right) as i64,
+                BinaryOperator::NotEqual => (left != right) as i64,
+                BinaryOperator::Less => (left < right) as i64,
+                BinaryOperator::LessOrEqual => (left <= right) as i64,
+            };
+            Some(ConstValue::Int(value))
+        }
+        _ => None,
+    }
+}
+
+// The address of an lvalue designating an object with static storage duration, such as
+// `&a[2]` or `&s.m`, is a constant if the indices are
+fn constant_address(
+    lvalue: &TypedExpr,
+    layouts: &Layouts,
+    symbols: &[Symbol],
+) -> Option<ConstValue> {
+    let (base, offset) = match &lvalue.kind {
+        TypedExprKind::Variable(id) => return static_address(*id, symbols),
+        TypedExprKind::Deref(pointer) => (eval_constant(pointer, layouts, symbols)?, 0),
+        TypedExprKind::Index(pointer, index) => {
+            let ConstValue::Int(index) = eval_constant(index, layouts, symbols)? else {
+                return None;
+            };
+            let size = layouts.size_of(&lvalue.data_type) as i64;
+            (
+                eval_constant(pointer, layouts, symbols)?,
+                index.wrapping_mul(size),
+            )
+        }
+        TypedExprKind::Member(object, _, offset) => {
+            (constant_address(object, layouts, symbols)?, *offset as i64)
+        }
+        TypedExprKind::PointerMember(pointer, _, offset) => {
+            (eval_constant(pointer, layouts, symbols)?, *offset as i64)
+        }
+        _ => return None,
+    };
+    match base {
+        ConstValue::Address(base, addend) => {
+            Some(ConstValue::Address(base, addend.wrapping_add(offset)))
+        }
+        ConstValue::Int(_) => None,
+    }
+}
+
+// The address of a variable with static storage duration or a function is a constant
+fn static_address(id: SymbolId, symbols: &[Symbol]) -> Option<ConstValue> {
+    match symbols[id.0].kind {
+        SymbolKind::Local => None,
+        _ => Some(ConstValue::Address(AddressBase::Symbol(id), 0)),
+    }
+}
@@ -0,0 +1,828 @@
+use crate::ast::DataType;
+use std::fmt;
+
+// A target-independent intermediate representation in three-address code,
+// lowered from the typed AST.
+// A function consists of basic blocks of instructions on virtual registers,
+// each of which ends with a terminator.
+// Scalar local variables whose addresses are not taken live in virtual registers,
+// which can be assigned more than once, and the others live in stack slots.
+// Values of structs, unions, arrays and va_list are represented by their addresses.
+
+pub struct Module {
+    pub functions: Vec<Function>,
+    // Variables with static storage duration in the order of their definitions
+    pub globals: Vec<Global>,
+    // Indexed by the operand of `Instruction::StringAddress`
+    pub string_literals: Vec<Vec<u8>>,
+}
+
+pub struct Global {
+    pub label: String,
+    pub size: u32,
+    pub align: u32,
+    // None if the variable is initialized with zeros
+    pub data: Option<Data>,
+    // True if the variable has external linkage
+    pub is_global: bool,
+}
+
+// The initial content of a global variable
+pub struct Data {
+    pub bytes: Vec<u8>,
+    // The offsets where the address of a symbol or a string literal plus an addend is stored
+    pub relocations: Vec<(u32, Address, i64)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Address {
+    // The label of a global variable, a static local variable or a function
+    Symbol(String),
+    // The index of a string literal
+    StringLiteral(usize),
+}
+
+pub struct Function {
+    pub name: String,
+    // True if the function has external linkage
+    pub is_global: bool,
+    pub parameters: Vec<Parameter>,
+    pub variadic: bool,
+    pub return_type: ReturnType,
+    // Indexed by `VirtualRegister`
+    pub registers: Vec<Type>,
+    // Indexed by `SlotId`
+    pub slots: Vec<StackSlot>,
+    // Indexed by `BlockId`, where the first block is the entry
+    pub blocks: Vec<Block>,
+}
+
+/// The type of a virtual register.
+/// `I8` is a char, which arithmetic does not take without being converted to `I64`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Type {
+    I8,
+    I64,
+    Ptr,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ReturnType {
+    Void,
+    Scalar(Type),
+    // A struct or a union, which a return copies from the address
+    Aggregate(DataType),
+}
+
+pub enum Parameter {
+    Scalar(VirtualRegister),
+    // A struct or a union passed by value, which is copied to the slot on entry
+    Aggregate(SlotId, DataType),
+}
+
+pub struct StackSlot {
+    pub size: u32,
+    pub align: u32,
+    // The variable stored in the slot, or None for a temporary
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct VirtualRegister(pub usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct BlockId(pub usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SlotId(pub usize);
+
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Value {
+    Register(VirtualRegister),
+    Constant(i64),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Callee {
+    // A function called by its name
+    Direct(String),
+    // A function pointer
+    Indirect(Value),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Argument {
+    Scalar(Value),
+    // A struct or a union passed by value, given by its address
+    Aggregate(Value, DataType),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CallResult {
+    None,
+    Scalar(VirtualRegister),
+    // A struct or a union returned by value, which is stored to the address
+    Aggregate(Value, DataType),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Instruction {
+    Copy(VirtualRegister, Value),
+    Binary(BinaryOp, VirtualRegister, Value, Value),
+    Neg(VirtualRegister, Value),
+    // Convert the value to the type of the register.
+    // A char is sign-extended to an integer, and an integer is truncated to a char.
+    Cast(VirtualRegister, Value),
+    // Load a value of the type of the register from the address
+    Load(VirtualRegister, Value),
+    // Store a value of the type to the address given first
+    Store(Type, Value, Value),
+    SlotAddress(VirtualRegister, SlotId),
+    // The address of a global variable or a function. If `external` is true,
+    // the symbol can be defined in another object.
+    GlobalAddress {
+        destination: VirtualRegister,
+        name: String,
+        external: bool,
+    },
+    StringAddress(VirtualRegister, usize),
+    Call {
+        result: CallResult,
+        callee: Callee,
+        arguments: Vec<Argument>,
+    },
+    // Copy the number of bytes from the address given second to the one given first
+    MemCopy(Value, Value, u32),
+    // Fill the number of bytes from the address with zeros
+    MemZero(Value, u32),
+    // Initialize the va_list at the address for the variable arguments of the function
+    VaStart(Value),
+    // Fetch the next variable argument of the va_list at the address
+    VaArg(VirtualRegister, Value),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Terminator {
+    Jump(BlockId),
+    // Jump to the first block if the value is not zero, and to the second otherwise
+    Branch(Value, BlockId, BlockId),
+    // The value is the address of the result if the function returns an aggregate.
+    // It is None if the control reaches the end of a function without a value.
+    Return(Option<Value>),
+}
+
+impl Instruction {
+    /// The register this instruction assigns, if any
+    pub fn destination(&self) -> Option<VirtualRegister> {
+        match self {
+            Instruction::Copy(destination, _)
+            | Instruction::Binary(_, destination, _, _)
+            | Instruction::Neg(destination, _)
+            | Instruction::Cast(destination, _)
+            | Instruction::Load(destination, _)
+            | Instruction::SlotAddress(destination, _)
+            | Instruction::GlobalAddress { destination, .. }
+            | Instruction::StringAddress(destination, _)
+            | Instruction::Call {
+                result: CallResult::Scalar(destination),
+                ..
+            }
+            | Instruction::VaArg(destination, _) => Some(*destination),
+            _ => None,
+        }
+    }
+
+    /// The values this instruction reads
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            Instruction::Copy(_, value)
+            | Instruction::Neg(_, value)
+            | Instruction::Cast(_, value)
+            | Instruction::Load(_, value)
+            | Instruction::MemZero(value, _)
+            | Instruction::VaStart(value)
+            | Instruction::VaArg(_, value) => vec![*value],
+            Instruction::Binary(_, _, left, right)
+            | Instruction::Store(_, left, right)
+            | Instruction::MemCopy(left, right, _) => vec![*left, *right],
+            Instruction::SlotAddress(_, _)
+            | Instruction::GlobalAddress { .. }
+            | Instruction::StringAddress(_, _) => Vec::new(),
+            Instruction::Call {
+                result,
+                callee,
+                arguments,
+            } => {
+                let mut operands = Vec::new();
+                if let Callee::Indirect(value) = callee {
+                    operands.push(*value);
+                }
+                for argument in arguments.iter() {
+                    match argument {
+                        Argument::Scalar(value) | Argument::Aggregate(value, _) => {
+                            operands.push(*value)
+                        }
+                    }
+                }
+                if let CallResult::Aggregate(value, _) = result {
+                    operands.push(*value);
+                }
+                operands
+            }
+        }
+    }
+
+    /// The values this instruction reads, in the same order as `operands`
+    pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Instruction::Copy(_, value)
+            | Instruction::Neg(_, value)
+            | Instruction::Cast(_, value)
+            | Instruction::Load(_, value)
+            | Instruction::MemZero(value, _)
+            | Instruction::VaStart(value)
+            | Instruction::VaArg(_, value) => vec![value],
+            Instruction::Binary(_, _, left, right)
+            | Instruction::Store(_, left, right)
+            | Instruction::MemCopy(left, right, _) => vec![left, right],
+            Instruction::SlotAddress(_, _)
+            | Instruction::GlobalAddress { .. }
+            | Instruction::StringAddress(_, _) => Vec::new(),
+            Instruction::Call {
+                result,
+                callee,
+                arguments,
+            } => {
+                let mut operands = Vec::new();
+                if let Callee::Indirect(value) = callee {
+                    operands.push(value);
+                }
+                for argument in arguments.iter_mut() {
+                    match argument {
+                        Argument::Scalar(value) | Argument::Aggregate(value, _) => {
+                            operands.push(value)
+                        }
+                    }
+                }
+                if let CallResult::Aggregate(value, _) = result {
+                    operands.push(value);
+                }
+                operands
+            }
+        }
+    }
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch(_, then_block, else_block) => vec![*then_block, *else_block],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            Terminator::Branch(condition, _, _) => vec![*condition],
+            Terminator::Return(Some(value)) => vec![*value],
+            Terminator::Jump(_) | Terminator::Return(None) => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Terminator::Branch(condition, _, _) => vec![condition],
+            Terminator::Return(Some(value)) => vec![value],
+            Terminator::Jump(_) | Terminator::Return(None) => Vec::new(),
+        }
+    }
+}
+
+impl Function {
+    pub fn register_type(&self, register: VirtualRegister) -> Type {
+        self.registers[register.0]
+    }
+
+    /// Remove the blocks which cannot be reached from the entry, such as the code following
+    /// a return, and renumber the rest in their original order
+    pub fn remove_unreachable_blocks(&mut self) {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut stack = vec![BlockId(0)];
+        while let Some(block) = stack.pop() {
+            if !reachable[block.0] {
+                reachable[block.0] = true;
+                stack.extend(self.blocks[block.0].terminator.successors());
+            }
+        }
+        let mut new_ids = Vec::new();
+        let mut count = 0;
+        for is_reachable in reachable.iter() {
+            new_ids.push(BlockId(count));
+            if *is_reachable {
+                count += 1;
+            }
+        }
+        let blocks = std::mem::take(&mut self.blocks);
+        for (block, is_reachable) in blocks.into_iter().zip(reachable) {
+            if !is_reachable {
+                continue;
+            }
+            let terminator = match block.terminator {
+                Terminator::Jump(target) => Terminator::Jump(new_ids[target.0]),
+                Terminator::Branch(condition, then_block, else_block) => {
+                    Terminator::Branch(condition, new_ids[then_block.0], new_ids[else_block.0])
+                }
+                terminator @ Terminator::Return(_) => terminator,
+            };
+            self.blocks.push(Block {
+                instructions: block.instructions,
+                terminator,
+            });
+        }
+    }
+}
+
+/// An inconsistency of the IR, which is a bug of the pass producing it
+#[derive(Debug)]
+pub struct VerifyError {
+    pub function: String,
+    pub message: String,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid IR in function {}: {}",
+            self.function, self.message
+        )
+    }
+}
+
+/// Check that every function of the module is well-formed: the operands refer to
+/// existing registers, slots, blocks and string literals, every register read is assigned
+/// somewhere, and the operands have the types the instructions take.
+pub fn verify(module: &Module) -> Result<(), VerifyError> {
+    for function in module.functions.iter() {
+        FunctionVerifier { module, function }
+            .verify()
+            .map_err(|message| VerifyError {
+                function: function.name.clone(),
+                message,
+            })?;
+    }
+    Ok(())
+}
+
+struct FunctionVerifier<'a> {
+    module: &'a Module,
+    function: &'a Function,
+}
+
+impl FunctionVerifier<'_> {
+    fn verify(&self) -> Result<(), String> {
+        if self.function.blocks.is_empty() {
+            return Err("no entry block".to_string());
+        }
+        for parameter in self.function.parameters.iter() {
+            match parameter {
+                Parameter::Scalar(register) => self.check_register(*register)?,
+                Parameter::Aggregate(slot, _) => self.check_slot(*slot)?,
+            }
+        }
+        // A register read but never assigned is an uninitialized variable, whose value is
+        // indeterminate rather than invalid
+        for (index, block) in self.function.blocks.iter().enumerate() {
+            let check_operand = |value: &Value| match value {
+                Value::Register(register) => self.check_register(*register),
+                Value::Constant(_) => Ok(()),
+            };
+            for instruction in block.instructions.iter() {
+                if let Some(destination) = instruction.destination() {
+                    self.check_register(destination)?;
+                }
+            }
+            for instruction in block.instructions.iter() {
+                for operand in instruction.operands().iter() {
+                    check_operand(operand)?;
+                }
+                self.check_instruction(instruction)
+                    .map_err(|message| format!("{} in bb{}: {}", message, index, instruction))?;
+            }
+            for operand in block.terminator.operands().iter() {
+                check_operand(operand)?;
+            }
+            self.check_terminator(&block.terminator)
+                .map_err(|message| format!("{} in bb{}: {}", message, index, block.terminator))?;
+        }
+        Ok(())
+    }
+
+    fn check_register(&self, register: VirtualRegister) -> Result<(), String> {
+        if register.0 < self.function.registers.len() {
+            Ok(())
+        } else {
+            Err(format!("{} is not declared", register))
+        }
+    }
+
+    fn check_slot(&self, slot: SlotId) -> Result<(), String> {
+        if slot.0 < self.function.slots.len() {
+            Ok(())
+        } else {
+            Err(format!("{} is not declared", slot))
+        }
+    }
+
+    // The type of a value, where a constant can be of any type
+    fn value_type(&self, value: &Value) -> Option<Type> {
+        match value {
+            Value::Register(register) => Some(self.function.register_type(*register)),
+            Value::Constant(_) => None,
+        }
+    }
+
+    fn expect_type(&self, value: &Value, expected: &[Type]) -> Result<(), String> {
+        match self.value_type(value) {
+            Some(actual) if !expected.contains(&actual) => {
+                Err(format!("{} has type {}", value, actual))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn expect_register_type(
+        &self,
+        register: VirtualRegister,
+        expected: &[Type],
+    ) -> Result<(), String> {
+        self.expect_type(&Value::Register(register), expected)
+    }
+
+    fn check_instruction(&self, instruction: &Instruction) -> Result<(), String> {
+        const WORD: &[Type] = &[Type::I64, Type::Ptr];
+        match instruction {
+            Instruction::Copy(destination, value) => {
+                let destination_type = self.function.register_type(*destination);
+                self.expect_type(value, &[destination_type])
+            }
+            Instruction::Binary(op, destination, left, right) => {
+                self.expect_type(left, WORD)?;
+                self.expect_type(right, WORD)?;
+                match op {
+                    BinaryOp::Add | BinaryOp::Sub => self.expect_register_type(*destination, WORD),
+                    _ => self.expect_register_type(*destination, &[Type::I64]),
+                }
+            }
+            Instruction::Neg(destination, value) => {
+                self.expect_type(value, &[Type::I64])?;
+                self.expect_register_type(*destination, &[Type::I64])
+            }
+            Instruction::Cast(_, _) => Ok(()),
+            Instruction::Load(_, address) => self.expect_type(address, &[Type::Ptr]),
+            Instruction::Store(store_type, address, value) => {
+                self.expect_type(address, &[Type::Ptr])?;
+                self.expect_type(value, &[*store_type])
+            }
+            Instruction::SlotAddress(destination, slot) => {
+                self.check_slot(*slot)?;
+                self.expect_register_type(*destination, &[Type::Ptr])
+            }
+            Instruction::GlobalAddress { destination, .. } => {
+                self.expect_register_type(*destination, &[Type::Ptr])
+            }
+            Instruction::StringAddress(destination, index) => {
+                if *index >= self.module.string_literals.len() {
+                    return Err(format!("string literal #{} does not exist", index));
+                }
+                self.expect_register_type(*destination, &[Type::Ptr])
+            }
+            Instruction::Call {
+                result,
+                callee,
+                arguments,
+            } => {
+                if let Callee::Indirect(value) = callee {
+                    self.expect_type(value, &[Type::Ptr])?;
+                }
+                for argument in arguments.iter() {
+                    if let Argument::Aggregate(address, _) = argument {
+                        self.expect_type(address, &[Type::Ptr])?;
+                    }
+                }
+                match result {
+                    CallResult::Aggregate(address, _) => self.expect_type(address, &[Type::Ptr]),
+                    _ => Ok(()),
+                }
+            }
+            Instruction::MemCopy(destination, source, _) => {
+                self.expect_type(destination, &[Type::Ptr])?;
+                self.expect_type(source, &[Type::Ptr])
+            }
+            Instruction::MemZero(address, _) => self.expect_type(address, &[Type::Ptr]),
+            Instruction::VaStart(address) => {
+                if !self.function.variadic {
+                    return Err("va_start in a function with fixed arguments".to_string());
+                }
+                self.expect_type(address, &[Type::Ptr])
+            }
+            Instruction::VaArg(destination, address) => {
+                self.expect_register_type(*destination, WORD)?;
+                self.expect_type(address, &[Type::Ptr])
+            }
+        }
+    }
+
+    fn check_terminator(&self, terminator: &Terminator) -> Result<(), String> {
+        for successor in terminator.successors() {
+            if successor.0 >= self.function.blocks.len() {
+                return Err(format!("{} does not exist", successor));
+            }
+        }
+        match (terminator, &self.function.return_type) {
+            (Terminator::Return(Some(_)), ReturnType::Void) => {
+                Err("a value is returned from a void function".to_string())
+            }
+            (Terminator::Return(Some(value)), ReturnType::Scalar(return_type)) => {
+                self.expect_type(value, &[*return_type])
+            }
+            (Terminator::Return(Some(value)), ReturnType::Aggregate(_)) => {
+                self.expect_type(value, &[Type::Ptr])
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::I8 => write!(f, "i8"),
+            Type::I64 => write!(f, "i64"),
+            Type::Ptr => write!(f, "ptr"),
+        }
+    }
+}
+
+impl fmt::Display for ReturnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReturnType::Void => write!(f, "void"),
+            ReturnType::Scalar(scalar_type) => write!(f, "{}", scalar_type),
+            ReturnType::Aggregate(data_type) => write!(f, "{}", data_type),
+        }
+    }
+}
+
+impl fmt::Display for VirtualRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for SlotId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "${}", self.0)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Register(register) => write!(f, "{}", register),
+            Value::Constant(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "mul",
+            BinaryOp::Div => "div",
+            BinaryOp::Equal => "eq",
+            BinaryOp::NotEqual => "ne",
+            BinaryOp::Less => "lt",
+            BinaryOp::LessOrEqual => "le",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Symbol(label) => write!(f, "@{}", label),
+            Address::StringLiteral(index) => write!(f, "#{}", index),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Copy(destination, value) => write!(f, "{} = copy {}", destination, value),
+            Instruction::Binary(op, destination, left, right) => {
+                write!(f, "{} = {} {}, {}", destination, op, left, right)
+            }
+            Instruction::Neg(destination, value) => write!(f, "{} = neg {}", destination, value),
+            Instruction::Cast(destination, value) => write!(f, "{} = cast {}", destination, value),
+            Instruction::Load(destination, address) => {
+                write!(f, "{} = load {}", destination, address)
+            }
+            Instruction::Store(store_type, address, value) => {
+                write!(f, "store {} {}, {}", store_type, address, value)
+            }
+            Instruction::SlotAddress(destination, slot) => {
+                write!(f, "{} = slot {}", destination, slot)
+            }
+            Instruction::GlobalAddress {
+                destination,
+                name,
+                external,
+            } => {
+                let external = if *external { "extern " } else { "" };
+                write!(f, "{} = global {}@{}", destination, external, name)
+            }
+            Instruction::StringAddress(destination, index) => {
+                write!(f, "{} = string #{}", destination, index)
+            }
+            Instruction::Call {
+                result,
+                callee,
+                arguments,
+            } => {
+                match result {
+                    CallResult::None => write!(f, "call ")?,
+                    CallResult::Scalar(destination) => write!(f, "{} = call ", destination)?,
+                    CallResult::Aggregate(address, data_type) => {
+                        write!(f, "{} <- call {} ", address, data_type)?
+                    }
+                }
+                match callee {
+                    Callee::Direct(name) => write!(f, "@{}(", name)?,
+                    Callee::Indirect(value) => write!(f, "{}(", value)?,
+                }
+                for (index, argument) in arguments.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match argument {
+                        Argument::Scalar(value) => write!(f, "{}", value)?,
+                        Argument::Aggregate(address, data_type) => {
+                            write!(f, "{} {}", data_type, address)?
+                        }
+                    }
+                }
+                write!(f, ")")
+            }
+            Instruction::MemCopy(destination, source, size) => {
+                write!(f, "memcpy {}, {}, {}", destination, source, size)
+            }
+            Instruction::MemZero(address, size) => write!(f, "memzero {}, {}", address, size),
+            Instruction::VaStart(address) => write!(f, "va_start {}", address),
+            Instruction::VaArg(destination, address) => {
+                write!(f, "{} = va_arg {}", destination, address)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jmp {}", target),
+            Terminator::Branch(condition, then_block, else_block) => {
+                write!(f, "br {}, {}, {}", condition, then_block, else_block)
+            }
+            Terminator::Return(Some(value)) => write!(f, "ret {}", value),
+            Terminator::Return(None) => write!(f, "ret"),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let linkage = if self.is_global { "" } else { "internal " };
+        write!(f, "define {}{} @{}(", linkage, self.return_type, self.name)?;
+        for (index, parameter) in self.parameters.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            match parameter {
+                Parameter::Scalar(register) => {
+                    write!(f, "{} {}", self.register_type(*register), register)?
+                }
+                Parameter::Aggregate(slot, data_type) => write!(f, "{} {}", data_type, slot)?,
+            }
+        }
+        if self.variadic && self.parameters.is_empty() {
+            write!(f, "...")?;
+        } else if self.variadic {
+            write!(f, ", ...")?;
+        }
+        writeln!(f, ") {{")?;
+        for (index, slot) in self.slots.iter().enumerate() {
+            write!(
+                f,
+                "  {} = slot {}, align {}",
+                SlotId(index),
+                slot.size,
+                slot.align
+            )?;
+            match &slot.name {
+                Some(name) => writeln!(f, " ; {}", name)?,
+                None => writeln!(f)?,
+            }
+        }
+        // Every register is listed with its type since instructions do not repeat it
+        if !self.registers.is_empty() {
+            write!(f, "  registers")?;
+            for (index, register_type) in self.registers.iter().enumerate() {
+                let separator = if index > 0 { "," } else { "" };
+                write!(
+                    f,
+                    "{} {}: {}",
+                    separator,
+                    VirtualRegister(index),
+                    register_type
+                )?;
+            }
+            writeln!(f)?;
+        }
+        for (index, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "{}:", BlockId(index))?;
+            for instruction in block.instructions.iter() {
+                writeln!(f, "  {}", instruction)?;
+            }
+            writeln!(f, "  {}", block.terminator)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for global in self.globals.iter() {
+            let linkage = if global.is_global { "" } else { "internal " };
+            write!(
+                f,
+                "@{} = {}global {}, align {}",
+                global.label, linkage, global.size, global.align
+            )?;
+            match &global.data {
+                Some(data) => {
+                    write!(f, " [")?;
+                    for (index, byte) in data.bytes.iter().enumerate() {
+                        let separator = if index > 0 { " " } else { "" };
+                        write!(f, "{}{:02x}", separator, byte)?;
+                    }
+                    write!(f, "]")?;
+                    for (offset, address, addend) in data.relocations.iter() {
+                        write!(f, " +{}: {} + {}", offset, address, addend)?;
+                    }
+                    writeln!(f)?;
+                }
+                None => writeln!(f, " zero")?,
+            }
+        }
+        for (index, literal) in self.string_literals.iter().enumerate() {
+            writeln!(f, "#{} = string \"{}\"", index, literal.escape_ascii())?;
+        }
+        // Functions are separated from the data and from each other by blank lines
+        let has_data = !self.globals.is_empty() || !self.string_literals.is_empty();
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 || has_data {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+use crate::ast::*;
+use crate::compile_error::CompilerError;
+use std::collections::HashMap;
+
+pub struct MemberInfo {
+    pub name: String,
+    pub data_type: DataType,
+    pub offset: u32,
+}
+
+// The layout of a struct or a union
+pub struct StructInfo {
+    pub members: Vec<MemberInfo>,
+    pub size: u32,
+    pub align: u32,
+}
+
+/// The layouts of the structs and unions defined so far, which decide the sizes of types
+#[derive(Default)]
+pub struct Layouts {
+    // structs and unions share the same namespace of tags
+    structs: HashMap<String, StructInfo>,
+}
+
+impl Layouts {
+    pub fn register_struct(
+        &mut self,
+        name: &str,
+        members: &[(DataType, String)],
+        is_union: bool,
+    ) -> Result<(), CompilerError> {
+        let mut member_infos = Vec::new();
+        let mut size: u32 = 0;
+        let mut align: u32 = 1;
+        let too_large = || {
+            let data_type = if is_union {
+                DataType::Union(name.to_string())
+            } else {
+                DataType::Struct(name.to_string())
+            };
+            CompilerError::TypeTooLarge(data_type)
+        };
+        for (data_type, member_name) in members.iter() {
+            self.check_complete(data_type)?;
+            let member_align = self.align_of(data_type);
+            let offset = if is_union {
+                0
+            } else {
+                size.checked_next_multiple_of(member_align)
+                    .ok_or_else(too_large)?
+            };
+            let end = offset
+                .checked_add(self.size_of(data_type))
+                .ok_or_else(too_large)?;
+            size = size.max(end);
+            align = align.max(member_align);
+            member_infos.push(MemberInfo {
+                name: member_name.clone(),
+                data_type: data_type.clone(),
+                offset,
+            });
+        }
+        self.structs.insert(
+            name.to_string(),
+            StructInfo {
+                members: member_infos,
+                size: size.checked_next_multiple_of(align).ok_or_else(too_large)?,
+                align,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn get_struct(&self, name: &str) -> Option<&StructInfo> {
+        self.structs.get(name)
+    }
+
+    pub fn get_member(
+        &self,
+        data_type: &DataType,
+        member: &str,
+    ) -> Result<&MemberInfo, CompilerError> {
+        let struct_info = match data_type {
+            DataType::Struct(name) | DataType::Union(name) => self.get_struct(name),
+            _ => return Err(CompilerError::NotAStruct(data_type.clone())),
+        };
+        match struct_info.and_then(|s| s.members.iter().find(|m| m.name == member)) {
+            Some(member_info) => Ok(member_info),
+            None => Err(CompilerError::NoMember(
+                data_type.clone(),
+                member.to_string(),
+            )),
+        }
+    }
+
+    // Variables and members cannot have void, an array type without a length,
+    // or a struct or union type which is not defined yet
+    pub fn check_complete(&self, data_type: &DataType) -> Result<(), CompilerError> {
+        match data_type {
+            DataType::Struct(name) | DataType::Union(name) if self.get_struct(name).is_none() => {
+                Err(CompilerError::IncompleteType(data_type.clone()))
+            }
+            DataType::Array(_, None) | DataType::Primitive(PrimitiveType::Void) => {
+                Err(CompilerError::IncompleteType(data_type.clone()))
+            }
+            DataType::Array(element_type, Some(_)) => {
+                self.check_complete(element_type)?;
+                match self.checked_size_of(data_type) {
+                    Some(_) => Ok(()),
+                    None => Err(CompilerError::TypeTooLarge(data_type.clone())),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The number of bytes a value of this type occupies in memory.
+    /// Note that `int` is 64 bits wide in this compiler.
+    pub fn size_of(&self, data_type: &DataType) -> u32 {
+        match data_type {
+            DataType::Primitive(PrimitiveType::Int) => 8,
+            DataType::Primitive(PrimitiveType::Char) => 1,
+            DataType::Primitive(PrimitiveType::Void) => 1,
+            DataType::Primitive(PrimitiveType::VaList) => 24,
+            DataType::Pointer(_, _) => 8,
+            DataType::Function(_, _, _) => 1,
+            DataType::Struct(name) | DataType::Union(name) => {
+                self.get_struct(name).map_or(0, |s| s.size)
+            }
+            DataType::Array(_, Some(_)) => self
+                .checked_size_of(data_type)
+                .expect("the sizes of arrays are checked with their completeness"),
+            DataType::Array(_, None) => 0,
+        }
+    }
+
+    /// The size of a type, or None if it does not fit in 32 bits
+    fn checked_size_of(&self, data_type: &DataType) -> Option<u32> {
+        match data_type {
+            DataType::Array(element_type, Some(length)) => {
+                self.checked_size_of(element_type)?.checked_mul(*length)
+            }
+            _ => Some(self.size_of(data_type)),
+        }
+    }
+
+    pub fn align_of(&self, data_type: &DataType) -> u32 {
+        match data_type {
+            DataType::Primitive(PrimitiveType::VaList) => 8,
+            DataType::Struct(name) | DataType::Union(name) => {
+                self.get_struct(name).map_or(1, |s| s.align)
+            }
+            DataType::Array(element_type, _) => self.align_of(element_type),
+            _ => self.size_of(data_type),
+        }
+    }
+}
@@ -0,0 +1,260 @@
+#[macro_use]
+extern crate lalrpop_util;
+
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    #[allow(unused)]
+    pub parser
+);
+pub mod abi;
+pub mod assembly;
+pub mod ast;
+pub mod cfg;
+pub mod compile_error;
+pub mod desugar;
+pub mod diagnostic;
+pub mod diagnostic_format;
+pub mod dump;
+pub mod gen_code;
+pub mod initializer;
+pub mod ir;
+pub mod layout;
+pub mod liveness;
+pub mod lower;
+pub mod optimize;
+pub mod preprocessor;
+pub mod preprocessor_expression;
+pub mod regalloc;
+pub mod sema;
+pub mod translation_phase;
+pub mod typed_ast;
+pub mod unparse;
+pub mod visit;
+pub mod warning;
+use crate::assembly::{write_assembly_code, Assembly};
+use crate::ast::Span;
+use crate::compile_error::CompilerError;
+use crate::desugar::desugar;
+use crate::diagnostic::{Diagnostic, Label, Severity};
+use crate::dump::{dump_ast, dump_cfg_dot, dump_tokens, dump_typed_program};
+use crate::gen_code::get_assembly;
+use crate::ir::{verify, VerifyError};
+use crate::lower::lower;
+use crate::optimize::optimize;
+use crate::preprocessor::{PreprocessedSource, Preprocessor};
+use crate::sema::analyze;
+use crate::warning::{Warning, WarningOptions};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// What a compilation produces, given by `--emit`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Emit {
+    // The tokens after preprocessing
+    Tokens,
+    Ast,
+    // The AST after semantic analysis, with resolved names, types and implicit conversions
+    TypedAst,
+    // The AST printed back as C source, which serves as a formatter
+    C,
+    // The three-address code lowered from the typed AST
+    Ir,
+    // The control flow graph of each function of the IR in the Graphviz format
+    CfgDot,
+    #[default]
+    Asm,
+}
+
+impl Emit {
+    pub fn from_name(name: &str) -> Option<Emit> {
+        match name {
+            "tokens" => Some(Emit::Tokens),
+            "ast" => Some(Emit::Ast),
+            "typed-ast" => Some(Emit::TypedAst),
+            "c" => Some(Emit::C),
+            "ir" => Some(Emit::Ir),
+            "cfg-dot" => Some(Emit::CfgDot),
+            "asm" => Some(Emit::Asm),
+            _ => None,
+        }
+    }
+}
+
+/// How much the IR is optimized, given by `-O`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum OptimizationLevel {
+    // -O0, where the IR is compiled as it is lowered
+    None,
+    // Constant folding and propagation, which is done with any other level
+    #[default]
+    Basic,
+}
+
+/// How a source is compiled
+#[derive(Clone, Default)]
+pub struct Options {
+    // Include paths and macros given on the command line
+    pub preprocessor: Preprocessor,
+    pub warning_options: WarningOptions,
+    // The name of the source shown in diagnostics
+    pub file_name: String,
+    // The path of the source, which `#include "..."` is resolved relative to
+    pub path: Option<PathBuf>,
+    // The stage where the compilation stops and what it produces
+    pub emit: Emit,
+    pub optimization_level: OptimizationLevel,
+}
+
+pub enum Output {
+    Assembly(Assembly),
+    // The textual dump of an intermediate result, given by `Options::emit`
+    Dump(String),
+}
+
+/// The result of a successful compilation
+pub struct CompiledOutput {
+    pub output: Output,
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl CompiledOutput {
+    /// Write the assembly in the syntax of the GNU assembler, or the dump
+    pub fn emit(&self, out: &mut impl io::Write) -> io::Result<()> {
+        match &self.output {
+            Output::Assembly(assembly) => write_assembly_code(out, assembly),
+            Output::Dump(dump) => out.write_all(dump.as_bytes()),
+        }
+    }
+}
+
+// Invalid IR is a bug of the compiler, which is reported as an error instead of a panic
+fn internal_error(error: VerifyError) -> Vec<CompilerError> {
+    vec![CompilerError::InternalError(error.to_string())]
+}
+
+// `#warning` is reported with its line in the original file, since the directive is not in the
+// preprocessed text. The offset where it appears decides its level under the pragmas.
+fn warning_directives(
+    preprocessed: &PreprocessedSource,
+    warning_options: &WarningOptions,
+) -> Vec<Diagnostic> {
+    let mut warnings = Vec::new();
+    for (offset, location, message) in preprocessed.warning_directives.iter() {
+        let offset = Span {
+            start: *offset,
+            end: *offset,
+        };
+        if let Some(mut diagnostic) =
+            warning_options.diagnostic(Warning::Cpp, offset, message.clone())
+        {
+            let (source, span) = location.to_source();
+            diagnostic.primary = Some(Label {
+                span,
+                message: String::new(),
+            });
+            diagnostic.source = Some(Arc::new(source));
+            warnings.push(diagnostic);
+        }
+    }
+    warnings
+}
+
+/// Compile a C source to assembly, or up to the stage given by `options.emit`.
+/// On failure, all the diagnostics are returned, with the warnings followed by the errors.
+pub fn compile(source: &str, options: &Options) -> Result<CompiledOutput, Vec<Diagnostic>> {
+    let preprocessed = options
+        .preprocessor
+        .clone()
+        .preprocess(source, &options.file_name, options.path.as_deref())
+        .map_err(|e| vec![e.to_diagnostic()])?;
+    let mut warning_options = options.warning_options.clone();
+    warning_options.add_pragmas(&preprocessed.diagnostic_pragmas);
+    let mut warnings = warning_directives(&preprocessed, &warning_options);
+    let has_warning_errors = |warnings: &[Diagnostic]| {
+        warnings
+            .iter()
+            .any(|warning| warning.severity == Severity::Error)
+    };
+    if options.emit == Emit::Tokens {
+        if has_warning_errors(&warnings) {
+            return Err(warnings);
+        }
+        return Ok(CompiledOutput {
+            output: Output::Dump(dump_tokens(&preprocessed)),
+            warnings,
+        });
+    }
+    // Syntax errors are recovered from to report all of them. The recovered tree is analyzed
+    // to report the other errors as well, but the program is compiled only if there are none.
+    let mut recovered_errors = Vec::new();
+    let result = parser::ProgramParser::new().parse(&mut recovered_errors, &preprocessed.text);
+    let mut errors: Vec<Diagnostic> = recovered_errors
+        .iter()
+        .map(|recovery| Diagnostic::from_parse_error(&recovery.error))
+        .collect();
+    let output = match result {
+        Ok(parse_tree) if errors.is_empty() => {
+            let output = match options.emit {
+                Emit::Ast => Ok(Output::Dump(dump_ast(&parse_tree))),
+                Emit::C => Ok(Output::Dump(parse_tree.to_string())),
+                _ => analyze(&desugar(parse_tree), warning_options, &mut warnings).and_then(
+                    |typed_program| match options.emit {
+                        Emit::TypedAst => Ok(Output::Dump(dump_typed_program(&typed_program))),
+                        _ => {
+                            let mut module = lower(&typed_program);
+                            verify(&module).map_err(internal_error)?;
+                            if options.optimization_level == OptimizationLevel::Basic {
+                                optimize(&mut module);
+                                verify(&module).map_err(internal_error)?;
+                            }
+                            Ok(match options.emit {
+                                Emit::Ir => Output::Dump(module.to_string()),
+                                Emit::CfgDot => Output::Dump(dump_cfg_dot(&module)),
+                                _ => {
+                                    Output::Assembly(get_assembly(&module, &typed_program.layouts))
+                                }
+                            })
+                        }
+                    },
+                ),
+            };
+            match output {
+                Ok(output) => Some(output),
+                Err(compile_errors) => {
+                    errors.extend(compile_errors.iter().map(|e| e.to_diagnostic()));
+                    None
+                }
+            }
+        }
+        // Warnings are dropped, since the placeholders of the statements with errors
+        // make them unreliable
+        Ok(parse_tree) => {
+            if !matches!(options.emit, Emit::Ast | Emit::C) {
+                let program = desugar(parse_tree);
+                if let Err(compile_errors) = analyze(&program, warning_options, &mut Vec::new()) {
+                    errors.extend(compile_errors.iter().map(|e| e.to_diagnostic()));
+                }
+            }
+            None
+        }
+        Err(e) => {
+            errors.push(Diagnostic::from_parse_error(&e));
+            None
+        }
+    };
+    // The diagnostics share the source to be rendered with its lines
+    let preprocessed = Arc::new(preprocessed);
+    for diagnostic in warnings.iter_mut().chain(errors.iter_mut()) {
+        diagnostic
+            .source
+            .get_or_insert_with(|| preprocessed.clone());
+    }
+    match output {
+        Some(output) if !has_warning_errors(&warnings) => Ok(CompiledOutput { output, warnings }),
+        _ => {
+            warnings.append(&mut errors);
+            Err(warnings)
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::cfg::ControlFlowGraph;
+use crate::ir::*;
+use std::collections::HashSet;
+
+// A virtual register is live at a point if its value there may be read later.
+// The sets are computed by the backward dataflow analysis iterated to a fixed point.
+pub struct Liveness {
+    // Indexed by `BlockId`
+    pub live_in: Vec<HashSet<VirtualRegister>>,
+    pub live_out: Vec<HashSet<VirtualRegister>>,
+}
+
+impl Liveness {
+    pub fn new(function: &Function, cfg: &ControlFlowGraph) -> Liveness {
+        // The registers each block reads before assigning them, and the ones it assigns
+        let mut uses = Vec::new();
+        let mut definitions = Vec::new();
+        for block in function.blocks.iter() {
+            let mut used = HashSet::new();
+            let mut defined = HashSet::new();
+            let operands = block
+                .instructions
+                .iter()
+                .map(|instruction| (instruction.operands(), instruction.destination()))
+                .chain([(block.terminator.operands(), None)]);
+            for (operands, destination) in operands {
+                for operand in operands {
+                    if let Value::Register(register) = operand {
+                        if !defined.contains(&register) {
+                            used.insert(register);
+                        }
+                    }
+                }
+                if let Some(destination) = destination {
+                    defined.insert(destination);
+                }
+            }
+            uses.push(used);
+            definitions.push(defined);
+        }
+
+        let count = function.blocks.len();
+        let mut live_in: Vec<HashSet<VirtualRegister>> = vec![HashSet::new(); count];
+        let mut live_out: Vec<HashSet<VirtualRegister>> = vec![HashSet::new(); count];
+        // Visiting blocks in postorder propagates liveness against the edges quickly
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in cfg.reverse_postorder.iter().rev() {
+                let out: HashSet<VirtualRegister> = cfg.successors[block.0]
+                    .iter()
+                    .flat_map(|successor| live_in[successor.0].iter().copied())
+                    .collect();
+                let mut new_in = uses[block.0].clone();
+                new_in.extend(out.difference(&definitions[block.0]));
+                live_out[block.0] = out;
+                if new_in != live_in[block.0] {
+                    live_in[block.0] = new_in;
+                    changed = true;
+                }
+            }
+        }
+        Liveness { live_in, live_out }
+    }
+}
@@ -0,0 +1,1030 @@
+use crate::ast::*;
+use crate::initializer::*;
+use crate::ir::*;
+use crate::layout::Layouts;
+use crate::typed_ast::*;
+use std::collections::{HashMap, HashSet};
+
+// Where a local variable or a parameter lives
+#[derive(Clone, Copy)]
+enum Location {
+    Register(VirtualRegister),
+    Slot(SlotId),
+}
+
+struct Lowering<'a> {
+    symbols: &'a [Symbol],
+    layouts: &'a Layouts,
+    // Variables whose addresses are taken cannot live in registers
+    address_taken: HashSet<SymbolId>,
+    globals: Vec<Global>,
+    string_literals: Vec<Vec<u8>>,
+    // The mangled labels of static local variables
+    static_labels: HashMap<SymbolId, String>,
+    static_count: u64,
+}
+
+// A function being lowered, whose blocks are terminated as the control leaves them
+struct FunctionBuilder {
+    registers: Vec<Type>,
+    slots: Vec<StackSlot>,
+    blocks: Vec<(Vec<Instruction>, Option<Terminator>)>,
+    current: BlockId,
+    return_type: ReturnType,
+    locations: HashMap<SymbolId, Location>,
+    break_targets: Vec<BlockId>,
+}
+
+/// Lower the typed program produced by semantic analysis to the IR
+pub fn lower(program: &TypedProgram) -> Module {
+    let mut lowering = Lowering {
+        symbols: &program.symbols,
+        layouts: &program.layouts,
+        address_taken: HashSet::new(),
+        globals: Vec::new(),
+        string_literals: Vec::new(),
+        static_labels: HashMap::new(),
+        static_count: 0,
+    };
+    for unit in program.units.iter() {
+        match unit {
+            TypedUnit::Function(function) => {
+                collect_address_taken_statement(&function.body, &mut lowering.address_taken)
+            }
+            TypedUnit::Statement(statement) => {
+                collect_address_taken_statement(statement, &mut lowering.address_taken)
+            }
+        }
+    }
+
+    // Top level statements form main, which also initializes global variables
+    // whose initializers are not constant expressions
+    let mut main = FunctionBuilder::new(ReturnType::Scalar(Type::I64));
+    let mut has_main_code = false;
+    let mut last_value = None;
+    let mut functions = Vec::new();
+    for unit in program.units.iter() {
+        match unit {
+            TypedUnit::Function(function) => functions.push(lowering.function(function)),
+            TypedUnit::Statement(TypedStatement {
+                kind: TypedStatementKind::VarDef(symbol, initializer),
+                ..
+            }) => {
+                has_main_code |= lowering.global_var_def(*symbol, initializer, &mut main);
+            }
+            TypedUnit::Statement(statement) => {
+                has_main_code = true;
+                last_value = lowering.statement(statement, &mut main);
+            }
+        }
+    }
+    // A file without top level statements, such as one linked with other files,
+    // has no main of its own
+    if has_main_code {
+        let value = last_value.unwrap_or(Value::Constant(0));
+        let value = main.coerce(value, Type::I64);
+        main.terminate(Terminator::Return(Some(value)));
+        functions.insert(0, main.finish("main".to_string(), true, Vec::new(), false));
+    }
+    Module {
+        functions,
+        globals: lowering.globals,
+        string_literals: lowering.string_literals,
+    }
+}
+
+// The type of a register holding a value of the type.
+// Functions, structs, unions and arrays are represented by their addresses.
+fn register_type(data_type: &DataType) -> Type {
+    match data_type {
+        DataType::Primitive(PrimitiveType::Char) => Type::I8,
+        DataType::Primitive(PrimitiveType::Int) => Type::I64,
+        _ => Type::Ptr,
+    }
+}
+
+fn is_scalar(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Primitive(PrimitiveType::Int | PrimitiveType::Char) | DataType::Pointer(_, _)
+    )
+}
+
+fn is_represented_by_address(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Struct(_)
+            | DataType::Union(_)
+            | DataType::Array(_, _)
+            | DataType::Primitive(PrimitiveType::VaList)
+            | DataType::Function(_, _, _)
+    )
+}
+
+fn collect_address_taken_statement(statement: &TypedStatement, result: &mut HashSet<SymbolId>) {
+    match &statement.kind {
+        TypedStatementKind::Expr(value) | TypedStatementKind::Return(value) => {
+            collect_address_taken_expr(value, result)
+        }
+        TypedStatementKind::Assign(lvalue, value) => {
+            collect_address_taken_expr(lvalue, result);
+            collect_address_taken_expr(value, result);
+        }
+        TypedStatementKind::If(condition, if_statement, else_statement) => {
+            collect_address_taken_expr(condition, result);
+            collect_address_taken_statement(if_statement, result);
+            if let Some(else_statement) = else_statement {
+                collect_address_taken_statement(else_statement, result);
+            }
+        }
+        TypedStatementKind::Block(statements) => {
+            for statement in statements.iter() {
+                collect_address_taken_statement(statement, result);
+            }
+        }
+        TypedStatementKind::While(condition, body) => {
+            collect_address_taken_expr(condition, result);
+            collect_address_taken_statement(body, result);
+        }
+        TypedStatementKind::For(init, condition, step, body) => {
+            if let Some(condition) = condition {
+                collect_address_taken_expr(condition, result);
+            }
+            for statement in [init, step].into_iter().flatten() {
+                collect_address_taken_statement(statement, result);
+            }
+            collect_address_taken_statement(body, result);
+        }
+        TypedStatementKind::Break => (),
+        TypedStatementKind::VarDef(_, initializer) => {
+            for item in initializer.iter().flat_map(|i| i.items.iter()) {
+                if let InitValue::Expr(value) = &item.value {
+                    collect_address_taken_expr(value, result);
+                }
+            }
+        }
+    }
+}
+
+fn collect_address_taken_expr(expr: &TypedExpr, result: &mut HashSet<SymbolId>) {
+    match &expr.kind {
+        TypedExprKind::Number(_) | TypedExprKind::StringLiteral(_) => (),
+        // A variable read by its value does not need its address
+        TypedExprKind::Variable(_) => (),
+        TypedExprKind::AddressOf(operand) => collect_address_taken_lvalue(operand, result),
+        TypedExprKind::Binary(_, left, right)
+        | TypedExprKind::Index(left, right)
+        | TypedExprKind::VaCopy(left, right) => {
+            collect_address_taken_expr(left, result);
+            collect_address_taken_expr(right, result);
+        }
+        TypedExprKind::Neg(operand)
+        | TypedExprKind::Deref(operand)
+        | TypedExprKind::Member(operand, _, _)
+        | TypedExprKind::PointerMember(operand, _, _)
+        | TypedExprKind::VaStart(operand)
+        | TypedExprKind::VaArg(operand)
+        | TypedExprKind::VaEnd(operand)
+        | TypedExprKind::Convert(_, operand) => collect_address_taken_expr(operand, result),
+        TypedExprKind::Call(callee, arguments) => {
+            collect_address_taken_expr(callee, result);
+            for argument in arguments.iter() {
+                collect_address_taken_expr(argument, result);
+            }
+        }
+    }
+}
+
+// The variable whose member is designated by the operand of `&` needs an address as well
+fn collect_address_taken_lvalue(lvalue: &TypedExpr, result: &mut HashSet<SymbolId>) {
+    match &lvalue.kind {
+        TypedExprKind::Variable(symbol) => {
+            result.insert(*symbol);
+        }
+        TypedExprKind::Member(operand, _, _) => collect_address_taken_lvalue(operand, result),
+        _ => collect_address_taken_expr(lvalue, result),
+    }
+}
+
+impl FunctionBuilder {
+    fn new(return_type: ReturnType) -> FunctionBuilder {
+        FunctionBuilder {
+            registers: Vec::new(),
+            slots: Vec::new(),
+            blocks: vec![(Vec::new(), None)],
+            current: BlockId(0),
+            return_type,
+            locations: HashMap::new(),
+            break_targets: Vec::new(),
+        }
+    }
+
+    fn new_register(&mut self, register_type: Type) -> VirtualRegister {
+        self.registers.push(register_type);
+        VirtualRegister(self.registers.len() - 1)
+    }
+
+    fn new_slot(&mut self, size: u32, align: u32, name: Option<String>) -> SlotId {
+        self.slots.push(StackSlot { size, align, name });
+        SlotId(self.slots.len() - 1)
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push((Vec::new(), None));
+        BlockId(self.blocks.len() - 1)
+    }
+
+    fn switch_to(&mut self, block: BlockId) {
+        self.current = block;
+    }
+
+    fn push(&mut self, instruction: Instruction) {
+        self.blocks[self.current.0].0.push(instruction);
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.blocks[self.current.0].1 = Some(terminator);
+    }
+
+    // The code following a jump or a return goes to a new block,
+    // which is unreachable unless it is jumped to
+    fn terminate_and_continue(&mut self, terminator: Terminator) {
+        self.terminate(terminator);
+        let next = self.new_block();
+        self.switch_to(next);
+    }
+
+    // Compute a value into a new register of the type
+    fn compute(
+        &mut self,
+        register_type: Type,
+        instruction: impl FnOnce(VirtualRegister) -> Instruction,
+    ) -> Value {
+        let register = self.new_register(register_type);
+        self.push(instruction(register));
+        Value::Register(register)
+    }
+
+    fn value_type(&self, value: Value) -> Option<Type> {
+        match value {
+            Value::Register(register) => Some(self.registers[register.0]),
+            Value::Constant(_) => None,
+        }
+    }
+
+    // Convert a value to the type if it has another one.
+    // A constant converted to a char is truncated.
+    fn coerce(&mut self, value: Value, target_type: Type) -> Value {
+        match value {
+            Value::Constant(n) if target_type == Type::I8 => Value::Constant(n as i8 as i64),
+            Value::Register(_) if self.value_type(value) != Some(target_type) => {
+                self.compute(target_type, |register| Instruction::Cast(register, value))
+            }
+            _ => value,
+        }
+    }
+
+    // The address plus a constant offset in bytes
+    fn offset_address(&mut self, address: Value, offset: u32) -> Value {
+        if offset == 0 {
+            return address;
+        }
+        self.compute(Type::Ptr, |register| {
+            Instruction::Binary(
+                BinaryOp::Add,
+                register,
+                address,
+                Value::Constant(offset as i64),
+            )
+        })
+    }
+
+    fn finish(
+        self,
+        name: String,
+        is_global: bool,
+        parameters: Vec<Parameter>,
+        variadic: bool,
+    ) -> Function {
+        let blocks = self
+            .blocks
+            .into_iter()
+            .map(|(instructions, terminator)| Block {
+                instructions,
+                terminator: terminator.expect("every block is terminated"),
+            })
+            .collect();
+        let mut function = Function {
+            name,
+            is_global,
+            parameters,
+            variadic,
+            return_type: self.return_type,
+            registers: self.registers,
+            slots: self.slots,
+            blocks,
+        };
+        function.remove_unreachable_blocks();
+        function
+    }
+}
+
+impl Lowering<'_> {
+    fn size_of(&self, data_type: &DataType) -> u32 {
+        self.layouts.size_of(data_type)
+    }
+
+    fn return_type(&self, data_type: &DataType) -> ReturnType {
+        match data_type {
+            DataType::Primitive(PrimitiveType::Void) => ReturnType::Void,
+            data_type if is_scalar(data_type) => ReturnType::Scalar(register_type(data_type)),
+            data_type => ReturnType::Aggregate(data_type.clone()),
+        }
+    }
+
+    fn register_string_literal(&mut self, bytes: &[u8]) -> usize {
+        match self.string_literals.iter().position(|s| s == bytes) {
+            Some(index) => index,
+            None => {
+                self.string_literals.push(bytes.to_vec());
+                self.string_literals.len() - 1
+            }
+        }
+    }
+
+    // The label of a variable with static storage duration or a function
+    fn label(&self, symbol: SymbolId) -> String {
+        match self.static_labels.get(&symbol) {
+            Some(label) => label.clone(),
+            None => self.symbols[symbol.0].name.clone(),
+        }
+    }
+
+    fn data(&mut self, image: Option<DataImage>) -> Option<Data> {
+        let image = image?;
+        let relocations = image
+            .relocations
+            .into_iter()
+            .map(|(offset, base, addend)| {
+                let address = match base {
+                    AddressBase::Symbol(symbol) => Address::Symbol(self.label(symbol)),
+                    AddressBase::StringLiteral(bytes) => {
+                        Address::StringLiteral(self.register_string_literal(&bytes))
+                    }
+                };
+                (offset, address, addend)
+            })
+            .collect();
+        Some(Data {
+            bytes: image.bytes,
+            relocations,
+        })
+    }
+
+    fn add_global(
+        &mut self,
+        symbol: SymbolId,
+        label: String,
+        image: Option<DataImage>,
+        is_global: bool,
+    ) {
+        let data_type = &self.symbols[symbol.0].data_type;
+        let (size, align) = (self.size_of(data_type), self.layouts.align_of(data_type));
+        let data = self.data(image);
+        // A tentative definition is replaced by the following definition
+        self.globals.retain(|global| global.label != label);
+        self.globals.push(Global {
+            label,
+            size,
+            align,
+            data,
+            is_global,
+        });
+    }
+
+    // Define a global variable. If the initializer is not a constant expression,
+    // the variable is initialized at that point of main, and true is returned.
+    fn global_var_def(
+        &mut self,
+        symbol: SymbolId,
+        initializer: &Option<TypedInitializer>,
+        main: &mut FunctionBuilder,
+    ) -> bool {
+        let symbols = self.symbols;
+        let symbol_info = &symbols[symbol.0];
+        let is_static = match symbol_info.kind {
+            SymbolKind::Global { is_static } => is_static,
+            // A declaration with extern only introduces the name
+            _ => return false,
+        };
+        let image = initializer.as_ref().and_then(|i| i.image.clone());
+        let is_constant = image.is_some() || initializer.is_none();
+        self.add_global(symbol, symbol_info.name.clone(), image, !is_static);
+        match initializer {
+            Some(initializer) if !is_constant => {
+                let address = self.symbol_address(symbol, main);
+                self.initialize(address, &symbol_info.data_type, initializer, main);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn function(&mut self, function: &TypedFunction) -> Function {
+        let symbols = self.symbols;
+        let symbol_info = &symbols[function.symbol.0];
+        let DataType::Function(return_type, _, variadic) = &symbol_info.data_type else {
+            unreachable!("the symbol of a function has a function type")
+        };
+        let mut builder = FunctionBuilder::new(self.return_type(return_type));
+        let mut parameters = Vec::new();
+        for parameter in function.parameters.iter() {
+            let data_type = &symbols[parameter.0].data_type;
+            if is_scalar(data_type) {
+                let register = builder.new_register(register_type(data_type));
+                parameters.push(Parameter::Scalar(register));
+                // A parameter whose address is taken is stored to a slot on entry
+                if self.address_taken.contains(parameter) {
+                    let slot = self.new_variable_slot(*parameter, &mut builder);
+                    builder.locations.insert(*parameter, Location::Slot(slot));
+                    self.assign_symbol(
+                        *parameter,
+                        data_type,
+                        Value::Register(register),
+                        &mut builder,
+                    );
+                } else {
+                    builder
+                        .locations
+                        .insert(*parameter, Location::Register(register));
+                }
+            } else {
+                let slot = self.new_variable_slot(*parameter, &mut builder);
+                builder.locations.insert(*parameter, Location::Slot(slot));
+                parameters.push(Parameter::Aggregate(slot, data_type.clone()));
+            }
+        }
+        let value = self.statement(&function.body, &mut builder);
+        // The value of the last statement is returned if the control reaches the end
+        let value = match builder.return_type {
+            ReturnType::Scalar(return_type) => {
+                let value = value.unwrap_or(Value::Constant(0));
+                Some(builder.coerce(value, return_type))
+            }
+            ReturnType::Void | ReturnType::Aggregate(_) => None,
+        };
+        builder.terminate(Terminator::Return(value));
+        let is_global = function.storage_class != Some(StorageClass::Static);
+        builder.finish(symbol_info.name.clone(), is_global, parameters, *variadic)
+    }
+
+    fn new_variable_slot(&self, symbol: SymbolId, builder: &mut FunctionBuilder) -> SlotId {
+        let symbol_info = &self.symbols[symbol.0];
+        builder.new_slot(
+            self.size_of(&symbol_info.data_type),
+            self.layouts.align_of(&symbol_info.data_type),
+            Some(symbol_info.name.clone()),
+        )
+    }
+
+    // Decide where a local variable lives
+    fn define_local(&self, symbol: SymbolId, builder: &mut FunctionBuilder) {
+        let data_type = &self.symbols[symbol.0].data_type;
+        let location = if is_scalar(data_type) && !self.address_taken.contains(&symbol) {
+            Location::Register(builder.new_register(register_type(data_type)))
+        } else {
+            Location::Slot(self.new_variable_slot(symbol, builder))
+        };
+        builder.locations.insert(symbol, location);
+    }
+
+    // Zero-fill an object and store the values of its initializer
+    fn initialize(
+        &mut self,
+        address: Value,
+        data_type: &DataType,
+        initializer: &TypedInitializer,
+        builder: &mut FunctionBuilder,
+    ) {
+        builder.push(Instruction::MemZero(address, self.size_of(data_type)));
+        for item in initializer.items.iter() {
+            let value = self.init_value(&item.value, builder);
+            let item_address = builder.offset_address(address, item.offset);
+            self.store(item_address, &item.data_type, value, builder);
+        }
+    }
+
+    fn init_value(&mut self, value: &InitValue<TypedExpr>, builder: &mut FunctionBuilder) -> Value {
+        match value {
+            InitValue::Expr(expr) => self.expr(expr, builder),
+            InitValue::Byte(byte) => Value::Constant(*byte as i8 as i64),
+        }
+    }
+
+    // Store a value of the type to the address, or copy an aggregate value from its address
+    fn store(
+        &self,
+        address: Value,
+        data_type: &DataType,
+        value: Value,
+        builder: &mut FunctionBuilder,
+    ) {
+        if is_represented_by_address(data_type) {
+            let value = builder.coerce(value, Type::Ptr);
+            builder.push(Instruction::MemCopy(
+                address,
+                value,
+                self.size_of(data_type),
+            ));
+        } else {
+            let store_type = register_type(data_type);
+            let value = builder.coerce(value, store_type);
+            builder.push(Instruction::Store(store_type, address, value));
+        }
+    }
+
+    // Assign a value to a variable, which lives in a register or in memory
+    fn assign_symbol(
+        &mut self,
+        symbol: SymbolId,
+        data_type: &DataType,
+        value: Value,
+        builder: &mut FunctionBuilder,
+    ) {
+        match builder.locations.get(&symbol) {
+            Some(Location::Register(register)) => {
+                let register = *register;
+                let value = builder.coerce(value, builder.registers[register.0]);
+                builder.push(Instruction::Copy(register, value));
+            }
+            _ => {
+                let address = self.symbol_address(symbol, builder);
+                self.store(address, data_type, value, builder);
+            }
+        }
+    }
+
+    // Returns the value of the statement, which main returns if it is the last one
+    fn statement(
+        &mut self,
+        statement: &TypedStatement,
+        builder: &mut FunctionBuilder,
+    ) -> Option<Value> {
+        match &statement.kind {
+            TypedStatementKind::Expr(expr) => Some(self.expr(expr, builder)),
+            // The address of the lvalue is computed before the value
+            TypedStatementKind::Assign(lvalue, value) => match &lvalue.kind {
+                TypedExprKind::Variable(symbol) => {
+                    let value = self.expr(value, builder);
+                    self.assign_symbol(*symbol, &lvalue.data_type, value, builder);
+                    Some(value)
+                }
+                _ => {
+                    let address = self.address(lvalue, builder);
+                    let value = self.expr(value, builder);
+                    self.store(address, &lvalue.data_type, value, builder);
+                    Some(value)
+                }
+            },
+            TypedStatementKind::Return(expr) => {
+                let value = self.expr(expr, builder);
+                let value = match builder.return_type {
+                    ReturnType::Void => None,
+                    ReturnType::Scalar(return_type) => Some(builder.coerce(value, return_type)),
+                    ReturnType::Aggregate(_) => Some(value),
+                };
+                builder.terminate_and_continue(Terminator::Return(value));
+                None
+            }
+            TypedStatementKind::Block(statements) => {
+                let mut value = None;
+                for statement in statements.iter() {
+                    value = self.statement(statement, builder);
+                }
+                value
+            }
+            TypedStatementKind::If(condition, if_statement, else_statement) => {
+                let condition = self.expr(condition, builder);
+                let then_block = builder.new_block();
+                let end_block = builder.new_block();
+                let else_block = match else_statement {
+                    Some(_) => builder.new_block(),
+                    None => end_block,
+                };
+                builder.terminate(Terminator::Branch(condition, then_block, else_block));
+                builder.switch_to(then_block);
+                self.statement(if_statement, builder);
+                builder.terminate(Terminator::Jump(end_block));
+                if let Some(else_statement) = else_statement {
+                    builder.switch_to(else_block);
+                    self.statement(else_statement, builder);
+                    builder.terminate(Terminator::Jump(end_block));
+                }
+                builder.switch_to(end_block);
+                None
+            }
+            TypedStatementKind::While(condition, body) => {
+                let condition_block = builder.new_block();
+                let body_block = builder.new_block();
+                let end_block = builder.new_block();
+                builder.terminate(Terminator::Jump(condition_block));
+                builder.switch_to(condition_block);
+                let condition = self.expr(condition, builder);
+                builder.terminate(Terminator::Branch(condition, body_block, end_block));
+                builder.switch_to(body_block);
+                builder.break_targets.push(end_block);
+                self.statement(body, builder);
+                builder.break_targets.pop();
+                builder.terminate(Terminator::Jump(condition_block));
+                builder.switch_to(end_block);
+                None
+            }
+            TypedStatementKind::For(init, condition, step, body) => {
+                if let Some(init) = init {
+                    self.statement(init, builder);
+                }
+                let condition_block = builder.new_block();
+                let body_block = builder.new_block();
+                let step_block = builder.new_block();
+                let end_block = builder.new_block();
+                builder.terminate(Terminator::Jump(condition_block));
+                builder.switch_to(condition_block);
+                match condition {
+                    Some(condition) => {
+                        let condition = self.expr(condition, builder);
+                        builder.terminate(Terminator::Branch(condition, body_block, end_block));
+                    }
+                    None => builder.terminate(Terminator::Jump(body_block)),
+                }
+                builder.switch_to(body_block);
+                builder.break_targets.push(end_block);
+                self.statement(body, builder);
+                builder.break_targets.pop();
+                builder.terminate(Terminator::Jump(step_block));
+                builder.switch_to(step_block);
+                if let Some(step) = step {
+                    self.statement(step, builder);
+                }
+                builder.terminate(Terminator::Jump(condition_block));
+                builder.switch_to(end_block);
+                None
+            }
+            TypedStatementKind::Break => {
+                let target = *builder
+                    .break_targets
+                    .last()
+                    .expect("break is checked to be in a loop");
+                builder.terminate_and_continue(Terminator::Jump(target));
+                None
+            }
+            TypedStatementKind::VarDef(symbol, initializer) => {
+                let symbols = self.symbols;
+                let symbol_info = &symbols[symbol.0];
+                match symbol_info.kind {
+                    SymbolKind::Local => {
+                        self.define_local(*symbol, builder);
+                        if let Some(initializer) = initializer {
+                            self.initialize_local(*symbol, initializer, builder);
+                        }
+                    }
+                    // A static local variable is initialized once before the program starts
+                    SymbolKind::StaticLocal => {
+                        self.static_count += 1;
+                        let label = format!("{}.{}", symbol_info.name, self.static_count);
+                        self.static_labels.insert(*symbol, label.clone());
+                        let image = initializer.as_ref().and_then(|i| i.image.clone());
+                        self.add_global(*symbol, label, image, false);
+                    }
+                    SymbolKind::Global { .. } => {
+                        self.global_var_def(*symbol, initializer, builder);
+                    }
+                    // A declaration with extern only introduces the name
+                    SymbolKind::Extern | SymbolKind::Function => (),
+                }
+                None
+            }
+        }
+    }
+
+    fn initialize_local(
+        &mut self,
+        symbol: SymbolId,
+        initializer: &TypedInitializer,
+        builder: &mut FunctionBuilder,
+    ) {
+        let symbols = self.symbols;
+        let data_type = &symbols[symbol.0].data_type;
+        match builder.locations[&symbol] {
+            // A scalar has a single value, or none if the initializer is empty
+            Location::Register(register) => {
+                let mut value = Value::Constant(0);
+                for item in initializer.items.iter() {
+                    value = self.init_value(&item.value, builder);
+                }
+                let value = builder.coerce(value, builder.registers[register.0]);
+                builder.push(Instruction::Copy(register, value));
+            }
+            Location::Slot(_) => {
+                let address = self.symbol_address(symbol, builder);
+                self.initialize(address, data_type, initializer, builder);
+            }
+        }
+    }
+
+    // The address of a variable in memory or a function
+    fn symbol_address(&self, symbol: SymbolId, builder: &mut FunctionBuilder) -> Value {
+        let symbol_info = &self.symbols[symbol.0];
+        match symbol_info.kind {
+            SymbolKind::Local => match builder.locations[&symbol] {
+                Location::Slot(slot) => builder.compute(Type::Ptr, |register| {
+                    Instruction::SlotAddress(register, slot)
+                }),
+                Location::Register(_) => {
+                    unreachable!("the address of {} is not taken", symbol_info.name)
+                }
+            },
+            SymbolKind::StaticLocal | SymbolKind::Global { .. } => {
+                let name = self.label(symbol);
+                builder.compute(Type::Ptr, |destination| Instruction::GlobalAddress {
+                    destination,
+                    name,
+                    external: false,
+                })
+            }
+            // The address of a symbol which can be defined elsewhere is resolved by the linker
+            SymbolKind::Extern | SymbolKind::Function => {
+                let name = symbol_info.name.clone();
+                builder.compute(Type::Ptr, |destination| Instruction::GlobalAddress {
+                    destination,
+                    name,
+                    external: true,
+                })
+            }
+        }
+    }
+
+    // The address of an lvalue, which is a variable in memory, a dereference,
+    // a member of a struct or a union, or an element of an array
+    fn address(&mut self, lvalue: &TypedExpr, builder: &mut FunctionBuilder) -> Value {
+        match &lvalue.kind {
+            TypedExprKind::Variable(symbol) => self.symbol_address(*symbol, builder),
+            // The value of a pointer is the address it points to
+            TypedExprKind::Deref(operand) => {
+                let value = self.expr(operand, builder);
+                builder.coerce(value, Type::Ptr)
+            }
+            TypedExprKind::Index(inner, index) => {
+                let inner = self.expr(inner, builder);
+                let inner = builder.coerce(inner, Type::Ptr);
+                let index = self.expr(index, builder);
+                let index = builder.coerce(index, Type::I64);
+                let offset = self.scale(index, self.size_of(&lvalue.data_type), builder);
+                builder.compute(Type::Ptr, |register| {
+                    Instruction::Binary(BinaryOp::Add, register, inner, offset)
+                })
+            }
+            // The value of a struct or a union is its address
+            TypedExprKind::Member(inner, _, offset)
+            | TypedExprKind::PointerMember(inner, _, offset) => {
+                let inner = self.expr(inner, builder);
+                let inner = builder.coerce(inner, Type::Ptr);
+                builder.offset_address(inner, *offset)
+            }
+            _ => unreachable!("not an lvalue: {:?}", lvalue),
+        }
+    }
+
+    // Multiply an index by the size of an element
+    fn scale(&self, index: Value, size: u32, builder: &mut FunctionBuilder) -> Value {
+        if size == 1 {
+            return index;
+        }
+        builder.compute(Type::I64, |register| {
+            Instruction::Binary(BinaryOp::Mul, register, index, Value::Constant(size as i64))
+        })
+    }
+
+    // Pointer arithmetic is scaled by the size of the pointed type
+    fn pointee_size(&self, pointer_type: &DataType) -> u32 {
+        match pointer_type {
+            DataType::Pointer(1, base_type) => self.size_of(base_type).max(1),
+            _ => 8,
+        }
+    }
+
+    // Load the value of the type from the address, unless the value is represented by the address
+    fn load(&self, address: Value, data_type: &DataType, builder: &mut FunctionBuilder) -> Value {
+        let address = builder.coerce(address, Type::Ptr);
+        if is_represented_by_address(data_type) {
+            address
+        } else {
+            builder.compute(register_type(data_type), |register| {
+                Instruction::Load(register, address)
+            })
+        }
+    }
+
+    fn binary(
+        &mut self,
+        operator: BinaryOperator,
+        left: &TypedExpr,
+        right: &TypedExpr,
+        result_type: &DataType,
+        builder: &mut FunctionBuilder,
+    ) -> Value {
+        let left_value = self.expr(left, builder);
+        let right_value = self.expr(right, builder);
+        let op = match operator {
+            BinaryOperator::Add => BinaryOp::Add,
+            BinaryOperator::Sub => BinaryOp::Sub,
+            BinaryOperator::Mul => BinaryOp::Mul,
+            BinaryOperator::Div => BinaryOp::Div,
+            BinaryOperator::Equal => BinaryOp::Equal,
+            BinaryOperator::NotEqual => BinaryOp::NotEqual,
+            BinaryOperator::Less => BinaryOp::Less,
+            BinaryOperator::LessOrEqual => BinaryOp::LessOrEqual,
+        };
+        let result_type = register_type(result_type);
+        match (op, &left.data_type, &right.data_type) {
+            // The difference of two pointers is the number of elements between them
+            (BinaryOp::Sub, DataType::Pointer(_, _), DataType::Pointer(_, _)) => {
+                let difference = builder.compute(Type::I64, |register| {
+                    Instruction::Binary(BinaryOp::Sub, register, left_value, right_value)
+                });
+                let size = self.pointee_size(&left.data_type);
+                if size == 1 {
+                    return difference;
+                }
+                builder.compute(Type::I64, |register| {
+                    Instruction::Binary(
+                        BinaryOp::Div,
+                        register,
+                        difference,
+                        Value::Constant(size as i64),
+                    )
+                })
+            }
+            (BinaryOp::Add | BinaryOp::Sub, DataType::Pointer(_, _), _) => {
+                let right_value = builder.coerce(right_value, Type::I64);
+                let offset = self.scale(right_value, self.pointee_size(&left.data_type), builder);
+                builder.compute(result_type, |register| {
+                    Instruction::Binary(op, register, left_value, offset)
+                })
+            }
+            _ => {
+                let left_value = builder.coerce(left_value, Type::I64);
+                let right_value = builder.coerce(right_value, Type::I64);
+                let result_type = if result_type == Type::I8 {
+                    Type::I64
+                } else {
+                    result_type
+                };
+                builder.compute(result_type, |register| {
+                    Instruction::Binary(op, register, left_value, right_value)
+                })
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &TypedExpr, builder: &mut FunctionBuilder) -> Value {
+        match &expr.kind {
+            TypedExprKind::Number(n) => Value::Constant(*n as i64),
+            TypedExprKind::StringLiteral(bytes) => {
+                let index = self.register_string_literal(bytes);
+                builder.compute(Type::Ptr, |register| {
+                    Instruction::StringAddress(register, index)
+                })
+            }
+            // The value of a function designator is its address
+            TypedExprKind::Variable(symbol) => match builder.locations.get(symbol) {
+                Some(Location::Register(register)) => Value::Register(*register),
+                _ => {
+                    let address = self.symbol_address(*symbol, builder);
+                    self.load(address, &expr.data_type, builder)
+                }
+            },
+            TypedExprKind::AddressOf(operand) => self.address(operand, builder),
+            TypedExprKind::Binary(operator, left, right) => {
+                self.binary(*operator, left, right, &expr.data_type, builder)
+            }
+            TypedExprKind::Neg(operand) => {
+                let value = self.expr(operand, builder);
+                let value = builder.coerce(value, Type::I64);
+                builder.compute(Type::I64, |register| Instruction::Neg(register, value))
+            }
+            // Dereferencing a function pointer yields the function itself,
+            // whose value is its address
+            TypedExprKind::Deref(operand) => {
+                let address = self.expr(operand, builder);
+                self.load(address, &expr.data_type, builder)
+            }
+            TypedExprKind::Index(_, _)
+            | TypedExprKind::Member(_, _, _)
+            | TypedExprKind::PointerMember(_, _, _) => {
+                let address = self.address(expr, builder);
+                self.load(address, &expr.data_type, builder)
+            }
+            TypedExprKind::Call(callee, arguments) => {
+                self.call(callee, arguments, &expr.data_type, builder)
+            }
+            TypedExprKind::VaStart(ap) => {
+                let ap = self.expr(ap, builder);
+                let ap = builder.coerce(ap, Type::Ptr);
+                builder.push(Instruction::VaStart(ap));
+                Value::Constant(0)
+            }
+            // Only arguments of the INTEGER class are supported
+            TypedExprKind::VaArg(ap) => {
+                let ap = self.expr(ap, builder);
+                let ap = builder.coerce(ap, Type::Ptr);
+                let value_type = match register_type(&expr.data_type) {
+                    Type::I8 => Type::I64,
+                    value_type => value_type,
+                };
+                let value =
+                    builder.compute(value_type, |register| Instruction::VaArg(register, ap));
+                builder.coerce(value, register_type(&expr.data_type))
+            }
+            TypedExprKind::VaEnd(ap) => {
+                self.expr(ap, builder);
+                Value::Constant(0)
+            }
+            TypedExprKind::VaCopy(destination, source) => {
+                let destination = self.expr(destination, builder);
+                let destination = builder.coerce(destination, Type::Ptr);
+                let source = self.expr(source, builder);
+                let source = builder.coerce(source, Type::Ptr);
+                let size = self.size_of(&DataType::Primitive(PrimitiveType::VaList));
+                builder.push(Instruction::MemCopy(destination, source, size));
+                Value::Constant(0)
+            }
+            // The value of an array or a function is already its address
+            TypedExprKind::Convert(
+                Conversion::ArrayToPointer | Conversion::FunctionToPointer,
+                operand,
+            ) => self.expr(operand, builder),
+            TypedExprKind::Convert(
+                Conversion::IntegerPromotion | Conversion::Assignment,
+                operand,
+            ) => {
+                let value = self.expr(operand, builder);
+                builder.coerce(value, register_type(&expr.data_type))
+            }
+        }
+    }
+
+    // A function designator is called directly, and a function pointer indirectly
+    fn call(
+        &mut self,
+        callee: &TypedExpr,
+        arguments: &[TypedExpr],
+        return_type: &DataType,
+        builder: &mut FunctionBuilder,
+    ) -> Value {
+        let mut argument_values = Vec::new();
+        for argument in arguments.iter() {
+            let value = self.expr(argument, builder);
+            argument_values.push(if is_scalar(&argument.data_type) {
+                Argument::Scalar(value)
+            } else {
+                Argument::Aggregate(builder.coerce(value, Type::Ptr), argument.data_type.clone())
+            });
+        }
+        let callee = match callee.kind {
+            TypedExprKind::Variable(symbol)
+                if self.symbols[symbol.0].kind == SymbolKind::Function =>
+            {
+                Callee::Direct(self.symbols[symbol.0].name.clone())
+            }
+            _ => {
+                let value = self.expr(callee, builder);
+                Callee::Indirect(builder.coerce(value, Type::Ptr))
+            }
+        };
+        let (result, value) = match self.return_type(return_type) {
+            ReturnType::Void => (CallResult::None, Value::Constant(0)),
+            ReturnType::Scalar(value_type) => {
+                let register = builder.new_register(value_type);
+                (CallResult::Scalar(register), Value::Register(register))
+            }
+            // An aggregate return value is stored to a temporary slot of the caller
+            ReturnType::Aggregate(data_type) => {
+                let slot = builder.new_slot(
+                    self.size_of(&data_type),
+                    self.layouts.align_of(&data_type),
+                    None,
+                );
+                let address = builder.compute(Type::Ptr, |register| {
+                    Instruction::SlotAddress(register, slot)
+                });
+                (CallResult::Aggregate(address, data_type), address)
+            }
+        };
+        builder.push(Instruction::Call {
+            result,
+            callee,
+            arguments: argument_values,
+        });
+        value
+    }
+}
@@ -1,38 +1,422 @@
-#[macro_use]
-extern crate lalrpop_util;
-
-lalrpop_mod!(
-    #[allow(clippy::all)]
-    #[allow(unused)]
-    pub parser
-);
-pub mod assembly;
-pub mod ast;
-pub mod compile_error;
-pub mod gen_code;
-pub mod infer_type;
-use crate::gen_code::print_assembly;
+use rust_9cc::compile_error::CompilerError;
+use rust_9cc::diagnostic::{Diagnostic, Severity};
+use rust_9cc::diagnostic_format::{render_diagnostics, DiagnosticFormat};
+use rust_9cc::{compile, CompiledOutput, Emit, OptimizationLevel};
+use std::cell::RefCell;
 use std::env;
-use std::process::ExitCode;
+use std::fs::{self, File};
+use std::io::{stderr, stdin, stdout, BufWriter, IsTerminal, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, ExitCode};
 
-fn main() -> ExitCode {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("The number of arguments is invalid");
-        return ExitCode::from(1);
+// What the compiler produces, chosen by -S and -c
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Assembly,
+    Object,
+    Executable,
+}
+
+// A file given to the compiler, whose kind is decided by its extension
+enum Input {
+    // A C source file, or the standard input given as `-`
+    Source(String),
+    Assembly(String),
+    // An object file, a library, or an option passed to the linker such as -l and -L
+    Linker(String),
+}
+
+struct Options {
+    // The options shared by the source files, whose names are set for each of them
+    compile_options: rust_9cc::Options,
+    // Diagnostics are colored when they are shown on a terminal, unless specified otherwise
+    color: bool,
+    format: DiagnosticFormat,
+    // The maximum number of errors reported for each file, where 0 means no limit
+    error_limit: usize,
+    stage: Stage,
+    output: Option<String>,
+    inputs: Vec<Input>,
+    // The diagnostics of all the inputs, which are written as one SARIF log at the end
+    sarif_diagnostics: RefCell<Vec<Diagnostic>>,
+}
+
+impl Options {
+    // Text and JSON are written as soon as the diagnostics are reported
+    fn report(&self, mut diagnostics: Vec<Diagnostic>, limit: Option<usize>) {
+        match self.format {
+            DiagnosticFormat::Sarif => self.sarif_diagnostics.borrow_mut().append(&mut diagnostics),
+            _ => eprint!(
+                "{}",
+                render_diagnostics(&diagnostics, self.format, self.color, limit)
+            ),
+        }
     }
-    match parser::ProgramParser::new().parse(&args[1]) {
-        Ok(parse_tree) => {
-            if let Err(e) = print_assembly(&parse_tree) {
-                eprintln!("Failed to compile: {}", e);
-                return ExitCode::from(1);
+
+    fn finish_report(&self) {
+        if self.format == DiagnosticFormat::Sarif {
+            let diagnostics = self.sarif_diagnostics.take();
+            eprint!(
+                "{}",
+                render_diagnostics(&diagnostics, self.format, self.color, None)
+            );
+        }
+    }
+}
+
+fn invalid_command_line(message: String) -> Diagnostic {
+    CompilerError::InvalidCommandLine(message).to_diagnostic()
+}
+
+// Options taking a value, either attached or as the next argument
+const OPTIONS_WITH_VALUE: &[&str] = &["-I", "-D", "-U", "-o", "-l", "-L"];
+
+// Parse the arguments in the same way as gcc. Errors in the command line are returned
+// after all the options are read, so that they are written in the format given by any of them.
+fn parse_arguments(args: &[String]) -> (Options, Vec<Diagnostic>) {
+    let mut options = Options {
+        compile_options: rust_9cc::Options::default(),
+        color: stderr().is_terminal(),
+        format: DiagnosticFormat::Text,
+        error_limit: 20,
+        stage: Stage::Executable,
+        output: None,
+        inputs: Vec::new(),
+        sarif_diagnostics: RefCell::new(Vec::new()),
+    };
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut index = 0;
+    while index < args.len() {
+        let arg = &args[index];
+        index += 1;
+        if let Some(when) = arg.strip_prefix("-fdiagnostics-color=") {
+            match when {
+                "always" => options.color = true,
+                "never" => options.color = false,
+                "auto" => options.color = stderr().is_terminal(),
+                _ => diagnostics.push(invalid_command_line(format!(
+                    "Invalid argument '{}' to -fdiagnostics-color",
+                    when
+                ))),
+            }
+            continue;
+        }
+        if let Some(name) = arg.strip_prefix("--diagnostics-format=") {
+            match DiagnosticFormat::from_name(name) {
+                Some(format) => options.format = format,
+                None => diagnostics.push(invalid_command_line(format!(
+                    "Invalid argument '{}' to --diagnostics-format",
+                    name
+                ))),
+            }
+            continue;
+        }
+        if let Some(limit) = arg.strip_prefix("-ferror-limit=") {
+            match limit.parse() {
+                Ok(limit) => options.error_limit = limit,
+                Err(_) => diagnostics.push(invalid_command_line(format!(
+                    "Invalid argument '{}' to -ferror-limit",
+                    limit
+                ))),
+            }
+            continue;
+        }
+        // -Wl,<options> passes the options separated by commas to the linker
+        if let Some(linker_options) = arg.strip_prefix("-Wl,") {
+            for linker_option in linker_options.split(',') {
+                options
+                    .inputs
+                    .push(Input::Linker(format!("-Wl,{}", linker_option)));
+            }
+            continue;
+        }
+        if let Some(option) = arg.strip_prefix("-W") {
+            if !options.compile_options.warning_options.apply(option) {
+                diagnostics.push(invalid_command_line(format!(
+                    "Unknown warning option '{}'",
+                    arg
+                )));
             }
+            continue;
         }
+        // -fdump-<name> is the same as --emit=<name>
+        let emit = ["--emit=", "-fdump-"]
+            .iter()
+            .find_map(|prefix| Some((*prefix, arg.strip_prefix(prefix)?)));
+        if let Some((prefix, name)) = emit {
+            match Emit::from_name(name) {
+                Some(emit) => {
+                    options.compile_options.emit = emit;
+                    options.stage = Stage::Assembly;
+                }
+                None => diagnostics.push(invalid_command_line(format!(
+                    "Invalid argument '{}' to {}",
+                    name,
+                    prefix.trim_end_matches('=')
+                ))),
+            }
+            continue;
+        }
+        match arg.as_str() {
+            "-S" => options.stage = Stage::Assembly,
+            "-c" => options.stage = Stage::Object,
+            "-O0" => options.compile_options.optimization_level = OptimizationLevel::None,
+            _ if arg.starts_with("-O") => {
+                options.compile_options.optimization_level = OptimizationLevel::Basic
+            }
+            // Accepted for compatibility with gcc, and ignored
+            "-g" => (),
+            _ if arg.starts_with("-std=") => (),
+            "-" => options.inputs.push(Input::Source(arg.clone())),
+            _ if arg.starts_with('-') => {
+                let option = match OPTIONS_WITH_VALUE.iter().find(|o| arg.starts_with(*o)) {
+                    Some(option) => *option,
+                    None => {
+                        diagnostics.push(invalid_command_line(format!("Unknown option '{}'", arg)));
+                        continue;
+                    }
+                };
+                let value = if arg.len() > option.len() {
+                    arg[option.len()..].to_string()
+                } else {
+                    match args.get(index) {
+                        Some(value) => {
+                            index += 1;
+                            value.clone()
+                        }
+                        None => {
+                            diagnostics.push(invalid_command_line(format!(
+                                "Missing argument after {}",
+                                option
+                            )));
+                            continue;
+                        }
+                    }
+                };
+                match option {
+                    "-I" => options
+                        .compile_options
+                        .preprocessor
+                        .add_include_path(PathBuf::from(value)),
+                    "-D" => {
+                        if let Err(e) = options.compile_options.preprocessor.define_macro(&value) {
+                            diagnostics.push(e.to_diagnostic());
+                        }
+                    }
+                    "-U" => options.compile_options.preprocessor.undefine_macro(&value),
+                    "-o" => options.output = Some(value),
+                    _ => options
+                        .inputs
+                        .push(Input::Linker(format!("{}{}", option, value))),
+                }
+            }
+            _ if arg.ends_with(".c") => options.inputs.push(Input::Source(arg.clone())),
+            _ if arg.ends_with(".s") => options.inputs.push(Input::Assembly(arg.clone())),
+            _ => options.inputs.push(Input::Linker(arg.clone())),
+        }
+    }
+    if options.inputs.is_empty() {
+        diagnostics.push(invalid_command_line("No input files".to_string()));
+    }
+    // Each source file is compiled to its own output unless they are linked together
+    let output_count = options
+        .inputs
+        .iter()
+        .filter(|input| match input {
+            Input::Source(_) => true,
+            Input::Assembly(_) => options.stage == Stage::Object,
+            Input::Linker(_) => false,
+        })
+        .count();
+    if options.output.is_some() && options.stage != Stage::Executable && output_count > 1 {
+        diagnostics.push(invalid_command_line(
+            "Cannot specify -o with -c or -S with multiple files".to_string(),
+        ));
+    }
+    (options, diagnostics)
+}
+
+// Compile a source file, reporting the diagnostics.
+// Returns None if there are errors.
+fn compile_source(input: &str, options: &Options) -> Option<CompiledOutput> {
+    let mut compile_options = options.compile_options.clone();
+    let source = if input == "-" {
+        compile_options.file_name = "<stdin>".to_string();
+        let mut source = String::new();
+        stdin().read_to_string(&mut source).map(|_| source)
+    } else {
+        compile_options.file_name = input.to_string();
+        compile_options.path = Some(PathBuf::from(input));
+        fs::read_to_string(input)
+    };
+    let source = match source {
+        Ok(source) => source,
         Err(e) => {
-            eprintln!("Failed to parse: {}", e);
-            return ExitCode::from(1);
+            let message = format!("{}: {}", compile_options.file_name, e);
+            options.report(
+                vec![CompilerError::DriverError(message).to_diagnostic()],
+                None,
+            );
+            return None;
         }
     };
+    match compile(&source, &compile_options) {
+        Ok(mut output) => {
+            options.report(mem::take(&mut output.warnings), None);
+            Some(output)
+        }
+        // Warnings are not limited, and shown before the errors
+        Err(diagnostics) => {
+            let (mut warnings, mut errors): (Vec<Diagnostic>, Vec<Diagnostic>) = diagnostics
+                .into_iter()
+                .partition(|diagnostic| diagnostic.severity == Severity::Warning);
+            let error_limit = options.error_limit;
+            let limit = if error_limit > 0 && errors.len() > error_limit {
+                errors.truncate(error_limit);
+                Some(error_limit)
+            } else {
+                None
+            };
+            warnings.append(&mut errors);
+            options.report(warnings, limit);
+            None
+        }
+    }
+}
+
+// The name of the output for an input in the current directory, as `dir/a.c` gives `a.s`
+fn output_name(input: &str, extension: &str) -> String {
+    let stem = Path::new(input)
+        .file_stem()
+        .map_or(input.into(), |stem| stem.to_string_lossy());
+    format!("{}.{}", stem, extension)
+}
+
+// Write the assembly to a file, or to the standard output if the name is `-`
+fn write_output(name: &str, output: &CompiledOutput) -> Result<(), CompilerError> {
+    let result = if name == "-" {
+        output.emit(&mut stdout().lock())
+    } else {
+        File::create(name).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            output.emit(&mut writer)?;
+            writer.flush()
+        })
+    };
+    result.map_err(|e| CompilerError::DriverError(format!("{}: {}", name, e)))
+}
+
+fn run_tool(program: &str, arguments: &[String]) -> Result<(), CompilerError> {
+    match Command::new(program).args(arguments).status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(CompilerError::DriverError(format!(
+            "{} failed with {}",
+            program, status
+        ))),
+        Err(e) => Err(CompilerError::DriverError(format!(
+            "Failed to run {}: {}",
+            program, e
+        ))),
+    }
+}
+
+fn assemble(input: &str, output: &str) -> Result<(), CompilerError> {
+    run_tool(
+        "as",
+        &["-o".to_string(), output.to_string(), input.to_string()],
+    )
+}
 
-    ExitCode::from(0)
+// Compile, assemble and link the inputs up to the stage given by the options.
+// Returns false if a source file failed to compile, whose errors are already reported.
+fn build(options: &Options, temporary_files: &mut Vec<String>) -> Result<bool, CompilerError> {
+    let mut failed = false;
+    let mut linker_arguments = Vec::new();
+    for input in options.inputs.iter() {
+        let (assembly_file, path) = match input {
+            Input::Source(path) => {
+                let output = match compile_source(path, options) {
+                    Some(output) => output,
+                    None => {
+                        failed = true;
+                        continue;
+                    }
+                };
+                if options.stage == Stage::Assembly {
+                    // Dumps are written to the standard output unless -o is given
+                    let name = options.output.clone().unwrap_or_else(|| {
+                        match options.compile_options.emit {
+                            Emit::Asm => output_name(path, "s"),
+                            _ => "-".to_string(),
+                        }
+                    });
+                    write_output(&name, &output)?;
+                    continue;
+                }
+                let temporary_file = env::temp_dir()
+                    .join(format!(
+                        "rust-9cc-{}-{}.s",
+                        process::id(),
+                        temporary_files.len()
+                    ))
+                    .to_string_lossy()
+                    .to_string();
+                temporary_files.push(temporary_file.clone());
+                write_output(&temporary_file, &output)?;
+                (temporary_file, path)
+            }
+            Input::Assembly(path) => (path.clone(), path),
+            Input::Linker(argument) => {
+                linker_arguments.push(argument.clone());
+                continue;
+            }
+        };
+        match options.stage {
+            // Assembly files are already assembly
+            Stage::Assembly => (),
+            Stage::Object => {
+                let output = options.output.clone();
+                assemble(&assembly_file, &output.unwrap_or(output_name(path, "o")))?;
+            }
+            Stage::Executable => linker_arguments.push(assembly_file),
+        }
+    }
+    if failed {
+        return Ok(false);
+    }
+    if options.stage == Stage::Executable {
+        let mut arguments = vec![
+            "-o".to_string(),
+            options.output.clone().unwrap_or("a.out".to_string()),
+        ];
+        arguments.append(&mut linker_arguments);
+        run_tool("cc", &arguments)?;
+    }
+    Ok(true)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    let (options, diagnostics) = parse_arguments(&args[1..]);
+    if !diagnostics.is_empty() {
+        options.report(diagnostics, None);
+        options.finish_report();
+        return ExitCode::from(1);
+    }
+    let mut temporary_files = Vec::new();
+    let result = build(&options, &mut temporary_files);
+    for temporary_file in temporary_files.iter() {
+        let _ = fs::remove_file(temporary_file);
+    }
+    let exit_code = match result {
+        Ok(true) => ExitCode::from(0),
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            options.report(vec![e.to_diagnostic()], None);
+            ExitCode::from(1)
+        }
+    };
+    options.finish_report();
+    exit_code
 }
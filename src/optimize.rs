@@ -0,0 +1,190 @@
+use crate::cfg::ControlFlowGraph;
+use crate::ir::*;
+
+// Optimization of the IR by constant folding and constant propagation.
+// The IR is not in SSA form, so only a register assigned once is known to hold the constant
+// it is assigned wherever it is read. Reading it before the assignment reads an uninitialized
+// variable, whose value is indeterminate and can be taken to be the constant.
+
+pub fn optimize(module: &mut Module) {
+    for function in module.functions.iter_mut() {
+        propagate_constants(function);
+        remove_dead_instructions(function);
+        merge_blocks(function);
+    }
+}
+
+// Replace the reads of registers holding a constant with the constant, evaluate the
+// instructions whose operands are all constants, and turn branches on a constant into jumps,
+// until nothing changes. Removing the blocks a branch no longer reaches can leave a variable
+// assigned once, such as one assigned in the body of `while (0)`.
+fn propagate_constants(function: &mut Function) {
+    loop {
+        let assignment_counts = assignment_counts(function);
+        let mut constants: Vec<Option<i64>> = vec![None; function.registers.len()];
+        let mut changed = false;
+        for block in function.blocks.iter_mut() {
+            for instruction in block.instructions.iter_mut() {
+                changed |= substitute(instruction.operands_mut(), &constants);
+                let Some((destination, value)) = evaluate(instruction, &function.registers) else {
+                    continue;
+                };
+                let folded = Instruction::Copy(destination, Value::Constant(value));
+                if *instruction != folded {
+                    *instruction = folded;
+                    changed = true;
+                }
+                if assignment_counts[destination.0] == 1 {
+                    constants[destination.0] = Some(value);
+                }
+            }
+            changed |= substitute(block.terminator.operands_mut(), &constants);
+            if let Terminator::Branch(Value::Constant(n), then_block, else_block) = block.terminator
+            {
+                block.terminator = Terminator::Jump(if n != 0 { then_block } else { else_block });
+                changed = true;
+            }
+        }
+        if !changed {
+            return;
+        }
+        function.remove_unreachable_blocks();
+    }
+}
+
+// The number of times each register is assigned, where a parameter is assigned on entry
+fn assignment_counts(function: &Function) -> Vec<usize> {
+    let mut counts = vec![0; function.registers.len()];
+    for parameter in function.parameters.iter() {
+        if let Parameter::Scalar(register) = parameter {
+            counts[register.0] += 1;
+        }
+    }
+    for block in function.blocks.iter() {
+        for instruction in block.instructions.iter() {
+            if let Some(destination) = instruction.destination() {
+                counts[destination.0] += 1;
+            }
+        }
+    }
+    counts
+}
+
+// Returns true if any of the operands is replaced
+fn substitute(operands: Vec<&mut Value>, constants: &[Option<i64>]) -> bool {
+    let mut changed = false;
+    for operand in operands {
+        if let Value::Register(register) = *operand {
+            if let Some(n) = constants[register.0] {
+                *operand = Value::Constant(n);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+// The constant an instruction assigns to its destination, if its operands are constants.
+// A char is sign-extended to 64 bits, as the generated code keeps it.
+fn evaluate(instruction: &Instruction, registers: &[Type]) -> Option<(VirtualRegister, i64)> {
+    let (destination, value) = match instruction {
+        Instruction::Copy(destination, Value::Constant(n))
+        | Instruction::Cast(destination, Value::Constant(n)) => (*destination, *n),
+        Instruction::Neg(destination, Value::Constant(n)) => (*destination, n.wrapping_neg()),
+        Instruction::Binary(op, destination, Value::Constant(left), Value::Constant(right)) => {
+            (*destination, evaluate_binary(*op, *left, *right)?)
+        }
+        _ => return None,
+    };
+    match registers[destination.0] {
+        Type::I8 => Some((destination, value as i8 as i64)),
+        Type::I64 | Type::Ptr => Some((destination, value)),
+    }
+}
+
+// Arithmetic wraps around as the generated code does. A division by zero, and the division
+// of the minimum by -1 which overflows, are left to trap at run time.
+fn evaluate_binary(op: BinaryOp, left: i64, right: i64) -> Option<i64> {
+    Some(match op {
+        BinaryOp::Add => left.wrapping_add(right),
+        BinaryOp::Sub => left.wrapping_sub(right),
+        BinaryOp::Mul => left.wrapping_mul(right),
+        BinaryOp::Div => left.checked_div(right)?,
+        BinaryOp::Equal => (left == right) as i64,
+        BinaryOp::NotEqual => (left != right) as i64,
+        BinaryOp::Less => (left < right) as i64,
+        BinaryOp::LessOrEqual => (left <= right) as i64,
+    })
+}
+
+// Remove the instructions without side effects whose results are never read,
+// such as the assignments of the constants propagated to all the reads
+fn remove_dead_instructions(function: &mut Function) {
+    loop {
+        let mut read = vec![false; function.registers.len()];
+        for block in function.blocks.iter() {
+            let instruction_operands = block.instructions.iter().flat_map(|i| i.operands());
+            for operand in instruction_operands.chain(block.terminator.operands()) {
+                if let Value::Register(register) = operand {
+                    read[register.0] = true;
+                }
+            }
+        }
+        let mut removed = false;
+        for block in function.blocks.iter_mut() {
+            block.instructions.retain(|instruction| {
+                let is_dead = has_no_side_effects(instruction)
+                    && instruction
+                        .destination()
+                        .is_some_and(|destination| !read[destination.0]);
+                removed |= is_dead;
+                !is_dead
+            });
+        }
+        if !removed {
+            return;
+        }
+    }
+}
+
+// Loads are kept, since the address can be invalid, and so are divisions which can trap
+fn has_no_side_effects(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Binary(BinaryOp::Div, _, _, divisor) => {
+            matches!(divisor, Value::Constant(n) if *n != 0 && *n != -1)
+        }
+        Instruction::Copy(_, _)
+        | Instruction::Binary(_, _, _, _)
+        | Instruction::Neg(_, _)
+        | Instruction::Cast(_, _)
+        | Instruction::SlotAddress(_, _)
+        | Instruction::GlobalAddress { .. }
+        | Instruction::StringAddress(_, _) => true,
+        _ => false,
+    }
+}
+
+// Merge each block into the block jumping to it if it has no other predecessors,
+// such as the chains of jumps left where a branch on a constant was
+fn merge_blocks(function: &mut Function) {
+    let cfg = ControlFlowGraph::new(function);
+    for index in 0..function.blocks.len() {
+        while let Terminator::Jump(target) = function.blocks[index].terminator {
+            if target.0 == index || target == BlockId(0) || cfg.predecessors[target.0].len() != 1 {
+                break;
+            }
+            // The merged block is left jumping to itself without predecessors
+            let merged = std::mem::replace(
+                &mut function.blocks[target.0],
+                Block {
+                    instructions: Vec::new(),
+                    terminator: Terminator::Jump(target),
+                },
+            );
+            let block = &mut function.blocks[index];
+            block.instructions.extend(merged.instructions);
+            block.terminator = merged.terminator;
+        }
+    }
+    function.remove_unreachable_blocks();
+}
@@ -0,0 +1,1260 @@
+use crate::ast::Span;
+use crate::compile_error::CompilerError;
+use crate::preprocessor_expression::ExpressionEvaluator;
+use crate::translation_phase::strip_comments;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Includes nested deeper than this are assumed to be recursive
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenKind {
+    Identifier,
+    Number,
+    StringLiteral,
+    CharLiteral,
+    Punctuator,
+    // A character which does not form any other token
+    Other,
+    // The arguments of `#pragma GCC diagnostic`, which are not emitted into the text
+    // but recorded with their offset to control warnings from that point
+    DiagnosticPragma,
+    // The message of `#warning`, which is reported as a warning instead of emitted into the text
+    WarningDirective,
+}
+
+// Where a token appears in the original files
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Location {
+    // The index of the file in `SourceMap::files`
+    pub file: usize,
+    pub line: u32,
+    pub column: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    pub location: Location,
+    // True if the token is preceded by whitespace
+    pub has_space: bool,
+    // True if the token is the first one in its line
+    pub at_line_start: bool,
+    // The names of the macros whose expansion produced this token,
+    // which are not expanded again in this token
+    hide_set: HashSet<String>,
+}
+
+impl Token {
+    fn is_punctuator(&self, text: &str) -> bool {
+        self.kind == TokenKind::Punctuator && self.text == text
+    }
+}
+
+// Spell the tokens as a string literal for the `#` operator
+fn stringize(tokens: &[Token]) -> String {
+    let mut text = String::from("\"");
+    for (index, token) in tokens.iter().enumerate() {
+        if index > 0 && token.has_space {
+            text.push(' ');
+        }
+        match token.kind {
+            TokenKind::StringLiteral | TokenKind::CharLiteral => {
+                for c in token.text.chars() {
+                    if c == '"' || c == '\\' {
+                        text.push('\\');
+                    }
+                    text.push(c);
+                }
+            }
+            _ => text.push_str(&token.text),
+        }
+    }
+    text.push('"');
+    text
+}
+
+// Spell the tokens as they appear in the source
+fn spell(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    for (index, token) in tokens.iter().enumerate() {
+        if index > 0 && token.has_space {
+            text.push(' ');
+        }
+        text.push_str(&token.text);
+    }
+    text
+}
+
+// Punctuators made of more than one character, longest first
+const PUNCTUATORS: &[&str] = &[
+    "<<=", ">>=", "...", "->", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=",
+    "/=", "%=", "+=", "-=", "&=", "^=", "|=", "##",
+];
+
+/// Split the source into preprocessing tokens.
+/// Comments must be removed and lines must be spliced beforehand.
+pub fn tokenize(source: &str, file: usize) -> Vec<Token> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut has_space = false;
+    let mut at_line_start = true;
+    while index < chars.len() {
+        let c = chars[index];
+        if c == '\n' {
+            index += 1;
+            line += 1;
+            line_start = index;
+            has_space = false;
+            at_line_start = true;
+            continue;
+        }
+        if c.is_whitespace() {
+            index += 1;
+            has_space = true;
+            continue;
+        }
+        let start = index;
+        let is_identifier_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let kind = if c.is_ascii_alphabetic() || c == '_' {
+            while index < chars.len() && is_identifier_char(chars[index]) {
+                index += 1;
+            }
+            TokenKind::Identifier
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(index + 1).is_some_and(|c| c.is_ascii_digit()))
+        {
+            index += 1;
+            while index < chars.len() {
+                if matches!(chars[index], 'e' | 'E' | 'p' | 'P')
+                    && matches!(chars.get(index + 1), Some('+') | Some('-'))
+                {
+                    index += 2;
+                } else if is_identifier_char(chars[index]) || chars[index] == '.' {
+                    index += 1;
+                } else {
+                    break;
+                }
+            }
+            TokenKind::Number
+        } else if c == '"' || c == '\'' {
+            index += 1;
+            while index < chars.len() && chars[index] != c && chars[index] != '\n' {
+                if chars[index] == '\\' && index + 1 < chars.len() {
+                    index += 1;
+                }
+                index += 1;
+            }
+            if index < chars.len() && chars[index] == c {
+                index += 1;
+                if c == '"' {
+                    TokenKind::StringLiteral
+                } else {
+                    TokenKind::CharLiteral
+                }
+            } else {
+                // An unterminated literal is left to the parser to report
+                TokenKind::Other
+            }
+        } else {
+            let rest: String = chars[index..chars.len().min(index + 3)].iter().collect();
+            match PUNCTUATORS.iter().find(|p| rest.starts_with(*p)) {
+                Some(punctuator) => index += punctuator.len(),
+                None => index += 1,
+            }
+            if c.is_ascii_punctuation() {
+                TokenKind::Punctuator
+            } else {
+                TokenKind::Other
+            }
+        };
+        tokens.push(Token {
+            kind,
+            text: chars[start..index].iter().collect(),
+            location: Location {
+                file,
+                line,
+                column: (start - line_start) as u32 + 1,
+            },
+            has_space,
+            at_line_start,
+            hide_set: HashSet::new(),
+        });
+        has_space = false;
+        at_line_start = false;
+    }
+    tokens
+}
+
+// The text of a file, whose lines are numbered from 1 plus the offset given by #line
+#[derive(Clone)]
+struct FileText {
+    text: Arc<str>,
+    line_offset: i64,
+}
+
+impl FileText {
+    // The text of a line, which is empty if the file has no such line
+    fn line(&self, line: u32) -> &str {
+        let actual_line = line as i64 - self.line_offset;
+        if actual_line < 1 {
+            return "";
+        }
+        self.text
+            .lines()
+            .nth(actual_line as usize - 1)
+            .unwrap_or("")
+    }
+}
+
+// Where the text of the preprocessed source comes from in the original files
+pub struct SourceMap {
+    pub files: Vec<String>,
+    // The text of each file in `files`, which the lines of diagnostics are shown from
+    texts: Vec<FileText>,
+    // The offset of each token in the preprocessed source and where it appears.
+    // A token produced by a macro expansion appears where the macro is invoked,
+    // unless it comes from an argument of the macro.
+    locations: Vec<(usize, Location)>,
+}
+
+impl SourceMap {
+    // The offset and the location of the token at an offset, or the token before it
+    fn find(&self, offset: usize) -> Option<(usize, Location)> {
+        let index = match self
+            .locations
+            .binary_search_by_key(&offset, |(start, _)| *start)
+        {
+            Ok(index) => index,
+            Err(0) => 0,
+            Err(index) => index - 1,
+        };
+        self.locations.get(index).copied()
+    }
+
+    /// Returns the file name, the line and the column of an offset in the preprocessed source
+    pub fn locate(&self, offset: usize) -> (&str, u32, u32) {
+        match self.find(offset) {
+            Some((start, location)) => (
+                &self.files[location.file],
+                location.line,
+                location.column + offset.saturating_sub(start) as u32,
+            ),
+            None => (&self.files[0], 1, offset as u32 + 1),
+        }
+    }
+
+    /// Returns the location just after the last character of a span, or its start if it is empty.
+    /// The end of a token is located by the token, not by the one following it,
+    /// which can come from elsewhere in a macro expansion.
+    pub fn locate_end(&self, span: Span) -> (&str, u32, u32) {
+        if span.end <= span.start {
+            return self.locate(span.start);
+        }
+        let (file, line, column) = self.locate(span.end - 1);
+        (file, line, column + 1)
+    }
+
+    /// Returns the line of the original file where an offset in the preprocessed source is,
+    /// which is empty if the file is not available
+    pub fn original_line(&self, offset: usize) -> &str {
+        match self.find(offset) {
+            Some((_, location)) => self.texts[location.file].line(location.line),
+            None => "",
+        }
+    }
+}
+
+// A location in the original files with the text of its line, which the errors found
+// in preprocessing have since there is no preprocessed source to locate them in
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    pub text: String,
+}
+
+impl SourceLocation {
+    /// A source consisting of the line alone, and the empty span of the column in it
+    pub fn to_source(&self) -> (PreprocessedSource, Span) {
+        let start = self.column as usize - 1;
+        let source = PreprocessedSource {
+            text: self.text.clone(),
+            source_map: SourceMap {
+                files: vec![self.file.clone()],
+                texts: vec![FileText {
+                    text: self.text.as_str().into(),
+                    line_offset: self.line as i64 - 1,
+                }],
+                locations: vec![(
+                    0,
+                    Location {
+                        file: 0,
+                        line: self.line,
+                        column: 1,
+                    },
+                )],
+            },
+            diagnostic_pragmas: Vec::new(),
+            warning_directives: Vec::new(),
+            tokens: Vec::new(),
+        };
+        (source, Span { start, end: start })
+    }
+}
+
+pub struct PreprocessedSource {
+    pub text: String,
+    pub source_map: SourceMap,
+    // The arguments of `#pragma GCC diagnostic` following `diagnostic`,
+    // with the offsets in the text where they take effect
+    pub diagnostic_pragmas: Vec<(usize, String)>,
+    // The messages of `#warning` with their locations,
+    // and the offsets in the text where they are reported
+    pub warning_directives: Vec<(usize, SourceLocation, String)>,
+    // The tokens after macro expansion, which the text is spelled from
+    pub tokens: Vec<Token>,
+}
+
+#[derive(Clone)]
+struct Macro {
+    // The names of the parameters if the macro is function-like
+    parameters: Option<Vec<String>>,
+    // True if the parameters end with `...`, whose argument is `__VA_ARGS__`
+    variadic: bool,
+    replacement: Vec<Token>,
+}
+
+impl Macro {
+    // Returns the index of the argument which replaces the token
+    fn parameter_index(&self, token: &Token) -> Option<usize> {
+        if token.kind != TokenKind::Identifier {
+            return None;
+        }
+        let parameters = self.parameters.as_ref()?;
+        match parameters.iter().position(|p| *p == token.text) {
+            Some(index) => Some(index),
+            None if self.variadic && token.text == "__VA_ARGS__" => Some(parameters.len()),
+            None => None,
+        }
+    }
+}
+
+// Macros defined before preprocessing starts
+const PREDEFINED_MACROS: &[(&str, &str)] = &[
+    ("__STDC__", "1"),
+    ("__STDC_VERSION__", "201112L"),
+    ("__STDC_HOSTED__", "1"),
+    ("__x86_64__", "1"),
+    ("__x86_64", "1"),
+    ("__linux__", "1"),
+    ("__unix__", "1"),
+    ("__ELF__", "1"),
+    // Replaced with the current line and file in `expand`
+    ("__LINE__", ""),
+    ("__FILE__", ""),
+];
+
+// The state of an #if, #ifdef or #ifndef until the matching #endif
+struct Conditional {
+    // True if the lines of the current group are processed
+    active: bool,
+    // True if a group of this conditional has been processed, or the conditional is skipped
+    // as a whole, so that the following #elif and #else groups are skipped
+    done: bool,
+    // True after #else
+    has_else: bool,
+    // Where the #if is
+    location: Location,
+}
+
+// Cloned to preprocess each source file given to the compiler with the same options
+#[derive(Clone)]
+pub struct Preprocessor {
+    include_paths: Vec<PathBuf>,
+    macros: HashMap<String, Macro>,
+    // Files containing `#pragma once`
+    included_once: HashSet<PathBuf>,
+    files: Vec<String>,
+    // The text of each file in `files`
+    texts: Vec<FileText>,
+    include_depth: usize,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Preprocessor {
+        let mut preprocessor = Preprocessor {
+            include_paths: Vec::new(),
+            macros: HashMap::new(),
+            included_once: HashSet::new(),
+            files: vec!["<built-in>".to_string(), "<command line>".to_string()],
+            texts: vec![
+                FileText {
+                    text: "".into(),
+                    line_offset: 0,
+                };
+                2
+            ],
+            include_depth: 0,
+        };
+        for (name, value) in PREDEFINED_MACROS.iter() {
+            let tokens = tokenize(&format!("{} {}", name, value), 0);
+            // The predefined macros are always valid
+            preprocessor.define(&tokens, tokens[0].location).ok();
+        }
+        preprocessor
+    }
+
+    pub fn add_include_path(&mut self, path: PathBuf) {
+        self.include_paths.push(path);
+    }
+
+    /// Define a macro given by `-D`, as `NAME`, `NAME=VALUE` or `NAME(PARAMETERS)=VALUE`
+    pub fn define_macro(&mut self, definition: &str) -> Result<(), CompilerError> {
+        let definition = match definition.split_once('=') {
+            Some((name, value)) => format!("{} {}", name, value),
+            None => format!("{} 1", definition),
+        };
+        let tokens = tokenize(&definition, 1);
+        self.texts[1].text = definition.into();
+        match tokens.first() {
+            Some(first) => self.define(&tokens, first.location),
+            None => Err(self.error(
+                Location {
+                    file: 1,
+                    line: 1,
+                    column: 1,
+                },
+                "macro name missing after -D".to_string(),
+            )),
+        }
+    }
+
+    /// Undefine a macro given by `-U`
+    pub fn undefine_macro(&mut self, name: &str) {
+        self.macros.remove(name);
+    }
+
+    /// Preprocess the source read from `path`, or given otherwise, as from the standard input.
+    /// Quoted includes are searched from the directory of the file first,
+    /// or from the current directory if there is no file.
+    pub fn preprocess(
+        mut self,
+        source: &str,
+        file_name: &str,
+        path: Option<&Path>,
+    ) -> Result<PreprocessedSource, CompilerError> {
+        let tokens = self.process_file(source, file_name, path)?;
+        Ok(self.emit(tokens))
+    }
+
+    fn error(&self, location: Location, message: String) -> CompilerError {
+        CompilerError::PreprocessError(self.source_location(location), message)
+    }
+
+    fn source_location(&self, location: Location) -> SourceLocation {
+        SourceLocation {
+            file: self.files[location.file].clone(),
+            line: location.line,
+            column: location.column,
+            text: self.texts[location.file].line(location.line).to_string(),
+        }
+    }
+
+    fn process_file(
+        &mut self,
+        source: &str,
+        file_name: &str,
+        path: Option<&Path>,
+    ) -> Result<Vec<Token>, CompilerError> {
+        let mut file = self.files.len();
+        self.files.push(file_name.to_string());
+        self.texts.push(FileText {
+            text: source.into(),
+            line_offset: 0,
+        });
+        let source = strip_comments(source, file_name)?;
+        let tokens = tokenize(&source, file);
+
+        let mut output = Vec::new();
+        // Lines of text which are not expanded yet
+        let mut pending: Vec<Token> = Vec::new();
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        // The difference between the line numbers given by #line and the actual ones
+        let mut line_offset: i64 = 0;
+        let mut index = 0;
+        while index < tokens.len() {
+            let mut end = index + 1;
+            while end < tokens.len() && !tokens[end].at_line_start {
+                end += 1;
+            }
+            let mut line = tokens[index..end].to_vec();
+            index = end;
+            for token in line.iter_mut() {
+                token.location.file = file;
+                token.location.line = (token.location.line as i64 + line_offset) as u32;
+            }
+            let active = conditionals.last().is_none_or(|c| c.active);
+            if !line[0].is_punctuator("#") {
+                if active {
+                    pending.append(&mut line);
+                }
+                continue;
+            }
+            let directive = match line.get(1) {
+                Some(directive) => directive.clone(),
+                // The null directive
+                None => continue,
+            };
+            let arguments = &line[2..];
+            match directive.text.as_str() {
+                "if" | "ifdef" | "ifndef" => {
+                    let condition = if !active {
+                        false
+                    } else if directive.text == "if" {
+                        self.evaluate_condition(arguments, directive.location, path)?
+                    } else {
+                        match arguments.first() {
+                            Some(name) if name.kind == TokenKind::Identifier => {
+                                self.macros.contains_key(&name.text) == (directive.text == "ifdef")
+                            }
+                            _ => {
+                                return Err(self.error(
+                                    directive.location,
+                                    format!("no macro name given in #{} directive", directive.text),
+                                ))
+                            }
+                        }
+                    };
+                    conditionals.push(Conditional {
+                        active: condition,
+                        done: condition || !active,
+                        has_else: false,
+                        location: directive.location,
+                    });
+                }
+                "elif" | "else" => {
+                    let conditional = match conditionals.last() {
+                        Some(conditional) if !conditional.has_else => conditional,
+                        Some(_) => {
+                            return Err(self.error(
+                                directive.location,
+                                format!("#{} after #else", directive.text),
+                            ))
+                        }
+                        None => {
+                            return Err(self.error(
+                                directive.location,
+                                format!("#{} without #if", directive.text),
+                            ))
+                        }
+                    };
+                    let condition = if conditional.done {
+                        false
+                    } else if directive.text == "elif" {
+                        self.evaluate_condition(arguments, directive.location, path)?
+                    } else {
+                        true
+                    };
+                    let conditional = conditionals.last_mut().unwrap();
+                    conditional.active = condition;
+                    conditional.done |= condition;
+                    conditional.has_else = directive.text == "else";
+                }
+                "endif" => {
+                    if conditionals.pop().is_none() {
+                        return Err(
+                            self.error(directive.location, "#endif without #if".to_string())
+                        );
+                    }
+                }
+                // Other directives in skipped groups are ignored
+                _ if !active => (),
+                "line" => {
+                    let arguments = self.expand(arguments.to_vec())?;
+                    let line_number = match arguments.first() {
+                        Some(t)
+                            if t.kind == TokenKind::Number
+                                && t.text.chars().all(|c| c.is_ascii_digit()) =>
+                        {
+                            // The range of line numbers is that of the C standard
+                            match t.text.parse::<i64>() {
+                                Ok(line_number) if line_number <= i32::MAX as i64 => line_number,
+                                _ => {
+                                    return Err(self.error(
+                                        t.location,
+                                        format!("line number {} out of range in #line", t.text),
+                                    ))
+                                }
+                            }
+                        }
+                        _ => {
+                            return Err(self.error(
+                                directive.location,
+                                "#line directive requires a positive integer argument".to_string(),
+                            ))
+                        }
+                    };
+                    // The next line has the given number
+                    let actual_line = directive.location.line as i64 - line_offset + 1;
+                    line_offset = line_number - actual_line;
+                    let name = match arguments.get(1) {
+                        Some(name) if name.kind != TokenKind::StringLiteral => {
+                            return Err(self.error(
+                                directive.location,
+                                format!("invalid filename \"{}\" in #line", name.text),
+                            ));
+                        }
+                        Some(name) => name.text[1..name.text.len() - 1].to_string(),
+                        None => self.files[file].clone(),
+                    };
+                    self.texts.push(FileText {
+                        text: self.texts[file].text.clone(),
+                        line_offset,
+                    });
+                    file = self.files.len();
+                    self.files.push(name);
+                }
+                _ => {
+                    output.append(&mut self.expand(std::mem::take(&mut pending))?);
+                    self.process_directive(&line, path, &mut output)?;
+                }
+            }
+        }
+        if let Some(conditional) = conditionals.last() {
+            return Err(self.error(
+                conditional.location,
+                "unterminated conditional directive".to_string(),
+            ));
+        }
+        output.append(&mut self.expand(pending)?);
+        Ok(output)
+    }
+
+    // Evaluate the expression of #if or #elif
+    fn evaluate_condition(
+        &self,
+        arguments: &[Token],
+        location: Location,
+        path: Option<&Path>,
+    ) -> Result<bool, CompilerError> {
+        // `defined` and `__has_include` are evaluated before macros are expanded
+        let mut tokens = Vec::new();
+        let mut index = 0;
+        while index < arguments.len() {
+            let token = &arguments[index];
+            index += 1;
+            if token.kind != TokenKind::Identifier
+                || (token.text != "defined" && token.text != "__has_include")
+            {
+                tokens.push(token.clone());
+                continue;
+            }
+            let parenthesized = arguments.get(index).is_some_and(|t| t.is_punctuator("("));
+            let end = if parenthesized {
+                match arguments[index..].iter().position(|t| t.is_punctuator(")")) {
+                    Some(position) => index + position,
+                    None => {
+                        return Err(self.error(
+                            token.location,
+                            format!("missing ')' after \"{}\"", token.text),
+                        ))
+                    }
+                }
+            } else {
+                index + 1
+            };
+            let operand = &arguments[(index + parenthesized as usize).min(end)..end];
+            let value = if token.text == "defined" {
+                match operand {
+                    [name] if name.kind == TokenKind::Identifier => {
+                        self.macros.contains_key(&name.text) || name.text == "__has_include"
+                    }
+                    _ => {
+                        return Err(self.error(
+                            token.location,
+                            "operator \"defined\" requires an identifier".to_string(),
+                        ))
+                    }
+                }
+            } else {
+                if !parenthesized {
+                    return Err(self.error(
+                        token.location,
+                        "missing '(' after \"__has_include\"".to_string(),
+                    ));
+                }
+                let (header_name, is_quoted) = self.header_name(operand, token.location)?;
+                self.find_header(&header_name, is_quoted, path).is_some()
+            };
+            let mut value_token = token.clone();
+            value_token.kind = TokenKind::Number;
+            value_token.text = (value as i64).to_string();
+            tokens.push(value_token);
+            index = end + parenthesized as usize;
+        }
+        let tokens = self.expand(tokens)?;
+        ExpressionEvaluator::new(&tokens)
+            .evaluate()
+            .map_err(|message| self.error(location, message))
+    }
+
+    // Process a line beginning with `#`
+    fn process_directive(
+        &mut self,
+        line: &[Token],
+        path: Option<&Path>,
+        output: &mut Vec<Token>,
+    ) -> Result<(), CompilerError> {
+        let directive = match line.get(1) {
+            Some(directive) => directive,
+            // The null directive
+            None => return Ok(()),
+        };
+        let arguments = &line[2..];
+        match directive.text.as_str() {
+            "include" => {
+                let mut included = self.include(arguments, directive.location, path)?;
+                output.append(&mut included);
+            }
+            "define" => self.define(arguments, directive.location)?,
+            "undef" => match arguments.first() {
+                Some(name) if name.kind == TokenKind::Identifier => {
+                    self.macros.remove(&name.text);
+                }
+                _ => {
+                    return Err(self.error(
+                        directive.location,
+                        "macro name missing in #undef".to_string(),
+                    ))
+                }
+            },
+            "error" => {
+                return Err(self.error(directive.location, format!("#error {}", spell(arguments))))
+            }
+            "warning" => {
+                let mut warning = directive.clone();
+                warning.kind = TokenKind::WarningDirective;
+                warning.text = format!("#warning {}", spell(arguments));
+                output.push(warning);
+            }
+            "pragma" => match arguments {
+                [once, ..] if once.text == "once" => {
+                    if let Some(path) = path {
+                        self.included_once.insert(path.to_path_buf());
+                    }
+                }
+                [namespace, diagnostic, rest @ ..]
+                    if (namespace.text == "GCC" || namespace.text == "clang")
+                        && diagnostic.text == "diagnostic" =>
+                {
+                    let mut pragma = diagnostic.clone();
+                    pragma.kind = TokenKind::DiagnosticPragma;
+                    pragma.text = spell(rest);
+                    output.push(pragma);
+                }
+                // Other pragmas are ignored
+                _ => (),
+            },
+            _ => {
+                return Err(self.error(
+                    directive.location,
+                    format!("invalid preprocessing directive #{}", directive.text),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, arguments: &[Token], location: Location) -> Result<(), CompilerError> {
+        let name = match arguments.first() {
+            Some(name) if name.kind == TokenKind::Identifier => name,
+            _ => return Err(self.error(location, "macro name missing in #define".to_string())),
+        };
+        // A function-like macro has `(` right after its name
+        let (parameters, variadic, replacement) = if arguments
+            .get(1)
+            .is_some_and(|t| t.is_punctuator("(") && !t.has_space)
+        {
+            let mut parameters = Vec::new();
+            let mut variadic = false;
+            let mut index = 2;
+            loop {
+                match arguments.get(index) {
+                    Some(t) if t.is_punctuator(")") && parameters.is_empty() && !variadic => break,
+                    Some(t) if t.is_punctuator("...") => variadic = true,
+                    Some(t) if t.kind == TokenKind::Identifier && !variadic => {
+                        parameters.push(t.text.clone())
+                    }
+                    _ => {
+                        return Err(self.error(
+                            name.location,
+                            format!("invalid parameter list of macro {}", name.text),
+                        ))
+                    }
+                }
+                index += 1;
+                match arguments.get(index) {
+                    Some(t) if t.is_punctuator(")") => break,
+                    Some(t) if t.is_punctuator(",") && !variadic => index += 1,
+                    _ => {
+                        return Err(self.error(
+                            name.location,
+                            format!("invalid parameter list of macro {}", name.text),
+                        ))
+                    }
+                }
+            }
+            (Some(parameters), variadic, arguments[index + 1..].to_vec())
+        } else {
+            (None, false, arguments[1..].to_vec())
+        };
+        let defined = Macro {
+            parameters,
+            variadic,
+            replacement,
+        };
+
+        // `#` must be followed by a parameter, and `##` cannot be at either end
+        let replacement = &defined.replacement;
+        for (index, token) in replacement.iter().enumerate() {
+            if token.is_punctuator("#")
+                && defined.parameters.is_some()
+                && replacement
+                    .get(index + 1)
+                    .and_then(|t| defined.parameter_index(t))
+                    .is_none()
+            {
+                return Err(self.error(
+                    token.location,
+                    "'#' is not followed by a macro parameter".to_string(),
+                ));
+            }
+            if token.is_punctuator("##") && (index == 0 || index == replacement.len() - 1) {
+                return Err(self.error(
+                    token.location,
+                    "'##' cannot appear at either end of a macro expansion".to_string(),
+                ));
+            }
+        }
+        self.macros.insert(name.text.clone(), defined);
+        Ok(())
+    }
+
+    fn include(
+        &mut self,
+        arguments: &[Token],
+        location: Location,
+        path: Option<&Path>,
+    ) -> Result<Vec<Token>, CompilerError> {
+        // The header name may be given by macros
+        let arguments = match arguments.first() {
+            Some(t) if t.kind == TokenKind::Identifier => self.expand(arguments.to_vec())?,
+            _ => arguments.to_vec(),
+        };
+        let (header_name, is_quoted) = self.header_name(&arguments, location)?;
+        let header_path = match self.find_header(&header_name, is_quoted, path) {
+            Some(header_path) => header_path,
+            None => {
+                return Err(self.error(location, format!("{}: No such file", header_name)));
+            }
+        };
+        let canonical_path = fs::canonicalize(&header_path).unwrap_or(header_path.clone());
+        if self.included_once.contains(&canonical_path) {
+            return Ok(Vec::new());
+        }
+        if self.include_depth >= MAX_INCLUDE_DEPTH {
+            return Err(self.error(location, "#include nested too deeply".to_string()));
+        }
+        let source = match fs::read_to_string(&header_path) {
+            Ok(source) => source,
+            Err(e) => return Err(self.error(location, format!("{}: {}", header_name, e))),
+        };
+        self.include_depth += 1;
+        let result = self.process_file(
+            &source,
+            &header_path.to_string_lossy(),
+            Some(&canonical_path),
+        );
+        self.include_depth -= 1;
+        result
+    }
+
+    // Returns the name of the header given as `"name"` or `<name>`,
+    // and whether it is quoted
+    fn header_name(
+        &self,
+        arguments: &[Token],
+        location: Location,
+    ) -> Result<(String, bool), CompilerError> {
+        match arguments.first() {
+            Some(t) if t.kind == TokenKind::StringLiteral => {
+                Ok((t.text[1..t.text.len() - 1].to_string(), true))
+            }
+            Some(t) if t.is_punctuator("<") => {
+                let mut header_name = String::new();
+                for t in arguments[1..].iter() {
+                    if t.is_punctuator(">") {
+                        return Ok((header_name, false));
+                    }
+                    if t.has_space && !header_name.is_empty() {
+                        header_name.push(' ');
+                    }
+                    header_name.push_str(&t.text);
+                }
+                Err(self.error(location, "missing terminating > character".to_string()))
+            }
+            _ => Err(self.error(
+                location,
+                "#include expects \"FILENAME\" or <FILENAME>".to_string(),
+            )),
+        }
+    }
+
+    // Search for a header.
+    // Quoted headers are searched in the directory of the including file first.
+    fn find_header(
+        &self,
+        header_name: &str,
+        is_quoted: bool,
+        path: Option<&Path>,
+    ) -> Option<PathBuf> {
+        let mut candidates = Vec::new();
+        if is_quoted {
+            let directory = match path.and_then(|path| path.parent()) {
+                Some(directory) => directory.to_path_buf(),
+                None => PathBuf::new(),
+            };
+            candidates.push(directory.join(header_name));
+        }
+        for include_path in self.include_paths.iter() {
+            candidates.push(include_path.join(header_name));
+        }
+        candidates.into_iter().find(|c| c.is_file())
+    }
+
+    // Expand macros in the tokens, rescanning the result of each expansion.
+    // Each token has the set of macros which must not be expanded in it,
+    // so that recursive macros terminate (Prosser's algorithm).
+    fn expand(&self, tokens: Vec<Token>) -> Result<Vec<Token>, CompilerError> {
+        let mut input: VecDeque<Token> = tokens.into();
+        let mut output = Vec::new();
+        // The token following an expansion is separated by a space
+        // so that it is not joined with the expansion
+        let mut needs_space = false;
+        while let Some(mut token) = input.pop_front() {
+            let defined = match self.macros.get(&token.text) {
+                Some(defined)
+                    if token.kind == TokenKind::Identifier
+                        && !token.hide_set.contains(&token.text) =>
+                {
+                    defined
+                }
+                _ => {
+                    token.has_space |= needs_space;
+                    needs_space = false;
+                    output.push(token);
+                    continue;
+                }
+            };
+            let mut expansion = match defined.parameters {
+                None if token.text == "__LINE__" || token.text == "__FILE__" => {
+                    let mut replacement = token.clone();
+                    if token.text == "__LINE__" {
+                        replacement.kind = TokenKind::Number;
+                        replacement.text = token.location.line.to_string();
+                    } else {
+                        replacement.kind = TokenKind::StringLiteral;
+                        replacement.text = format!("{:?}", self.files[token.location.file]);
+                    }
+                    vec![replacement]
+                }
+                None => {
+                    let mut hide_set = token.hide_set.clone();
+                    hide_set.insert(token.text.clone());
+                    self.substitute(defined, &[], hide_set, token.location)?
+                }
+                Some(_) => {
+                    // The name of a function-like macro without arguments is not expanded
+                    if !input.front().is_some_and(|t| t.is_punctuator("(")) {
+                        token.has_space |= needs_space;
+                        needs_space = false;
+                        output.push(token);
+                        continue;
+                    }
+                    let (arguments, right_paren) = self.collect_arguments(&token, &mut input)?;
+                    let mut hide_set: HashSet<String> = token
+                        .hide_set
+                        .intersection(&right_paren.hide_set)
+                        .cloned()
+                        .collect();
+                    hide_set.insert(token.text.clone());
+                    self.substitute(defined, &arguments, hide_set, token.location)?
+                }
+            };
+            for (index, replacement) in expansion.iter_mut().enumerate() {
+                replacement.at_line_start = index == 0 && token.at_line_start;
+                if index == 0 {
+                    replacement.has_space = true;
+                }
+            }
+            for replacement in expansion.into_iter().rev() {
+                input.push_front(replacement);
+            }
+            needs_space = true;
+        }
+        Ok(output)
+    }
+
+    // Collect the arguments of a function-like macro call up to the matching `)`.
+    // Returns the arguments and the `)`.
+    fn collect_arguments(
+        &self,
+        name: &Token,
+        input: &mut VecDeque<Token>,
+    ) -> Result<(Vec<Vec<Token>>, Token), CompilerError> {
+        let defined = &self.macros[&name.text];
+        let parameter_count = defined.parameters.as_ref().map_or(0, |p| p.len());
+        input.pop_front();
+        let mut arguments = vec![Vec::new()];
+        let mut depth = 0;
+        let right_paren = loop {
+            let token = match input.pop_front() {
+                Some(token) => token,
+                None => {
+                    return Err(self.error(
+                        name.location,
+                        format!("unterminated argument list invoking macro {}", name.text),
+                    ))
+                }
+            };
+            if token.is_punctuator(")") && depth == 0 {
+                break token;
+            }
+            if token.is_punctuator("(") {
+                depth += 1;
+            } else if token.is_punctuator(")") {
+                depth -= 1;
+            }
+            // Commas in the variable arguments are a part of __VA_ARGS__
+            if token.is_punctuator(",")
+                && depth == 0
+                && !(defined.variadic && arguments.len() > parameter_count)
+            {
+                arguments.push(Vec::new());
+                continue;
+            }
+            arguments.last_mut().unwrap().push(token);
+        };
+        // `F()` passes no arguments to a macro without parameters
+        if parameter_count == 0 && arguments.len() == 1 && arguments[0].is_empty() {
+            arguments.clear();
+        }
+        if defined.variadic && arguments.len() == parameter_count {
+            arguments.push(Vec::new());
+        }
+        let expected = parameter_count + defined.variadic as usize;
+        if arguments.len() != expected {
+            return Err(self.error(
+                name.location,
+                format!(
+                    "macro {} requires {} arguments, but {} given",
+                    name.text,
+                    expected,
+                    arguments.len()
+                ),
+            ));
+        }
+        Ok((arguments, right_paren))
+    }
+
+    // Replace the parameters in the replacement list with the arguments,
+    // and add the hide set to the result.
+    // The tokens of the arguments keep their locations, and the others are at `location`
+    // where the macro is invoked.
+    fn substitute(
+        &self,
+        defined: &Macro,
+        arguments: &[Vec<Token>],
+        hide_set: HashSet<String>,
+        location: Location,
+    ) -> Result<Vec<Token>, CompilerError> {
+        let replacement = &defined.replacement;
+        let mut output: Vec<Token> = Vec::new();
+        let mut index = 0;
+        while index < replacement.len() {
+            let token = &replacement[index];
+            let next = replacement.get(index + 1);
+            let parameter = defined.parameter_index(token);
+
+            // Stringification
+            if token.is_punctuator("#") && defined.parameters.is_some() {
+                let argument = &arguments[defined.parameter_index(next.unwrap()).unwrap()];
+                let mut stringized = token.clone();
+                stringized.location = location;
+                stringized.kind = TokenKind::StringLiteral;
+                stringized.text = stringize(argument);
+                stringized.has_space = token.has_space;
+                output.push(stringized);
+                index += 2;
+                continue;
+            }
+
+            // Token pasting with the following token or argument
+            if token.is_punctuator("##") {
+                let next = next.unwrap();
+                let right = match defined.parameter_index(next) {
+                    Some(i) => arguments[i].clone(),
+                    None => vec![Token {
+                        location,
+                        ..next.clone()
+                    }],
+                };
+                // GNU extension: `, ## __VA_ARGS__` removes the comma if there are no variable arguments
+                if defined.variadic
+                    && next.text == "__VA_ARGS__"
+                    && output.last().is_some_and(|t| t.is_punctuator(","))
+                {
+                    if right.is_empty() {
+                        output.pop();
+                    } else {
+                        output.extend(right);
+                    }
+                    index += 2;
+                    continue;
+                }
+                let mut right = right.into_iter();
+                if let Some(first) = right.next() {
+                    match output.pop() {
+                        Some(left) => output.push(Token {
+                            location,
+                            ..self.paste(&left, &first)?
+                        }),
+                        None => output.push(first),
+                    }
+                }
+                output.extend(right);
+                index += 2;
+                continue;
+            }
+
+            match parameter {
+                // An argument followed by `##` is not expanded
+                Some(i) if next.is_some_and(|t| t.is_punctuator("##")) => {
+                    let mut argument = arguments[i].clone();
+                    if let Some(first) = argument.first_mut() {
+                        first.has_space = token.has_space;
+                    }
+                    output.extend(argument);
+                }
+                // Other arguments are fully expanded before substitution
+                Some(i) => {
+                    let mut argument = self.expand(arguments[i].clone())?;
+                    if let Some(first) = argument.first_mut() {
+                        first.has_space = token.has_space;
+                    }
+                    output.extend(argument);
+                }
+                None => output.push(Token {
+                    location,
+                    ..token.clone()
+                }),
+            }
+            index += 1;
+        }
+        for token in output.iter_mut() {
+            token.hide_set.extend(hide_set.iter().cloned());
+        }
+        Ok(output)
+    }
+
+    // Join two tokens into one with `##`
+    fn paste(&self, left: &Token, right: &Token) -> Result<Token, CompilerError> {
+        let text = format!("{}{}", left.text, right.text);
+        let mut tokens = tokenize(&text, left.location.file);
+        if tokens.len() != 1 {
+            return Err(self.error(
+                left.location,
+                format!(
+                    "pasting \"{}\" and \"{}\" does not give a valid preprocessing token",
+                    left.text, right.text
+                ),
+            ));
+        }
+        let mut token = tokens.pop().unwrap();
+        token.location = left.location;
+        token.has_space = left.has_space;
+        token.at_line_start = left.at_line_start;
+        token.hide_set = left.hide_set.clone();
+        Ok(token)
+    }
+
+    // Join the tokens into the preprocessed source.
+    // Tokens are placed at their original columns where possible.
+    fn emit(self, tokens: Vec<Token>) -> PreprocessedSource {
+        let mut text = String::new();
+        let mut locations = Vec::new();
+        let mut current_line = None;
+        let mut column = 1;
+        let mut diagnostic_pragmas = Vec::new();
+        let mut warning_directives = Vec::new();
+        for token in tokens.iter() {
+            match token.kind {
+                TokenKind::DiagnosticPragma => {
+                    diagnostic_pragmas.push((text.len(), token.text.clone()));
+                    continue;
+                }
+                TokenKind::WarningDirective => {
+                    let location = self.source_location(token.location);
+                    warning_directives.push((text.len(), location, token.text.clone()));
+                    continue;
+                }
+                _ => (),
+            }
+            let location = token.location;
+            if current_line != Some((location.file, location.line)) {
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                current_line = Some((location.file, location.line));
+                column = 1;
+            }
+            if location.column > column {
+                for _ in column..location.column {
+                    text.push(' ');
+                }
+                column = location.column;
+            } else if token.has_space {
+                text.push(' ');
+                column += 1;
+            }
+            locations.push((text.len(), location));
+            text.push_str(&token.text);
+            column += token.text.chars().count() as u32;
+        }
+        PreprocessedSource {
+            text,
+            source_map: SourceMap {
+                files: self.files,
+                texts: self.texts,
+                locations,
+            },
+            diagnostic_pragmas,
+            warning_directives,
+            tokens,
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use crate::ast::unescape;
+use crate::preprocessor::{Token, TokenKind};
+
+// Evaluates the controlling expression of `#if` and `#elif`.
+// `defined` and `__has_include` must be replaced, and macros must be expanded beforehand.
+// Remaining identifiers are evaluated as 0.
+pub struct ExpressionEvaluator<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+// A value of intmax_t or uintmax_t, which the integers in `#if` have.
+// The bits of an unsigned value are kept in `value`.
+#[derive(Clone, Copy)]
+struct Integer {
+    value: i64,
+    unsigned: bool,
+}
+
+impl Integer {
+    fn signed(value: i64) -> Integer {
+        Integer {
+            value,
+            unsigned: false,
+        }
+    }
+
+    fn is_true(self) -> bool {
+        self.value != 0
+    }
+}
+
+// Binary operators from the lowest precedence to the highest
+const BINARY_OPERATORS: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", ">", "<=", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+impl<'a> ExpressionEvaluator<'a> {
+    pub fn new(tokens: &'a [Token]) -> ExpressionEvaluator<'a> {
+        ExpressionEvaluator {
+            tokens,
+            position: 0,
+        }
+    }
+
+    /// Evaluate the whole expression, returning true if it is not zero
+    pub fn evaluate(mut self) -> Result<bool, String> {
+        if self.tokens.is_empty() {
+            return Err("#if with no expression".to_string());
+        }
+        let value = self.conditional(true)?;
+        match self.tokens.get(self.position) {
+            Some(token) => Err(format!(
+                "missing binary operator before token \"{}\"",
+                token.text
+            )),
+            None => Ok(value.is_true()),
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn consume(&mut self, text: &str) -> bool {
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::Punctuator && token.text == text => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        if self.consume(text) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}' in preprocessor expression", text))
+        }
+    }
+
+    // `evaluated` is false in the operands skipped by `&&`, `||` and `?:`,
+    // where division by zero is not an error.
+    // The result of `?:` is unsigned if either of the second and the third operands is.
+    fn conditional(&mut self, evaluated: bool) -> Result<Integer, String> {
+        let condition = self.binary(0, evaluated)?;
+        if !self.consume("?") {
+            return Ok(condition);
+        }
+        let then_value = self.conditional(evaluated && condition.is_true())?;
+        self.expect(":")?;
+        let else_value = self.conditional(evaluated && !condition.is_true())?;
+        let value = if condition.is_true() {
+            then_value
+        } else {
+            else_value
+        };
+        Ok(Integer {
+            value: value.value,
+            unsigned: then_value.unsigned || else_value.unsigned,
+        })
+    }
+
+    fn binary(&mut self, level: usize, evaluated: bool) -> Result<Integer, String> {
+        if level == BINARY_OPERATORS.len() {
+            return self.unary(evaluated);
+        }
+        let mut left = self.binary(level + 1, evaluated)?;
+        loop {
+            let operator = match self.peek() {
+                Some(token)
+                    if token.kind == TokenKind::Punctuator
+                        && BINARY_OPERATORS[level].contains(&token.text.as_str()) =>
+                {
+                    token.text.as_str()
+                }
+                _ => return Ok(left),
+            };
+            self.position += 1;
+            let right_evaluated = match operator {
+                "&&" => evaluated && left.is_true(),
+                "||" => evaluated && !left.is_true(),
+                _ => evaluated,
+            };
+            let right = self.binary(level + 1, right_evaluated)?;
+            left = evaluate_binary(operator, left, right, evaluated)?;
+        }
+    }
+
+    fn unary(&mut self, evaluated: bool) -> Result<Integer, String> {
+        if self.consume("+") {
+            self.unary(evaluated)
+        } else if self.consume("-") {
+            let operand = self.unary(evaluated)?;
+            Ok(Integer {
+                value: operand.value.wrapping_neg(),
+                ..operand
+            })
+        } else if self.consume("~") {
+            let operand = self.unary(evaluated)?;
+            Ok(Integer {
+                value: !operand.value,
+                ..operand
+            })
+        } else if self.consume("!") {
+            Ok(Integer::signed(!self.unary(evaluated)?.is_true() as i64))
+        } else {
+            self.primary(evaluated)
+        }
+    }
+
+    fn primary(&mut self, evaluated: bool) -> Result<Integer, String> {
+        if self.consume("(") {
+            let value = self.conditional(evaluated)?;
+            self.expect(")")?;
+            return Ok(value);
+        }
+        let token = match self.peek() {
+            Some(token) => token,
+            None => return Err("#if expression is incomplete".to_string()),
+        };
+        self.position += 1;
+        match token.kind {
+            TokenKind::Number => parse_integer(&token.text),
+            TokenKind::CharLiteral => {
+                let bytes = unescape(&token.text[1..token.text.len() - 1]);
+                Ok(Integer::signed(
+                    bytes.first().map_or(0, |byte| *byte as i8 as i64),
+                ))
+            }
+            TokenKind::Identifier => Ok(Integer::signed(0)),
+            _ => Err(format!(
+                "token \"{}\" is not valid in preprocessor expressions",
+                token.text
+            )),
+        }
+    }
+}
+
+// The operands of arithmetic, bitwise and relational operators are converted to uintmax_t
+// if either of them is unsigned. The result of a shift has the type of the left operand.
+fn evaluate_binary(
+    operator: &str,
+    left: Integer,
+    right: Integer,
+    evaluated: bool,
+) -> Result<Integer, String> {
+    let unsigned = left.unsigned || right.unsigned;
+    let (l, r) = (left.value, right.value);
+    let (ul, ur) = (l as u64, r as u64);
+    let value = match operator {
+        "||" => return Ok(Integer::signed((left.is_true() || right.is_true()) as i64)),
+        "&&" => return Ok(Integer::signed((left.is_true() && right.is_true()) as i64)),
+        "==" => return Ok(Integer::signed((l == r) as i64)),
+        "!=" => return Ok(Integer::signed((l != r) as i64)),
+        "<" | ">" | "<=" | ">=" => {
+            let ordering = if unsigned { ul.cmp(&ur) } else { l.cmp(&r) };
+            let result = match operator {
+                "<" => ordering.is_lt(),
+                ">" => ordering.is_gt(),
+                "<=" => ordering.is_le(),
+                _ => ordering.is_ge(),
+            };
+            return Ok(Integer::signed(result as i64));
+        }
+        "<<" => {
+            return Ok(Integer {
+                value: l.wrapping_shl(r as u32),
+                ..left
+            })
+        }
+        ">>" => {
+            let value = if left.unsigned {
+                ul.wrapping_shr(r as u32) as i64
+            } else {
+                l.wrapping_shr(r as u32)
+            };
+            return Ok(Integer { value, ..left });
+        }
+        "|" => l | r,
+        "^" => l ^ r,
+        "&" => l & r,
+        "+" => l.wrapping_add(r),
+        "-" => l.wrapping_sub(r),
+        "*" => l.wrapping_mul(r),
+        "/" | "%" if r == 0 => {
+            if evaluated {
+                return Err("division by zero in #if".to_string());
+            }
+            0
+        }
+        "/" if unsigned => (ul / ur) as i64,
+        "/" => l.wrapping_div(r),
+        _ if unsigned => (ul % ur) as i64,
+        _ => l.wrapping_rem(r),
+    };
+    Ok(Integer { value, unsigned })
+}
+
+// Parse an integer constant with an optional prefix and suffix.
+// It is unsigned with the suffix `u` or if it is too large for intmax_t.
+fn parse_integer(text: &str) -> Result<Integer, String> {
+    let digits = text.trim_end_matches(['u', 'U', 'l', 'L']);
+    let has_unsigned_suffix = text[digits.len()..].contains(['u', 'U']);
+    let lower = digits.to_ascii_lowercase();
+    let (digits, radix) = if let Some(hex) = lower.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        (binary, 2)
+    } else if lower.len() > 1 && lower.starts_with('0') {
+        (&lower[1..], 8)
+    } else {
+        (lower.as_str(), 10)
+    };
+    match u64::from_str_radix(digits, radix) {
+        Ok(value) => Ok(Integer {
+            value: value as i64,
+            unsigned: has_unsigned_suffix || value > i64::MAX as u64,
+        }),
+        Err(_) => Err(format!("invalid integer constant \"{}\" in #if", text)),
+    }
+}
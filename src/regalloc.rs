@@ -0,0 +1,177 @@
+use crate::assembly::Register;
+use crate::cfg::ControlFlowGraph;
+use crate::ir::*;
+use crate::liveness::Liveness;
+
+// Register allocation by linear scan over live intervals.
+// Instructions are numbered in the order of the blocks, and the instruction numbered n
+// reads its operands at 2n and assigns its destination at 2n + 1, so that an operand
+// and the destination can share a register. Parameters are assigned at 0.
+// A virtual register gets a single interval covering every point where it is live.
+
+// Registers which values can live in. rax, rdx, r10 and r11 are left as scratch registers
+// for instruction selection, which needs rax and rdx for division and return values.
+// Values live across a call only get callee-saved registers, which the callee preserves.
+pub const CALLER_SAVED_REGISTERS: &[Register] = &[
+    Register::RCX,
+    Register::RSI,
+    Register::RDI,
+    Register::R8,
+    Register::R9,
+];
+pub const CALLEE_SAVED_REGISTERS: &[Register] = &[
+    Register::RBX,
+    Register::R12,
+    Register::R13,
+    Register::R14,
+    Register::R15,
+];
+
+// Where the value of a virtual register lives
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Location {
+    Register(Register),
+    // The index of a spill slot of 8 bytes in the stack frame
+    Spill(usize),
+}
+
+pub struct Allocation {
+    // Indexed by `VirtualRegister`, where None is a register which is never assigned nor read
+    pub locations: Vec<Option<Location>>,
+    pub spill_count: usize,
+    // The callee-saved registers used, which the function saves on entry and restores on return
+    pub callee_saved_registers: Vec<Register>,
+}
+
+struct Interval {
+    register: VirtualRegister,
+    start: usize,
+    end: usize,
+    crosses_call: bool,
+}
+
+impl Allocation {
+    pub fn new(function: &Function) -> Allocation {
+        let mut intervals = live_intervals(function);
+        intervals.sort_by_key(|interval| (interval.start, interval.register));
+        let mut allocation = Allocation {
+            locations: vec![None; function.registers.len()],
+            spill_count: 0,
+            callee_saved_registers: Vec::new(),
+        };
+        // The intervals holding a register, which end at the first element
+        let mut active: Vec<(usize, VirtualRegister, Register)> = Vec::new();
+        for interval in intervals.iter() {
+            active.retain(|(end, _, _)| *end >= interval.start);
+            let candidates: Vec<Register> = if interval.crosses_call {
+                CALLEE_SAVED_REGISTERS.to_vec()
+            } else {
+                [CALLER_SAVED_REGISTERS, CALLEE_SAVED_REGISTERS].concat()
+            };
+            let free = candidates
+                .iter()
+                .find(|candidate| active.iter().all(|(_, _, used)| used != *candidate));
+            let assigned = match free {
+                Some(free) => Some(*free),
+                None => {
+                    // Spill the interval ending last, whose register is free for longest
+                    let victim = active
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, (_, _, used))| candidates.contains(used))
+                        .max_by_key(|(_, (end, _, _))| *end)
+                        .map(|(index, (end, _, _))| (index, *end));
+                    match victim {
+                        Some((index, end)) if end > interval.end => {
+                            let (_, victim_register, used) = active.remove(index);
+                            allocation.spill(victim_register);
+                            Some(used)
+                        }
+                        _ => None,
+                    }
+                }
+            };
+            match assigned {
+                Some(used) => {
+                    allocation.locations[interval.register.0] = Some(Location::Register(used));
+                    if CALLEE_SAVED_REGISTERS.contains(&used)
+                        && !allocation.callee_saved_registers.contains(&used)
+                    {
+                        allocation.callee_saved_registers.push(used);
+                    }
+                    active.push((interval.end, interval.register, used));
+                }
+                None => allocation.spill(interval.register),
+            }
+        }
+        allocation
+    }
+
+    fn spill(&mut self, register: VirtualRegister) {
+        self.locations[register.0] = Some(Location::Spill(self.spill_count));
+        self.spill_count += 1;
+    }
+}
+
+fn live_intervals(function: &Function) -> Vec<Interval> {
+    let cfg = ControlFlowGraph::new(function);
+    let liveness = Liveness::new(function, &cfg);
+    let mut ranges: Vec<Option<(usize, usize)>> = vec![None; function.registers.len()];
+    let mut extend = |register: VirtualRegister, point: usize| {
+        let range = ranges[register.0].get_or_insert((point, point));
+        range.0 = range.0.min(point);
+        range.1 = range.1.max(point);
+    };
+    for parameter in function.parameters.iter() {
+        if let Parameter::Scalar(register) = parameter {
+            extend(*register, 0);
+        }
+    }
+    let mut calls = Vec::new();
+    let mut number = 1;
+    for (index, block) in function.blocks.iter().enumerate() {
+        for register in liveness.live_in[index].iter() {
+            extend(*register, 2 * number);
+        }
+        for instruction in block.instructions.iter() {
+            for operand in instruction.operands() {
+                if let Value::Register(register) = operand {
+                    extend(register, 2 * number);
+                }
+            }
+            if let Some(destination) = instruction.destination() {
+                extend(destination, 2 * number + 1);
+            }
+            if let Instruction::Call { result, .. } = instruction {
+                calls.push(number);
+                // An aggregate result is stored to its address after the call
+                if let CallResult::Aggregate(Value::Register(register), _) = result {
+                    extend(*register, 2 * number + 1);
+                }
+            }
+            number += 1;
+        }
+        for operand in block.terminator.operands() {
+            if let Value::Register(register) = operand {
+                extend(register, 2 * number);
+            }
+        }
+        for register in liveness.live_out[index].iter() {
+            extend(*register, 2 * number + 1);
+        }
+        number += 1;
+    }
+    ranges
+        .iter()
+        .enumerate()
+        .filter_map(|(register, range)| {
+            let (start, end) = (*range)?;
+            Some(Interval {
+                register: VirtualRegister(register),
+                start,
+                end,
+                crosses_call: calls.iter().any(|call| start <= 2 * call && 2 * call < end),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,1147 @@
+use crate::ast::*;
+use crate::compile_error::CompilerError;
+use crate::diagnostic::{Diagnostic, Label, Severity};
+use crate::initializer::*;
+use crate::layout::Layouts;
+use crate::typed_ast::*;
+use crate::warning::*;
+use std::collections::HashMap;
+
+// The function being analyzed
+struct FunctionContext {
+    return_type: DataType,
+    variadic: bool,
+    // The index of the scope of the parameters. The scopes below it are of main,
+    // and those above it are of the blocks in the function.
+    parameter_scope: usize,
+}
+
+struct Sema {
+    symbols: Vec<Symbol>,
+    // The local variables of main, which consists of the top level statements,
+    // and of the function being analyzed, with a scope for each block
+    scopes: Vec<HashMap<String, SymbolId>>,
+    globals: HashMap<String, SymbolId>,
+    functions: HashMap<String, SymbolId>,
+    layouts: Layouts,
+    function: Option<FunctionContext>,
+    // Errors reported so far, which are collected to report as many as possible at once
+    errors: Vec<CompilerError>,
+    warning_options: WarningOptions,
+    warnings: Vec<Diagnostic>,
+}
+
+/// Resolve the names in the program to symbols and compute the types of all the expressions,
+/// or return all the errors found in it. Warnings are appended to `warnings`,
+/// and the program is rejected if any of them is an error.
+pub fn analyze(
+    program: &Program,
+    warning_options: WarningOptions,
+    warnings: &mut Vec<Diagnostic>,
+) -> Result<TypedProgram, Vec<CompilerError>> {
+    let mut sema = Sema {
+        symbols: Vec::new(),
+        scopes: vec![HashMap::new()],
+        globals: HashMap::new(),
+        functions: HashMap::new(),
+        layouts: Layouts::default(),
+        function: None,
+        errors: Vec::new(),
+        warning_options,
+        warnings: Vec::new(),
+    };
+    let mut units = Vec::new();
+    for program_unit in program.program_units.iter() {
+        match sema.program_unit(program_unit) {
+            Ok(Some(unit)) => units.push(unit),
+            Ok(None) => (),
+            Err(e) => {
+                sema.errors.push(e);
+                // Leave the function where the error occurred to analyze the rest
+                sema.scopes.truncate(1);
+                sema.function = None;
+            }
+        }
+    }
+    // Top level statements and initializers which are not constant form main,
+    // which conflicts with a main defined in the program
+    let defines_main = units.iter().any(|unit| match unit {
+        TypedUnit::Function(function) => sema.symbols[function.symbol.0].name == "main",
+        TypedUnit::Statement(_) => false,
+    });
+    let statements = units.iter().filter_map(|unit| match unit {
+        TypedUnit::Statement(statement) if defines_main => Some(statement),
+        _ => None,
+    });
+    for statement in statements {
+        let error = match &statement.kind {
+            TypedStatementKind::VarDef(symbol, Some(initializer))
+                if initializer.image.is_none() =>
+            {
+                CompilerError::InvalidInitializer(format!(
+                    "initializer of global variable {} is not constant",
+                    sema.symbols[symbol.0].name
+                ))
+            }
+            TypedStatementKind::VarDef(_, _) => continue,
+            _ => CompilerError::TopLevelStatementWithMain,
+        };
+        sema.errors.push(error.at(statement.span));
+    }
+    let has_warning_errors = sema
+        .warnings
+        .iter()
+        .any(|warning| warning.severity == Severity::Error);
+    warnings.append(&mut sema.warnings);
+    if !sema.errors.is_empty() || has_warning_errors {
+        return Err(sema.errors);
+    }
+    Ok(TypedProgram {
+        units,
+        symbols: sema.symbols,
+        layouts: sema.layouts,
+    })
+}
+
+fn typed(kind: TypedExprKind, data_type: DataType, span: Span) -> TypedExpr {
+    TypedExpr {
+        kind,
+        data_type,
+        span,
+    }
+}
+
+fn convert(conversion: Conversion, data_type: DataType, expr: TypedExpr) -> TypedExpr {
+    let span = expr.span;
+    typed(
+        TypedExprKind::Convert(conversion, Box::new(expr)),
+        data_type,
+        span,
+    )
+}
+
+fn binary(
+    operator: BinaryOperator,
+    data_type: DataType,
+    left: TypedExpr,
+    right: TypedExpr,
+) -> TypedExpr {
+    let span = Span {
+        start: left.span.start,
+        end: right.span.end,
+    };
+    typed(
+        TypedExprKind::Binary(operator, Box::new(left), Box::new(right)),
+        data_type,
+        span,
+    )
+}
+
+// An array is converted to a pointer to its first element,
+// and a function designator to a pointer to the function
+fn decay_value(expr: TypedExpr) -> TypedExpr {
+    match &expr.data_type {
+        DataType::Array(_, _) => convert(Conversion::ArrayToPointer, decay(&expr.data_type), expr),
+        DataType::Function(_, _, _) => convert(
+            Conversion::FunctionToPointer,
+            pointer(1, expr.data_type.clone()),
+            expr,
+        ),
+        _ => expr,
+    }
+}
+
+// char is promoted to int in arithmetic
+fn promote(expr: TypedExpr) -> TypedExpr {
+    if expr.data_type == char() {
+        convert(Conversion::IntegerPromotion, int(), expr)
+    } else {
+        expr
+    }
+}
+
+fn is_integer(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Primitive(PrimitiveType::Int | PrimitiveType::Char)
+    )
+}
+
+fn is_scalar(data_type: &DataType) -> bool {
+    is_integer(data_type) || matches!(data_type, DataType::Pointer(_, _))
+}
+
+fn is_aggregate(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Struct(_)
+            | DataType::Union(_)
+            | DataType::Array(_, _)
+            | DataType::Primitive(PrimitiveType::VaList)
+    )
+}
+
+// Both operands of arithmetic are promoted and must be integers of the same type
+fn arithmetic(
+    operator: BinaryOperator,
+    left: TypedExpr,
+    right: TypedExpr,
+) -> Result<TypedExpr, CompilerError> {
+    let (left, right) = (promote(require_type(left)?), promote(require_type(right)?));
+    if left.data_type != right.data_type {
+        return Err(CompilerError::MismatchedOperands(
+            left.data_type,
+            right.data_type,
+        ));
+    }
+    if !is_integer(&left.data_type) {
+        let operator = match operator {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Sub => "-",
+            BinaryOperator::Mul => "*",
+            _ => "/",
+        };
+        return Err(CompilerError::InvalidOperand(
+            operator.to_string(),
+            left.data_type,
+        ));
+    }
+    let data_type = left.data_type.clone();
+    Ok(binary(operator, data_type, left, right))
+}
+
+// A pointer plus or minus an integer is a pointer, and the difference of two pointers is an int
+fn additive(
+    operator: BinaryOperator,
+    left: TypedExpr,
+    right: TypedExpr,
+) -> Result<TypedExpr, CompilerError> {
+    let (left, right) = (require_type(left)?, require_type(right)?);
+    if !is_scalar(&decay(&left.data_type)) {
+        let operator = if operator == BinaryOperator::Add {
+            "+"
+        } else {
+            "-"
+        };
+        return Err(CompilerError::InvalidOperand(
+            operator.to_string(),
+            left.data_type,
+        ));
+    }
+    let (left, right) = (decay_value(left), decay_value(right));
+    match (&left.data_type, &right.data_type) {
+        (DataType::Pointer(_, _), DataType::Pointer(_, _))
+            if operator == BinaryOperator::Sub && left.data_type == right.data_type =>
+        {
+            Ok(binary(operator, int(), left, right))
+        }
+        (DataType::Pointer(_, _), right_type) if is_integer(right_type) => {
+            let data_type = left.data_type.clone();
+            Ok(binary(operator, data_type, left, promote(right)))
+        }
+        _ => arithmetic(operator, left, right),
+    }
+}
+
+// *pointer, whose operand is converted to a pointer if it is an array
+// A variable, a dereference, an element of an array or a member designates an object,
+// or a function in the case of a variable or a dereference
+fn is_lvalue(expr: &TypedExpr) -> bool {
+    matches!(
+        expr.kind,
+        TypedExprKind::Variable(_)
+            | TypedExprKind::Deref(_)
+            | TypedExprKind::Index(_, _)
+            | TypedExprKind::Member(_, _, _)
+            | TypedExprKind::PointerMember(_, _, _)
+    )
+}
+
+fn deref(operand: TypedExpr) -> Result<TypedExpr, CompilerError> {
+    let operand = decay_value(operand);
+    let data_type = match &operand.data_type {
+        DataType::Pointer(1, data_type) => *data_type.clone(),
+        DataType::Pointer(depth, data_type) => pointer(depth - 1, *data_type.clone()),
+        // An integer holding an address is dereferenced as a pointer to int
+        data_type if is_integer(data_type) => int(),
+        data_type => {
+            return Err(CompilerError::InvalidDereference(data_type.clone()).at(operand.span));
+        }
+    };
+    let span = operand.span;
+    Ok(typed(
+        TypedExprKind::Deref(Box::new(operand)),
+        data_type,
+        span,
+    ))
+}
+
+// The result of dereferencing an integer is stored or discarded as int,
+// but it has no type to be an operand or an argument
+fn require_type(expr: TypedExpr) -> Result<TypedExpr, CompilerError> {
+    if let TypedExprKind::Deref(operand) = &expr.kind {
+        if is_integer(&operand.data_type) {
+            return Err(
+                CompilerError::InvalidDereference(operand.data_type.clone()).at(operand.span)
+            );
+        }
+    }
+    Ok(expr)
+}
+
+// Array parameters are adjusted to pointers. va_list is an array type,
+// so a va_list parameter is a pointer to the va_list passed.
+fn adjust_parameter(data_type: &DataType) -> DataType {
+    match data_type {
+        DataType::Primitive(PrimitiveType::VaList) => pointer(1, data_type.clone()),
+        _ => decay(data_type),
+    }
+}
+
+// va_list is an array type, so it is passed as a pointer
+fn pass_va_list(expr: TypedExpr) -> TypedExpr {
+    if expr.data_type == DataType::Primitive(PrimitiveType::VaList) {
+        let data_type = pointer(1, expr.data_type.clone());
+        convert(Conversion::ArrayToPointer, data_type, expr)
+    } else {
+        expr
+    }
+}
+
+// The return type, the parameter types and whether it is variadic
+// of the function called through a function designator or a function pointer
+fn called_function(callee_type: &DataType) -> Result<(DataType, &[DataType], bool), CompilerError> {
+    let function_type = match callee_type {
+        DataType::Pointer(1, data_type) => data_type,
+        _ => callee_type,
+    };
+    match function_type {
+        DataType::Function(return_type, parameter_types, variadic) => {
+            Ok((*return_type.clone(), parameter_types, *variadic))
+        }
+        _ => Err(CompilerError::NotCallable(callee_type.clone())),
+    }
+}
+
+impl Sema {
+    fn add_symbol(
+        &mut self,
+        name: &str,
+        kind: SymbolKind,
+        data_type: DataType,
+        declared_at: Span,
+    ) -> SymbolId {
+        self.symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            data_type,
+            declared_at,
+        });
+        SymbolId(self.symbols.len() - 1)
+    }
+
+    // Define a local variable in the current scope, which replaces one with the same name
+    fn define_local(
+        &mut self,
+        name: &str,
+        data_type: &DataType,
+        kind: SymbolKind,
+        declared_at: Span,
+    ) -> SymbolId {
+        let symbol = self.add_symbol(name, kind, data_type.clone(), declared_at);
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), symbol);
+        symbol
+    }
+
+    fn declare_function(&mut self, name: &str, function_type: DataType) -> SymbolId {
+        let symbol = self.add_symbol(name, SymbolKind::Function, function_type, Span::default());
+        self.functions.insert(name.to_string(), symbol);
+        symbol
+    }
+
+    // Declare a variable with extern, which is visible at the top level even if it is declared
+    // in a function. A definition in this file takes precedence.
+    fn declare_extern(&mut self, name: &str, data_type: &DataType, declared_at: Span) -> SymbolId {
+        if let Some(symbol) = self.globals.get(name) {
+            return *symbol;
+        }
+        let symbol = self.add_symbol(name, SymbolKind::Extern, data_type.clone(), declared_at);
+        self.globals.insert(name.to_string(), symbol);
+        symbol
+    }
+
+    // The innermost variable of the name, where a function cannot see the variables of main
+    fn get_variable(&self, name: &str) -> Option<SymbolId> {
+        let visible = self.function.as_ref().map_or(0, |f| f.parameter_scope);
+        self.scopes[visible..]
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.globals.get(name))
+            .copied()
+    }
+
+    fn symbol_expr(&self, symbol: SymbolId, span: Span) -> TypedExpr {
+        typed(
+            TypedExprKind::Variable(symbol),
+            self.symbols[symbol.0].data_type.clone(),
+            span,
+        )
+    }
+
+    /// Record a warning located at the span unless it is disabled there.
+    /// Returns the diagnostic to add labels to it.
+    fn warn(&mut self, warning: Warning, span: Span, message: String) -> Option<&mut Diagnostic> {
+        let diagnostic = self.warning_options.diagnostic(warning, span, message)?;
+        self.warnings.push(diagnostic);
+        self.warnings.last_mut()
+    }
+
+    fn program_unit(
+        &mut self,
+        program_unit: &ProgramUnit,
+    ) -> Result<Option<TypedUnit>, CompilerError> {
+        match program_unit {
+            ProgramUnit::FuncDef(
+                return_type,
+                func_name,
+                parameters,
+                variadic,
+                statement,
+                storage_class,
+            ) => {
+                let parameters: Vec<(DataType, String, Span)> = parameters
+                    .iter()
+                    .map(|(t, name, span)| (adjust_parameter(t), name.clone(), *span))
+                    .collect();
+
+                // Register the function before its body so that it can call itself
+                let parameter_types = parameters.iter().map(|(t, _, _)| t.clone()).collect();
+                let symbol = self.declare_function(
+                    func_name,
+                    function(return_type.clone(), parameter_types, *variadic),
+                );
+
+                // Enter a new scope and register parameters of functions
+                self.scopes.push(HashMap::new());
+                let mut parameter_symbols = Vec::new();
+                for (data_type, name, span) in parameters.iter() {
+                    self.layouts.check_complete(data_type)?;
+                    parameter_symbols.push(self.define_local(
+                        name,
+                        data_type,
+                        SymbolKind::Local,
+                        *span,
+                    ));
+                }
+                self.function = Some(FunctionContext {
+                    return_type: return_type.clone(),
+                    variadic: *variadic,
+                    parameter_scope: self.scopes.len() - 1,
+                });
+
+                let body = self.statement(statement)?;
+                self.check_function_warnings(return_type, func_name, &parameters, statement);
+
+                // Leave the scope
+                self.function = None;
+                self.scopes.pop();
+                Ok(Some(TypedUnit::Function(TypedFunction {
+                    symbol,
+                    parameters: parameter_symbols,
+                    body,
+                    storage_class: *storage_class,
+                })))
+            }
+            ProgramUnit::FuncDecl(return_type, func_name, parameter_types, variadic, _) => {
+                let parameter_types = parameter_types.iter().map(adjust_parameter).collect();
+                self.declare_function(
+                    func_name,
+                    function(return_type.clone(), parameter_types, *variadic),
+                );
+                Ok(None)
+            }
+            ProgramUnit::StructDef(name, members) => {
+                self.layouts.register_struct(name, members, false)?;
+                Ok(None)
+            }
+            ProgramUnit::UnionDef(name, members) => {
+                self.layouts.register_struct(name, members, true)?;
+                Ok(None)
+            }
+            // Variables defined at the top level are global variables
+            ProgramUnit::Statement(statement) => {
+                let statement = match &statement.kind {
+                    StatementKind::Error => return Ok(None),
+                    // A declaration with extern only introduces the name
+                    StatementKind::VarDef(
+                        data_type,
+                        var_name,
+                        None,
+                        Some(StorageClass::Extern),
+                    ) => {
+                        self.declare_extern(var_name, data_type, statement.span);
+                        return Ok(None);
+                    }
+                    StatementKind::VarDef(data_type, var_name, initializer, storage_class) => self
+                        .global_var_def(
+                            data_type,
+                            var_name,
+                            initializer,
+                            *storage_class,
+                            statement.span,
+                        )
+                        .map_err(|e| e.at(statement.span))?,
+                    _ => self.statement(statement)?,
+                };
+                Ok(Some(TypedUnit::Statement(statement)))
+            }
+        }
+    }
+
+    // Warn about unused variables and parameters, and the end of a function
+    // returning a value which can be reached without returning
+    fn check_function_warnings(
+        &mut self,
+        return_type: &DataType,
+        func_name: &str,
+        parameters: &[(DataType, String, Span)],
+        body: &Statement,
+    ) {
+        let usage = NameUsage::new(body);
+        for (name, span) in usage.declared.iter() {
+            if !usage.used.contains(name) {
+                self.warn(
+                    Warning::UnusedVariable,
+                    *span,
+                    format!("Unused variable: {}", name),
+                );
+            }
+        }
+        for (_, name, span) in parameters.iter() {
+            if !usage.used.contains(name.as_str()) {
+                self.warn(
+                    Warning::UnusedParameter,
+                    *span,
+                    format!("Unused parameter: {}", name),
+                );
+            }
+        }
+        // main returns 0 when it reaches the end
+        if *return_type != void() && func_name != "main" && can_complete(body) {
+            let closing_brace = Span {
+                start: body.span.end - 1,
+                end: body.span.end,
+            };
+            self.warn(
+                Warning::ReturnType,
+                closing_brace,
+                format!("Control reaches the end of non-void function {}", func_name),
+            );
+        }
+    }
+
+    // Warn about a local variable with the same name as a parameter, a local variable of
+    // an enclosing block or a global variable
+    fn check_shadow(&mut self, name: &str, declared_at: Span) {
+        let parameter_scope = match &self.function {
+            Some(function) => function.parameter_scope,
+            None => return,
+        };
+        let (current, enclosing) = self.scopes.split_last().unwrap();
+        // Another variable of the same name in the same block is replaced, not shadowed
+        if current.contains_key(name) {
+            return;
+        }
+        let local = (parameter_scope..enclosing.len())
+            .rev()
+            .find_map(|index| enclosing[index].get(name).map(|symbol| (index, symbol)));
+        let shadowed = match local {
+            Some((index, symbol)) => {
+                let kind = if index == parameter_scope {
+                    "parameter"
+                } else {
+                    "local variable"
+                };
+                Some((kind, self.symbols[symbol.0].declared_at))
+            }
+            None => self
+                .globals
+                .get(name)
+                .map(|symbol| ("global variable", self.symbols[symbol.0].declared_at)),
+        };
+        if let Some((kind, span)) = shadowed {
+            let message = format!("Declaration of {} shadows a {}", name, kind);
+            if let Some(diagnostic) = self.warn(Warning::Shadow, declared_at, message) {
+                diagnostic.secondary.push(Label {
+                    span,
+                    message: "shadowed declaration is here".to_string(),
+                });
+            }
+        }
+    }
+
+    // Convert a value assigned, passed, returned or used for initialization to the type of
+    // its destination, and warn about an implicit conversion between a pointer and an integer.
+    // A scalar is converted only to a scalar, and a struct or a union is assigned only to
+    // the same type. An array cannot be assigned to.
+    fn convert_for_assignment(
+        &mut self,
+        target_type: &DataType,
+        expr: &Expr,
+        value: TypedExpr,
+    ) -> Result<TypedExpr, CompilerError> {
+        let value = decay_value(value);
+        let source_type = &value.data_type;
+        let compatible = match target_type {
+            // A value returned from a void function is discarded
+            DataType::Primitive(PrimitiveType::Void) => true,
+            DataType::Array(_, _) => false,
+            target_type if is_scalar(target_type) => is_scalar(source_type),
+            target_type => source_type == target_type,
+        };
+        if !compatible {
+            return Err(
+                CompilerError::IncompatibleTypes(target_type.clone(), source_type.clone())
+                    .at(expr.span),
+            );
+        }
+        let is_pointer = |data_type: &DataType| matches!(data_type, DataType::Pointer(_, _));
+        // 0 is a null pointer constant
+        let direction = if is_pointer(target_type)
+            && is_integer(source_type)
+            && constant_value(expr) != Some(0)
+        {
+            Some("integer to pointer")
+        } else if is_integer(target_type) && is_pointer(source_type) {
+            Some("pointer to integer")
+        } else {
+            None
+        };
+        if let Some(direction) = direction {
+            let message = format!(
+                "Incompatible {} conversion from {} to {}",
+                direction, source_type, target_type
+            );
+            self.warn(Warning::IntConversion, expr.span, message);
+        }
+        if value.data_type != *target_type && is_scalar(target_type) && is_scalar(&value.data_type)
+        {
+            Ok(convert(Conversion::Assignment, target_type.clone(), value))
+        } else {
+            Ok(value)
+        }
+    }
+
+    // A condition of if, while and for, which must be a scalar
+    fn condition(&mut self, expr: &Expr) -> Result<TypedExpr, CompilerError> {
+        let condition = self.expr(expr)?;
+        if !is_scalar(&decay(&condition.data_type)) {
+            return Err(CompilerError::NotAScalar(condition.data_type).at(expr.span));
+        }
+        Ok(condition)
+    }
+
+    // Define a global variable. Its initial content is computed if the initializer is
+    // a constant expression, and otherwise it is initialized at that point of main.
+    fn global_var_def(
+        &mut self,
+        data_type: &DataType,
+        var_name: &str,
+        initializer: &Option<Initializer>,
+        storage_class: Option<StorageClass>,
+        declared_at: Span,
+    ) -> Result<TypedStatement, CompilerError> {
+        let data_type = match initializer {
+            Some(initializer) => complete_type(data_type, initializer),
+            None => data_type.clone(),
+        };
+        self.layouts.check_complete(&data_type)?;
+        let initializer = match initializer {
+            Some(initializer) => Some(self.initializer(&data_type, initializer, true)?),
+            None => None,
+        };
+        let kind = SymbolKind::Global {
+            is_static: storage_class == Some(StorageClass::Static),
+        };
+        let symbol = self.add_symbol(var_name, kind, data_type, declared_at);
+        self.globals.insert(var_name.to_string(), symbol);
+        Ok(TypedStatement {
+            kind: TypedStatementKind::VarDef(symbol, initializer),
+            span: declared_at,
+        })
+    }
+
+    // Flatten an initializer into the scalar values to store, converted to their types.
+    // The initial content of a variable with static storage duration is computed from them.
+    fn initializer(
+        &mut self,
+        data_type: &DataType,
+        initializer: &Initializer,
+        is_static_storage: bool,
+    ) -> Result<TypedInitializer, CompilerError> {
+        let mut flattened = Vec::new();
+        flatten_initializer(data_type, initializer, 0, &self.layouts, &mut flattened)?;
+        let mut items = Vec::new();
+        for item in flattened.into_iter() {
+            let value = match item.value {
+                InitValue::Expr(expr) => {
+                    let value = require_type(self.expr(expr)?)?;
+                    if is_aggregate(&item.data_type) && value.data_type != item.data_type {
+                        return Err(CompilerError::InvalidInitializer(format!(
+                            "{} cannot be initialized with {}",
+                            item.data_type, value.data_type
+                        )));
+                    }
+                    InitValue::Expr(self.convert_for_assignment(&item.data_type, expr, value)?)
+                }
+                InitValue::Byte(byte) => InitValue::Byte(byte),
+            };
+            items.push(InitItem {
+                offset: item.offset,
+                data_type: item.data_type,
+                value,
+            });
+        }
+        let image = if is_static_storage {
+            build_data_image(data_type, &items, &self.layouts, &self.symbols)
+        } else {
+            None
+        };
+        Ok(TypedInitializer { items, image })
+    }
+
+    // Errors are located at the innermost statement, expression or atom where they occur
+    fn statement(&mut self, statement: &Statement) -> Result<TypedStatement, CompilerError> {
+        let kind = self
+            .statement_kind(statement)
+            .map_err(|e| e.at(statement.span))?;
+        Ok(TypedStatement {
+            kind,
+            span: statement.span,
+        })
+    }
+
+    fn boxed_statement(
+        &mut self,
+        statement: &Option<Statement>,
+    ) -> Result<Option<Box<TypedStatement>>, CompilerError> {
+        match statement {
+            Some(statement) => Ok(Some(Box::new(self.statement(statement)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn statement_kind(
+        &mut self,
+        statement: &Statement,
+    ) -> Result<TypedStatementKind, CompilerError> {
+        match &statement.kind {
+            StatementKind::Expr(expr) => Ok(TypedStatementKind::Expr(self.expr(expr)?)),
+            StatementKind::Assign(_, _) | StatementKind::AssignPointer(_, _, _) => {
+                unreachable!("assignments are desugared into AssignLval")
+            }
+            StatementKind::AssignLval(left, expr) => {
+                let lvalue = self.atom(left)?;
+                if !is_lvalue(&lvalue) {
+                    return Err(CompilerError::NotAnLvalue.at(left.span));
+                }
+                let value = self.expr(expr)?;
+                let value = self.convert_for_assignment(&lvalue.data_type, expr, value)?;
+                Ok(TypedStatementKind::Assign(lvalue, value))
+            }
+            StatementKind::Return(expr) => {
+                let value = self.expr(expr)?;
+                let value = match &self.function {
+                    Some(function) => {
+                        let return_type = function.return_type.clone();
+                        self.convert_for_assignment(&return_type, expr, value)?
+                    }
+                    None => value,
+                };
+                Ok(TypedStatementKind::Return(value))
+            }
+            StatementKind::Block(statements) => {
+                // Only the first statement which cannot be reached is warned about
+                if let Some(index) = statements.iter().position(|s| !can_complete(s)) {
+                    if let Some(unreachable) = statements.get(index + 1) {
+                        self.warn(
+                            Warning::UnreachableCode,
+                            unreachable.span,
+                            "Unreachable code".to_string(),
+                        );
+                    }
+                }
+                self.scopes.push(HashMap::new());
+                let mut typed_statements = Vec::new();
+                for statement in statements.iter() {
+                    // An error in a statement does not prevent analyzing the following ones
+                    match self.statement(statement) {
+                        Ok(typed_statement) => typed_statements.push(typed_statement),
+                        Err(e) => self.errors.push(e),
+                    }
+                }
+                self.scopes.pop();
+                Ok(TypedStatementKind::Block(typed_statements))
+            }
+            StatementKind::If(condition, if_statement, else_statement) => {
+                Ok(TypedStatementKind::If(
+                    self.condition(condition)?,
+                    Box::new(self.statement(if_statement)?),
+                    self.boxed_statement(else_statement)?,
+                ))
+            }
+            StatementKind::While(condition, body) => Ok(TypedStatementKind::While(
+                self.condition(condition)?,
+                Box::new(self.statement(body)?),
+            )),
+            StatementKind::For(init, condition, step, body) => {
+                let init = self.boxed_statement(init)?;
+                let condition = match **condition {
+                    Some(ref condition) => Some(self.condition(condition)?),
+                    None => None,
+                };
+                Ok(TypedStatementKind::For(
+                    init,
+                    condition,
+                    self.boxed_statement(step)?,
+                    Box::new(self.statement(body)?),
+                ))
+            }
+            StatementKind::Break => Ok(TypedStatementKind::Break),
+            StatementKind::Error => Ok(TypedStatementKind::Block(Vec::new())),
+            StatementKind::VarDef(data_type, var_name, initializer, storage_class) => {
+                let data_type = match initializer {
+                    Some(initializer) => complete_type(data_type, initializer),
+                    None => data_type.clone(),
+                };
+                if *storage_class != Some(StorageClass::Extern) {
+                    self.check_shadow(var_name, statement.span);
+                }
+                let (symbol, initializer) = match (storage_class, initializer) {
+                    (Some(StorageClass::Extern), None) => (
+                        self.declare_extern(var_name, &data_type, statement.span),
+                        None,
+                    ),
+                    (Some(StorageClass::Extern), Some(_)) => {
+                        return Err(CompilerError::InvalidInitializer(format!(
+                            "extern variable {} cannot be initialized in a function",
+                            var_name
+                        )))
+                    }
+                    // A static local variable is initialized once before the program starts
+                    (Some(StorageClass::Static), _) => {
+                        self.layouts.check_complete(&data_type)?;
+                        let initializer = match initializer {
+                            Some(initializer) => {
+                                Some(self.initializer(&data_type, initializer, true)?)
+                            }
+                            None => None,
+                        };
+                        if initializer.as_ref().is_some_and(|i| i.image.is_none()) {
+                            return Err(CompilerError::InvalidInitializer(format!(
+                                "initializer of static variable {} is not constant",
+                                var_name
+                            )));
+                        }
+                        let symbol = self.define_local(
+                            var_name,
+                            &data_type,
+                            SymbolKind::StaticLocal,
+                            statement.span,
+                        );
+                        (symbol, initializer)
+                    }
+                    (None, _) => {
+                        self.layouts.check_complete(&data_type)?;
+                        let symbol = self.define_local(
+                            var_name,
+                            &data_type,
+                            SymbolKind::Local,
+                            statement.span,
+                        );
+                        let initializer = match initializer {
+                            Some(initializer) => {
+                                Some(self.initializer(&data_type, initializer, false)?)
+                            }
+                            None => None,
+                        };
+                        (symbol, initializer)
+                    }
+                };
+                Ok(TypedStatementKind::VarDef(symbol, initializer))
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<TypedExpr, CompilerError> {
+        self.expr_kind(expr).map_err(|e| e.at(expr.span))
+    }
+
+    fn expr_kind(&mut self, expr: &Expr) -> Result<TypedExpr, CompilerError> {
+        let (operator, left, right) = match &expr.kind {
+            ExprKind::ArithExpr(arith_expr) => return self.arith_expr(arith_expr),
+            ExprKind::Equal(left, right) => (BinaryOperator::Equal, left, right),
+            ExprKind::NotEqual(left, right) => (BinaryOperator::NotEqual, left, right),
+            ExprKind::Less(left, right) => (BinaryOperator::Less, left, right),
+            ExprKind::LessOrEqual(left, right) => (BinaryOperator::LessOrEqual, left, right),
+        };
+        // Any scalars can be compared
+        let left = promote(decay_value(self.arith_expr(left)?));
+        let right = promote(decay_value(self.arith_expr(right)?));
+        for operand in [&left, &right] {
+            if !is_scalar(&operand.data_type) {
+                return Err(CompilerError::NotAScalar(operand.data_type.clone()).at(operand.span));
+            }
+        }
+        Ok(binary(operator, int(), left, right))
+    }
+
+    fn arith_expr(&mut self, arith_expr: &ArithExpr) -> Result<TypedExpr, CompilerError> {
+        match arith_expr {
+            ArithExpr::Factor(factor) => self.factor(factor),
+            ArithExpr::Add(left, right) => {
+                let left = self.arith_expr(left)?;
+                additive(BinaryOperator::Add, left, self.factor(right)?)
+            }
+            ArithExpr::Sub(left, right) => {
+                let left = self.arith_expr(left)?;
+                additive(BinaryOperator::Sub, left, self.factor(right)?)
+            }
+        }
+    }
+
+    fn factor(&mut self, factor: &Factor) -> Result<TypedExpr, CompilerError> {
+        match factor {
+            Factor::Unary(unary) => self.unary(unary),
+            Factor::Mul(left, right) => {
+                let left = self.factor(left)?;
+                arithmetic(BinaryOperator::Mul, left, self.unary(right)?)
+            }
+            Factor::Div(left, right) => {
+                let left = self.factor(left)?;
+                arithmetic(BinaryOperator::Div, left, self.unary(right)?)
+            }
+        }
+    }
+
+    fn unary(&mut self, unary: &Unary) -> Result<TypedExpr, CompilerError> {
+        match unary {
+            Unary::Atom(atom) => self.atom(atom),
+            Unary::Neg(atom) => {
+                let operand = promote(require_type(self.atom(atom)?)?);
+                if operand.data_type != int() {
+                    return Err(
+                        CompilerError::InvalidOperand("-".to_string(), operand.data_type)
+                            .at(atom.span),
+                    );
+                }
+                let span = operand.span;
+                Ok(typed(TypedExprKind::Neg(Box::new(operand)), int(), span))
+            }
+            Unary::PointerDeref(atom) => deref(self.atom(atom)?),
+            Unary::AddressOf(atom) => {
+                let operand = match self.atom(atom)? {
+                    // The name of a function designates the function, which is not converted
+                    TypedExpr {
+                        kind: TypedExprKind::Convert(Conversion::FunctionToPointer, operand),
+                        ..
+                    } => *operand,
+                    operand => operand,
+                };
+                if !is_lvalue(&operand) {
+                    return Err(CompilerError::NotAnLvalue.at(atom.span));
+                }
+                let (data_type, span) = (pointer(1, operand.data_type.clone()), operand.span);
+                Ok(typed(
+                    TypedExprKind::AddressOf(Box::new(operand)),
+                    data_type,
+                    span,
+                ))
+            }
+        }
+    }
+
+    fn atom(&mut self, atom: &Atom) -> Result<TypedExpr, CompilerError> {
+        self.atom_kind(atom).map_err(|e| e.at(atom.span))
+    }
+
+    fn atom_kind(&mut self, atom: &Atom) -> Result<TypedExpr, CompilerError> {
+        let span = atom.span;
+        match &atom.kind {
+            AtomKind::Number(n) => Ok(typed(TypedExprKind::Number(*n), int(), span)),
+            AtomKind::Expr(expr) => self.expr(expr),
+            AtomKind::Variable(name) => match self.get_variable(name) {
+                Some(symbol) => Ok(self.symbol_expr(symbol, span)),
+                // A function designator is converted to a pointer to the function
+                None => match self.functions.get(name) {
+                    Some(symbol) => Ok(decay_value(self.symbol_expr(*symbol, span))),
+                    None => Err(CompilerError::UndefinedVariable(name.clone())),
+                },
+            },
+            AtomKind::FunctionCall(func_name, arguments) => {
+                let callee = match self.get_variable(func_name) {
+                    // Call through a variable holding a function pointer
+                    Some(symbol) => {
+                        let symbol_info = &self.symbols[symbol.0];
+                        if called_function(&symbol_info.data_type).is_err() {
+                            return Err(CompilerError::NotAFunction(
+                                func_name.clone(),
+                                symbol_info.data_type.clone(),
+                                symbol_info.declared_at,
+                            ));
+                        }
+                        self.symbol_expr(symbol, span)
+                    }
+                    None => match self.functions.get(func_name) {
+                        Some(symbol) => self.symbol_expr(*symbol, span),
+                        // A function without a declaration is assumed
+                        // to return int and to take any arguments
+                        None => {
+                            self.warn(
+                                Warning::ImplicitFunctionDeclaration,
+                                span,
+                                format!("Implicit declaration of function {}", func_name),
+                            );
+                            let symbol = self.add_symbol(
+                                func_name,
+                                SymbolKind::Function,
+                                function(int(), Vec::new(), true),
+                                span,
+                            );
+                            self.symbol_expr(symbol, span)
+                        }
+                    },
+                };
+                self.call(callee, arguments, span)
+            }
+            AtomKind::IndirectCall(callee, arguments) => {
+                let callee = self.atom(callee)?;
+                self.call(callee, arguments, span)
+            }
+            AtomKind::StringLiteral(bytes) => Ok(typed(
+                TypedExprKind::StringLiteral(bytes.clone()),
+                array(char(), Some(bytes.len() as u32 + 1)),
+                span,
+            )),
+            AtomKind::Index(inner, index) => {
+                let inner = decay_value(self.atom(inner)?);
+                let data_type = match &inner.data_type {
+                    DataType::Pointer(1, data_type) => *data_type.clone(),
+                    DataType::Pointer(depth, data_type) => pointer(depth - 1, *data_type.clone()),
+                    data_type => return Err(CompilerError::NotSubscriptable(data_type.clone())),
+                };
+                let index = promote(self.expr(index)?);
+                Ok(typed(
+                    TypedExprKind::Index(Box::new(inner), Box::new(index)),
+                    data_type,
+                    span,
+                ))
+            }
+            AtomKind::Member(inner, member) => {
+                let inner = self.atom(inner)?;
+                let member_info = self.layouts.get_member(&inner.data_type, member)?;
+                let (data_type, offset) = (member_info.data_type.clone(), member_info.offset);
+                Ok(typed(
+                    TypedExprKind::Member(Box::new(inner), member.clone(), offset),
+                    data_type,
+                    span,
+                ))
+            }
+            AtomKind::PointerMember(inner, member) => {
+                let inner = self.atom(inner)?;
+                let member_info = match &inner.data_type {
+                    DataType::Pointer(1, data_type) => {
+                        self.layouts.get_member(data_type, member)?
+                    }
+                    data_type => return Err(CompilerError::NotAStructPointer(data_type.clone())),
+                };
+                let (data_type, offset) = (member_info.data_type.clone(), member_info.offset);
+                Ok(typed(
+                    TypedExprKind::PointerMember(Box::new(inner), member.clone(), offset),
+                    data_type,
+                    span,
+                ))
+            }
+            AtomKind::VaStart(ap, _) => {
+                if !self.function.as_ref().is_some_and(|f| f.variadic) {
+                    return Err(CompilerError::VaStartOutsideVariadic);
+                }
+                let ap = self.va_list(ap)?;
+                Ok(typed(TypedExprKind::VaStart(Box::new(ap)), void(), span))
+            }
+            // Only arguments of the INTEGER class are supported
+            AtomKind::VaArg(ap, data_type) => {
+                if !is_scalar(data_type) {
+                    return Err(CompilerError::UnsupportedVaArgType(data_type.clone()));
+                }
+                Ok(typed(
+                    TypedExprKind::VaArg(Box::new(self.va_list(ap)?)),
+                    data_type.clone(),
+                    span,
+                ))
+            }
+            AtomKind::VaEnd(ap) => Ok(typed(
+                TypedExprKind::VaEnd(Box::new(self.va_list(ap)?)),
+                void(),
+                span,
+            )),
+            AtomKind::VaCopy(destination, source) => {
+                let destination = self.va_list(destination)?;
+                let source = self.va_list(source)?;
+                Ok(typed(
+                    TypedExprKind::VaCopy(Box::new(destination), Box::new(source)),
+                    void(),
+                    span,
+                ))
+            }
+        }
+    }
+
+    // The operand of va_start, va_arg, va_end and va_copy, which is a va_list
+    // or a va_list parameter holding its address
+    fn va_list(&mut self, expr: &Expr) -> Result<TypedExpr, CompilerError> {
+        let ap = self.expr(expr)?;
+        let va_list = DataType::Primitive(PrimitiveType::VaList);
+        if ap.data_type != va_list && ap.data_type != pointer(1, va_list) {
+            return Err(CompilerError::NotAVaList(ap.data_type).at(expr.span));
+        }
+        Ok(ap)
+    }
+
+    // Check the number of arguments against the parameters of the function called,
+    // and convert the arguments to the types of the parameters
+    fn call(
+        &mut self,
+        callee: TypedExpr,
+        arguments: &[Expr],
+        span: Span,
+    ) -> Result<TypedExpr, CompilerError> {
+        let (return_type, parameter_types, variadic) = called_function(&callee.data_type)?;
+        let parameter_types = parameter_types.to_vec();
+        let (expected, found) = (parameter_types.len(), arguments.len());
+        if found < expected || (found > expected && !variadic) {
+            return Err(CompilerError::WrongArgumentCount(expected, variadic, found));
+        }
+        let mut typed_arguments = Vec::new();
+        for (index, argument) in arguments.iter().enumerate() {
+            let value = pass_va_list(decay_value(require_type(self.expr(argument)?)?));
+            let value = match parameter_types.get(index) {
+                Some(parameter_type) => {
+                    self.convert_for_assignment(parameter_type, argument, value)?
+                }
+                // Variable arguments are promoted
+                None => promote(value),
+            };
+            typed_arguments.push(value);
+        }
+        Ok(typed(
+            TypedExprKind::Call(Box::new(callee), typed_arguments),
+            return_type,
+            span,
+        ))
+    }
+}
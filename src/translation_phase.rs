@@ -0,0 +1,143 @@
+use crate::compile_error::CompilerError;
+use crate::preprocessor::SourceLocation;
+use std::iter::Peekable;
+use std::str::Chars;
+
+// Characters of the source after removing backslash-newline sequences,
+// which splice physical lines into a logical line
+struct SplicedChars<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: u32,
+    column: u32,
+    // Newlines removed by splicing or from block comments in directives, which are emitted
+    // after the logical line so that the following lines keep their line numbers
+    deferred_newlines: u32,
+}
+
+impl<'a> SplicedChars<'a> {
+    fn new(source: &'a str) -> SplicedChars<'a> {
+        SplicedChars {
+            chars: source.chars().peekable(),
+            line: 1,
+            column: 1,
+            deferred_newlines: 0,
+        }
+    }
+
+    fn skip_splices(&mut self) {
+        loop {
+            let mut lookahead = self.chars.clone();
+            match (lookahead.next(), lookahead.next()) {
+                (Some('\\'), Some('\n')) => {
+                    self.chars.next();
+                    self.chars.next();
+                    self.line += 1;
+                    self.column = 1;
+                    self.deferred_newlines += 1;
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_splices();
+        self.chars.peek().copied()
+    }
+}
+
+impl Iterator for SplicedChars<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        self.skip_splices();
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+}
+
+/// Splice lines ending with a backslash and replace each comment with a space.
+/// Newlines in block comments are kept so that the following lines are not shifted,
+/// and they are moved after the line in a directive, which a newline would end.
+pub fn strip_comments(source: &str, file_name: &str) -> Result<String, CompilerError> {
+    let mut result = String::with_capacity(source.len());
+    let mut chars = SplicedChars::new(source);
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            result.push('\n');
+            for _ in 0..chars.deferred_newlines {
+                result.push('\n');
+            }
+            chars.deferred_newlines = 0;
+            continue;
+        }
+        match c {
+            '/' if chars.peek() == Some('/') => {
+                while let Some(c) = chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+                result.push(' ');
+            }
+            '/' if chars.peek() == Some('*') => {
+                let (line, column) = (chars.line, chars.column - 1);
+                chars.next();
+                let mut terminated = false;
+                let mut newlines = 0;
+                while let Some(c) = chars.next() {
+                    if c == '*' && chars.peek() == Some('/') {
+                        chars.next();
+                        terminated = true;
+                        break;
+                    }
+                    if c == '\n' {
+                        newlines += 1;
+                    }
+                }
+                if !terminated {
+                    return Err(CompilerError::UnterminatedComment(SourceLocation {
+                        file: file_name.to_string(),
+                        line,
+                        column,
+                        text: source
+                            .lines()
+                            .nth(line as usize - 1)
+                            .unwrap_or("")
+                            .to_string(),
+                    }));
+                }
+                result.push(' ');
+                let line_start = result.rfind('\n').map_or(0, |index| index + 1);
+                if result[line_start..].trim_start().starts_with('#') {
+                    chars.deferred_newlines += newlines;
+                } else {
+                    result.extend(std::iter::repeat_n('\n', newlines as usize));
+                }
+            }
+            // Comment markers in string and character literals are not comments
+            '"' | '\'' => {
+                result.push(c);
+                while let Some(inner) = chars.next() {
+                    result.push(inner);
+                    if inner == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            result.push(escaped);
+                        }
+                    } else if inner == c || inner == '\n' {
+                        break;
+                    }
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,157 @@
+use crate::ast::{DataType, Span, StorageClass};
+use crate::initializer::{DataImage, InitItem};
+use crate::layout::Layouts;
+
+// The typed AST produced by semantic analysis for code generation.
+// Names are resolved to symbols, every expression has its type,
+// and implicit conversions are explicit nodes.
+
+pub struct TypedProgram {
+    pub units: Vec<TypedUnit>,
+    // Indexed by `SymbolId`
+    pub symbols: Vec<Symbol>,
+    pub layouts: Layouts,
+}
+
+/// An index into the symbols of a typed program
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SymbolId(pub usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SymbolKind {
+    // A local variable or a parameter in the stack frame
+    Local,
+    // A local variable with static storage duration
+    StaticLocal,
+    // A variable defined at the top level, which has internal linkage if it is static
+    Global { is_static: bool },
+    // A variable declared with extern
+    Extern,
+    Function,
+}
+
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    // The type of a variable with its array length completed by the initializer
+    pub data_type: DataType,
+    // The span of the declaration. Functions have an empty span
+    // since the AST does not keep the spans of their declarations.
+    pub declared_at: Span,
+}
+
+// Declarations of functions, structs and unions are resolved by semantic analysis
+// and leave no units
+#[derive(Debug)]
+pub enum TypedUnit {
+    Function(TypedFunction),
+    // A top level statement, which is a part of main unless it defines a global variable
+    Statement(TypedStatement),
+}
+
+#[derive(Debug)]
+pub struct TypedFunction {
+    // The symbol of the function, whose type has array parameters adjusted to pointers
+    pub symbol: SymbolId,
+    pub parameters: Vec<SymbolId>,
+    pub body: TypedStatement,
+    pub storage_class: Option<StorageClass>,
+}
+
+#[derive(Debug)]
+pub struct TypedStatement {
+    pub kind: TypedStatementKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum TypedStatementKind {
+    Expr(TypedExpr),
+    // Store the value to the object designated by the lvalue, which is a variable,
+    // a dereference, an element of an array or a member
+    Assign(TypedExpr, TypedExpr),
+    Return(TypedExpr),
+    If(TypedExpr, Box<TypedStatement>, Option<Box<TypedStatement>>),
+    Block(Vec<TypedStatement>),
+    While(TypedExpr, Box<TypedStatement>),
+    For(
+        Option<Box<TypedStatement>>,
+        Option<TypedExpr>,
+        Option<Box<TypedStatement>>,
+        Box<TypedStatement>,
+    ),
+    Break,
+    // A definition or a declaration of any kind of variable given by the symbol
+    VarDef(SymbolId, Option<TypedInitializer>),
+}
+
+#[derive(Debug)]
+pub struct TypedInitializer {
+    // The scalar values stored at run time after the object is zero-filled
+    pub items: Vec<InitItem<TypedExpr>>,
+    // The initial content of a variable with static storage duration,
+    // which is None if the initializer is not a constant expression
+    pub image: Option<DataImage>,
+}
+
+#[derive(Debug)]
+pub struct TypedExpr {
+    pub kind: TypedExprKind,
+    pub data_type: DataType,
+    // The span of a binary operation covers its operands, and that of a unary operation
+    // or a conversion is the one of its operand
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum TypedExprKind {
+    Number(i32),
+    StringLiteral(Vec<u8>),
+    // A variable, or a function designator which is either called or converted to a pointer
+    Variable(SymbolId),
+    // The address of an lvalue or a function designator
+    AddressOf(Box<TypedExpr>),
+    // The operands of arithmetic are promoted and arrays in them are converted to pointers
+    Binary(BinaryOperator, Box<TypedExpr>, Box<TypedExpr>),
+    Neg(Box<TypedExpr>),
+    Deref(Box<TypedExpr>),
+    // A call of a function designator or a function pointer
+    Call(Box<TypedExpr>, Vec<TypedExpr>),
+    // pointer[index]
+    Index(Box<TypedExpr>, Box<TypedExpr>),
+    // The member with its name and its offset in a struct or a union
+    Member(Box<TypedExpr>, String, u32),
+    // The member with its name and its offset in a struct or a union pointed to
+    PointerMember(Box<TypedExpr>, String, u32),
+    VaStart(Box<TypedExpr>),
+    VaArg(Box<TypedExpr>),
+    VaEnd(Box<TypedExpr>),
+    VaCopy(Box<TypedExpr>, Box<TypedExpr>),
+    // An implicit conversion of the operand to the type of this expression
+    Convert(Conversion, Box<TypedExpr>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BinaryOperator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Conversion {
+    // An array, including va_list, to a pointer to its first element
+    ArrayToPointer,
+    // A function designator to a pointer to the function
+    FunctionToPointer,
+    // char to int in arithmetic and in variable arguments
+    IntegerPromotion,
+    // A value assigned, passed, returned or used for initialization
+    // to another scalar type of the destination
+    Assignment,
+}
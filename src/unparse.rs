@@ -0,0 +1,329 @@
+use crate::ast::*;
+use std::fmt;
+
+// Print the AST back as C source, which parses to the same AST except for the spans.
+// Statements in blocks are indented by 4 spaces, and braces are placed as in K&R style.
+
+const INDENT: &str = "    ";
+
+// A declaration of `name` with the type, such as `int *p`, `int a[2][3]` or `int (*f)(int)`.
+// An empty name gives an abstract declarator.
+fn declaration(data_type: &DataType, name: &str) -> String {
+    match data_type {
+        DataType::Array(element_type, length) => {
+            let length = length.map_or(String::new(), |length| length.to_string());
+            declaration(element_type, &format!("{}[{}]", name, length))
+        }
+        DataType::Pointer(depth, base_type) => match &**base_type {
+            // Only a pointer to a function is declared with a name
+            DataType::Function(return_type, parameter_types, variadic) if !name.is_empty() => {
+                let parameter_types = parameter_types.iter().map(|t| t.to_string());
+                format!(
+                    "{} (*{})({})",
+                    return_type,
+                    name,
+                    parameter_list(parameter_types, *variadic)
+                )
+            }
+            _ if name.is_empty() => data_type.to_string(),
+            _ => format!("{} {}{}", base_type, "*".repeat(*depth as usize), name),
+        },
+        _ if name.is_empty() => data_type.to_string(),
+        _ => format!("{} {}", data_type, name),
+    }
+}
+
+fn parameter_list(parameters: impl Iterator<Item = String>, variadic: bool) -> String {
+    let mut parameters: Vec<String> = parameters.collect();
+    if variadic {
+        parameters.push("...".to_string());
+    }
+    parameters.join(", ")
+}
+
+fn storage_class_prefix(storage_class: Option<StorageClass>) -> &'static str {
+    match storage_class {
+        Some(StorageClass::Static) => "static ",
+        Some(StorageClass::Extern) => "extern ",
+        None => "",
+    }
+}
+
+// Indent every line of the text by one level
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("{}{}\n", INDENT, line))
+        .collect()
+}
+
+// Escape the bytes of a string literal as `unescape` reads them back.
+// Octal escapes are used since a hexadecimal one would take the following digits.
+fn escape(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for byte in bytes.iter() {
+        match byte {
+            b'"' => text.push_str("\\\""),
+            b'\\' => text.push_str("\\\\"),
+            b'\n' => text.push_str("\\n"),
+            b'\t' => text.push_str("\\t"),
+            b' '..=b'~' => text.push(*byte as char),
+            _ => text.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    text
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Definitions of functions, structs and unions are separated by a blank line
+        let is_definition = |program_unit: &ProgramUnit| {
+            matches!(
+                program_unit,
+                ProgramUnit::FuncDef(..) | ProgramUnit::StructDef(..) | ProgramUnit::UnionDef(..)
+            )
+        };
+        for (index, program_unit) in self.program_units.iter().enumerate() {
+            if index > 0
+                && (is_definition(program_unit) || is_definition(&self.program_units[index - 1]))
+            {
+                writeln!(f)?;
+            }
+            writeln!(f, "{}", program_unit)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ProgramUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramUnit::FuncDef(return_type, name, parameters, variadic, body, storage_class) => {
+                let parameters = parameters.iter().map(|(t, name, _)| declaration(t, name));
+                write!(
+                    f,
+                    "{}{} {}",
+                    storage_class_prefix(*storage_class),
+                    declaration(
+                        return_type,
+                        &format!("{}({})", name, parameter_list(parameters, *variadic))
+                    ),
+                    body
+                )
+            }
+            // The names of the parameters are not kept, and they are named after their positions
+            ProgramUnit::FuncDecl(return_type, name, parameter_types, variadic, storage_class) => {
+                let parameters = parameter_types
+                    .iter()
+                    .enumerate()
+                    .map(|(index, t)| declaration(t, &format!("p{}", index)));
+                write!(
+                    f,
+                    "{}{};",
+                    storage_class_prefix(*storage_class),
+                    declaration(
+                        return_type,
+                        &format!("{}({})", name, parameter_list(parameters, *variadic))
+                    )
+                )
+            }
+            ProgramUnit::StructDef(name, members) | ProgramUnit::UnionDef(name, members) => {
+                let keyword = match self {
+                    ProgramUnit::StructDef(_, _) => "struct",
+                    _ => "union",
+                };
+                writeln!(f, "{} {} {{", keyword, name)?;
+                for (data_type, name) in members.iter() {
+                    writeln!(f, "{}{};", INDENT, declaration(data_type, name))?;
+                }
+                write!(f, "}};")
+            }
+            ProgramUnit::Statement(statement) => write!(f, "{}", statement),
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            // Statements in the clauses of for are written without `;`
+            StatementKind::Expr(_) | StatementKind::Assign(_, _) => {
+                write!(f, "{};", SimpleStatement(self))
+            }
+            StatementKind::AssignPointer(depth, name, expr) => {
+                write!(f, "{}{} = {};", "*".repeat(*depth as usize), name, expr)
+            }
+            StatementKind::AssignLval(atom, expr) => write!(f, "{} = {};", atom, expr),
+            StatementKind::Return(expr) => write!(f, "return {};", expr),
+            StatementKind::If(condition, if_statement, else_statement) => {
+                write!(f, "if ({}) {}", condition, if_statement)?;
+                match **else_statement {
+                    Some(ref else_statement) => write!(f, " else {}", else_statement),
+                    None => Ok(()),
+                }
+            }
+            StatementKind::Block(statements) if statements.is_empty() => write!(f, "{{}}"),
+            StatementKind::Block(statements) => {
+                writeln!(f, "{{")?;
+                for statement in statements.iter() {
+                    write!(f, "{}", indent(&statement.to_string()))?;
+                }
+                write!(f, "}}")
+            }
+            StatementKind::While(condition, body) => write!(f, "while ({}) {}", condition, body),
+            StatementKind::For(init, condition, step, body) => {
+                write!(f, "for (")?;
+                if let Some(ref init) = **init {
+                    write!(f, "{}", SimpleStatement(init))?;
+                }
+                write!(f, ";")?;
+                if let Some(ref condition) = **condition {
+                    write!(f, " {}", condition)?;
+                }
+                write!(f, ";")?;
+                if let Some(ref step) = **step {
+                    write!(f, " {}", SimpleStatement(step))?;
+                }
+                write!(f, ") {}", body)
+            }
+            StatementKind::Break => write!(f, "break;"),
+            StatementKind::Error => write!(f, ";"),
+            StatementKind::VarDef(data_type, name, initializer, storage_class) => {
+                write!(
+                    f,
+                    "{}{}",
+                    storage_class_prefix(*storage_class),
+                    declaration(data_type, name)
+                )?;
+                if let Some(initializer) = initializer {
+                    write!(f, " = {}", initializer)?;
+                }
+                write!(f, ";")
+            }
+        }
+    }
+}
+
+// An expression or an assignment statement without the trailing `;`
+struct SimpleStatement<'a>(&'a Statement);
+
+impl fmt::Display for SimpleStatement<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0.kind {
+            StatementKind::Expr(expr) => write!(f, "{}", expr),
+            StatementKind::Assign(name, expr) => write!(f, "{} = {}", name, expr),
+            _ => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl fmt::Display for Initializer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Initializer::Expr(expr) => write!(f, "{}", expr),
+            Initializer::List(items) if items.is_empty() => write!(f, "{{}}"),
+            Initializer::List(items) => {
+                write!(f, "{{")?;
+                for (index, (designators, initializer)) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " ")?;
+                    for designator in designators.iter() {
+                        match designator {
+                            Designator::Index(index) => write!(f, "[{}]", index)?,
+                            Designator::Member(member) => write!(f, ".{}", member)?,
+                        }
+                    }
+                    if !designators.is_empty() {
+                        write!(f, " = ")?;
+                    }
+                    write!(f, "{}", initializer)?;
+                }
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ExprKind::ArithExpr(arith_expr) => write!(f, "{}", arith_expr),
+            ExprKind::Equal(left, right) => write!(f, "{} == {}", left, right),
+            ExprKind::NotEqual(left, right) => write!(f, "{} != {}", left, right),
+            ExprKind::Less(left, right) => write!(f, "{} < {}", left, right),
+            ExprKind::LessOrEqual(left, right) => write!(f, "{} <= {}", left, right),
+        }
+    }
+}
+
+// The operands of binary operators are already grouped by the grammar,
+// and parentheses in the source are kept as atoms, so none are added
+impl fmt::Display for ArithExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithExpr::Factor(factor) => write!(f, "{}", factor),
+            ArithExpr::Add(left, right) => write!(f, "{} + {}", left, right),
+            ArithExpr::Sub(left, right) => write!(f, "{} - {}", left, right),
+        }
+    }
+}
+
+impl fmt::Display for Factor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Factor::Unary(unary) => write!(f, "{}", unary),
+            Factor::Mul(left, right) => write!(f, "{} * {}", left, right),
+            Factor::Div(left, right) => write!(f, "{} / {}", left, right),
+        }
+    }
+}
+
+impl fmt::Display for Unary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unary::Atom(atom) => write!(f, "{}", atom),
+            Unary::Neg(atom) => write!(f, "-{}", atom),
+            Unary::PointerDeref(atom) => write!(f, "*{}", atom),
+            Unary::AddressOf(atom) => write!(f, "&{}", atom),
+        }
+    }
+}
+
+fn write_arguments(f: &mut fmt::Formatter<'_>, arguments: &[Expr]) -> fmt::Result {
+    let arguments: Vec<String> = arguments
+        .iter()
+        .map(|argument| argument.to_string())
+        .collect();
+    write!(f, "({})", arguments.join(", "))
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            AtomKind::Number(value) => write!(f, "{}", value),
+            AtomKind::Expr(expr) => write!(f, "({})", expr),
+            AtomKind::Variable(name) => write!(f, "{}", name),
+            AtomKind::FunctionCall(name, arguments) => {
+                write!(f, "{}", name)?;
+                write_arguments(f, arguments)
+            }
+            AtomKind::IndirectCall(callee, arguments) => {
+                write!(f, "{}", callee)?;
+                write_arguments(f, arguments)
+            }
+            AtomKind::StringLiteral(bytes) => write!(f, "\"{}\"", escape(bytes)),
+            AtomKind::Index(inner, index) => write!(f, "{}[{}]", inner, index),
+            AtomKind::Member(inner, member) => write!(f, "{}.{}", inner, member),
+            AtomKind::PointerMember(inner, member) => write!(f, "{}->{}", inner, member),
+            AtomKind::VaStart(ap, last_parameter) => {
+                write!(f, "va_start({}, {})", ap, last_parameter)
+            }
+            AtomKind::VaArg(ap, data_type) => write!(f, "va_arg({}, {})", ap, data_type),
+            AtomKind::VaEnd(ap) => write!(f, "va_end({})", ap),
+            AtomKind::VaCopy(destination, source) => {
+                write!(f, "va_copy({}, {})", destination, source)
+            }
+        }
+    }
+}
@@ -0,0 +1,602 @@
+use crate::ast::*;
+
+// Traversals over the AST. A pass implements the methods for the nodes it is interested in,
+// and the default methods walk into the children of the others.
+// An overriding method calls the `walk_*` function to continue into the children.
+
+/// Visit the nodes of the AST by shared references, which live as long as the AST
+pub trait Visitor<'a>: Sized {
+    fn visit_statement(&mut self, statement: &'a Statement) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_initializer(&mut self, initializer: &'a Initializer) {
+        walk_initializer(self, initializer);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_arith_expr(&mut self, arith_expr: &'a ArithExpr) {
+        walk_arith_expr(self, arith_expr);
+    }
+
+    fn visit_factor(&mut self, factor: &'a Factor) {
+        walk_factor(self, factor);
+    }
+
+    fn visit_unary(&mut self, unary: &'a Unary) {
+        walk_unary(self, unary);
+    }
+
+    fn visit_atom(&mut self, atom: &'a Atom) {
+        walk_atom(self, atom);
+    }
+}
+
+pub fn walk_statement<'a, V: Visitor<'a>>(visitor: &mut V, statement: &'a Statement) {
+    match &statement.kind {
+        StatementKind::Expr(expr)
+        | StatementKind::Assign(_, expr)
+        | StatementKind::AssignPointer(_, _, expr)
+        | StatementKind::Return(expr) => visitor.visit_expr(expr),
+        StatementKind::AssignLval(atom, expr) => {
+            visitor.visit_atom(atom);
+            visitor.visit_expr(expr);
+        }
+        StatementKind::If(condition, if_statement, else_statement) => {
+            visitor.visit_expr(condition);
+            visitor.visit_statement(if_statement);
+            if let Some(ref else_statement) = **else_statement {
+                visitor.visit_statement(else_statement);
+            }
+        }
+        StatementKind::Block(statements) => {
+            for statement in statements.iter() {
+                visitor.visit_statement(statement);
+            }
+        }
+        StatementKind::While(condition, body) => {
+            visitor.visit_expr(condition);
+            visitor.visit_statement(body);
+        }
+        StatementKind::For(init, condition, step, body) => {
+            if let Some(ref init) = **init {
+                visitor.visit_statement(init);
+            }
+            if let Some(ref condition) = **condition {
+                visitor.visit_expr(condition);
+            }
+            if let Some(ref step) = **step {
+                visitor.visit_statement(step);
+            }
+            visitor.visit_statement(body);
+        }
+        StatementKind::Break | StatementKind::Error => (),
+        StatementKind::VarDef(_, _, initializer, _) => {
+            if let Some(initializer) = initializer {
+                visitor.visit_initializer(initializer);
+            }
+        }
+    }
+}
+
+pub fn walk_initializer<'a, V: Visitor<'a>>(visitor: &mut V, initializer: &'a Initializer) {
+    match initializer {
+        Initializer::Expr(expr) => visitor.visit_expr(expr),
+        Initializer::List(items) => {
+            for (_, initializer) in items.iter() {
+                visitor.visit_initializer(initializer);
+            }
+        }
+    }
+}
+
+pub fn walk_expr<'a, V: Visitor<'a>>(visitor: &mut V, expr: &'a Expr) {
+    match &expr.kind {
+        ExprKind::ArithExpr(arith_expr) => visitor.visit_arith_expr(arith_expr),
+        ExprKind::Equal(left, right)
+        | ExprKind::NotEqual(left, right)
+        | ExprKind::Less(left, right)
+        | ExprKind::LessOrEqual(left, right) => {
+            visitor.visit_arith_expr(left);
+            visitor.visit_arith_expr(right);
+        }
+    }
+}
+
+pub fn walk_arith_expr<'a, V: Visitor<'a>>(visitor: &mut V, arith_expr: &'a ArithExpr) {
+    match arith_expr {
+        ArithExpr::Factor(factor) => visitor.visit_factor(factor),
+        ArithExpr::Add(left, right) | ArithExpr::Sub(left, right) => {
+            visitor.visit_arith_expr(left);
+            visitor.visit_factor(right);
+        }
+    }
+}
+
+pub fn walk_factor<'a, V: Visitor<'a>>(visitor: &mut V, factor: &'a Factor) {
+    match factor {
+        Factor::Unary(unary) => visitor.visit_unary(unary),
+        Factor::Mul(left, right) | Factor::Div(left, right) => {
+            visitor.visit_factor(left);
+            visitor.visit_unary(right);
+        }
+    }
+}
+
+pub fn walk_unary<'a, V: Visitor<'a>>(visitor: &mut V, unary: &'a Unary) {
+    match unary {
+        Unary::Atom(atom)
+        | Unary::Neg(atom)
+        | Unary::PointerDeref(atom)
+        | Unary::AddressOf(atom) => visitor.visit_atom(atom),
+    }
+}
+
+pub fn walk_atom<'a, V: Visitor<'a>>(visitor: &mut V, atom: &'a Atom) {
+    match &atom.kind {
+        AtomKind::Number(_) | AtomKind::Variable(_) | AtomKind::StringLiteral(_) => (),
+        AtomKind::Expr(expr)
+        | AtomKind::VaStart(expr, _)
+        | AtomKind::VaArg(expr, _)
+        | AtomKind::VaEnd(expr) => visitor.visit_expr(expr),
+        AtomKind::FunctionCall(_, arguments) => {
+            for argument in arguments.iter() {
+                visitor.visit_expr(argument);
+            }
+        }
+        AtomKind::IndirectCall(callee, arguments) => {
+            visitor.visit_atom(callee);
+            for argument in arguments.iter() {
+                visitor.visit_expr(argument);
+            }
+        }
+        AtomKind::Index(inner, index) => {
+            visitor.visit_atom(inner);
+            visitor.visit_expr(index);
+        }
+        AtomKind::Member(inner, _) | AtomKind::PointerMember(inner, _) => visitor.visit_atom(inner),
+        AtomKind::VaCopy(destination, source) => {
+            visitor.visit_expr(destination);
+            visitor.visit_expr(source);
+        }
+    }
+}
+
+/// Visit the nodes of the AST by mutable references to change them in place
+pub trait VisitorMut: Sized {
+    fn visit_program_unit_mut(&mut self, program_unit: &mut ProgramUnit) {
+        walk_program_unit_mut(self, program_unit);
+    }
+
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_initializer_mut(&mut self, initializer: &mut Initializer) {
+        walk_initializer_mut(self, initializer);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_arith_expr_mut(&mut self, arith_expr: &mut ArithExpr) {
+        walk_arith_expr_mut(self, arith_expr);
+    }
+
+    fn visit_factor_mut(&mut self, factor: &mut Factor) {
+        walk_factor_mut(self, factor);
+    }
+
+    fn visit_unary_mut(&mut self, unary: &mut Unary) {
+        walk_unary_mut(self, unary);
+    }
+
+    fn visit_atom_mut(&mut self, atom: &mut Atom) {
+        walk_atom_mut(self, atom);
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program) {
+    for program_unit in program.program_units.iter_mut() {
+        visitor.visit_program_unit_mut(program_unit);
+    }
+}
+
+pub fn walk_program_unit_mut<V: VisitorMut>(visitor: &mut V, program_unit: &mut ProgramUnit) {
+    match program_unit {
+        ProgramUnit::FuncDef(_, _, _, _, body, _) => visitor.visit_statement_mut(body),
+        ProgramUnit::Statement(statement) => visitor.visit_statement_mut(statement),
+        ProgramUnit::FuncDecl(..) | ProgramUnit::StructDef(..) | ProgramUnit::UnionDef(..) => (),
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut>(visitor: &mut V, statement: &mut Statement) {
+    match &mut statement.kind {
+        StatementKind::Expr(expr)
+        | StatementKind::Assign(_, expr)
+        | StatementKind::AssignPointer(_, _, expr)
+        | StatementKind::Return(expr) => visitor.visit_expr_mut(expr),
+        StatementKind::AssignLval(atom, expr) => {
+            visitor.visit_atom_mut(atom);
+            visitor.visit_expr_mut(expr);
+        }
+        StatementKind::If(condition, if_statement, else_statement) => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_statement_mut(if_statement);
+            if let Some(ref mut else_statement) = **else_statement {
+                visitor.visit_statement_mut(else_statement);
+            }
+        }
+        StatementKind::Block(statements) => {
+            for statement in statements.iter_mut() {
+                visitor.visit_statement_mut(statement);
+            }
+        }
+        StatementKind::While(condition, body) => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_statement_mut(body);
+        }
+        StatementKind::For(init, condition, step, body) => {
+            if let Some(ref mut init) = **init {
+                visitor.visit_statement_mut(init);
+            }
+            if let Some(ref mut condition) = **condition {
+                visitor.visit_expr_mut(condition);
+            }
+            if let Some(ref mut step) = **step {
+                visitor.visit_statement_mut(step);
+            }
+            visitor.visit_statement_mut(body);
+        }
+        StatementKind::Break | StatementKind::Error => (),
+        StatementKind::VarDef(_, _, initializer, _) => {
+            if let Some(initializer) = initializer {
+                visitor.visit_initializer_mut(initializer);
+            }
+        }
+    }
+}
+
+pub fn walk_initializer_mut<V: VisitorMut>(visitor: &mut V, initializer: &mut Initializer) {
+    match initializer {
+        Initializer::Expr(expr) => visitor.visit_expr_mut(expr),
+        Initializer::List(items) => {
+            for (_, initializer) in items.iter_mut() {
+                visitor.visit_initializer_mut(initializer);
+            }
+        }
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Expr) {
+    match &mut expr.kind {
+        ExprKind::ArithExpr(arith_expr) => visitor.visit_arith_expr_mut(arith_expr),
+        ExprKind::Equal(left, right)
+        | ExprKind::NotEqual(left, right)
+        | ExprKind::Less(left, right)
+        | ExprKind::LessOrEqual(left, right) => {
+            visitor.visit_arith_expr_mut(left);
+            visitor.visit_arith_expr_mut(right);
+        }
+    }
+}
+
+pub fn walk_arith_expr_mut<V: VisitorMut>(visitor: &mut V, arith_expr: &mut ArithExpr) {
+    match arith_expr {
+        ArithExpr::Factor(factor) => visitor.visit_factor_mut(factor),
+        ArithExpr::Add(left, right) | ArithExpr::Sub(left, right) => {
+            visitor.visit_arith_expr_mut(left);
+            visitor.visit_factor_mut(right);
+        }
+    }
+}
+
+pub fn walk_factor_mut<V: VisitorMut>(visitor: &mut V, factor: &mut Factor) {
+    match factor {
+        Factor::Unary(unary) => visitor.visit_unary_mut(unary),
+        Factor::Mul(left, right) | Factor::Div(left, right) => {
+            visitor.visit_factor_mut(left);
+            visitor.visit_unary_mut(right);
+        }
+    }
+}
+
+pub fn walk_unary_mut<V: VisitorMut>(visitor: &mut V, unary: &mut Unary) {
+    match unary {
+        Unary::Atom(atom)
+        | Unary::Neg(atom)
+        | Unary::PointerDeref(atom)
+        | Unary::AddressOf(atom) => visitor.visit_atom_mut(atom),
+    }
+}
+
+pub fn walk_atom_mut<V: VisitorMut>(visitor: &mut V, atom: &mut Atom) {
+    match &mut atom.kind {
+        AtomKind::Number(_) | AtomKind::Variable(_) | AtomKind::StringLiteral(_) => (),
+        AtomKind::Expr(expr)
+        | AtomKind::VaStart(expr, _)
+        | AtomKind::VaArg(expr, _)
+        | AtomKind::VaEnd(expr) => visitor.visit_expr_mut(expr),
+        AtomKind::FunctionCall(_, arguments) => {
+            for argument in arguments.iter_mut() {
+                visitor.visit_expr_mut(argument);
+            }
+        }
+        AtomKind::IndirectCall(callee, arguments) => {
+            visitor.visit_atom_mut(callee);
+            for argument in arguments.iter_mut() {
+                visitor.visit_expr_mut(argument);
+            }
+        }
+        AtomKind::Index(inner, index) => {
+            visitor.visit_atom_mut(inner);
+            visitor.visit_expr_mut(index);
+        }
+        AtomKind::Member(inner, _) | AtomKind::PointerMember(inner, _) => {
+            visitor.visit_atom_mut(inner)
+        }
+        AtomKind::VaCopy(destination, source) => {
+            visitor.visit_expr_mut(destination);
+            visitor.visit_expr_mut(source);
+        }
+    }
+}
+
+/// Rebuild the AST from the nodes returned for each node, which can be of another kind.
+/// The children are folded before their parent by the default methods.
+pub trait Fold: Sized {
+    fn fold_program_unit(&mut self, program_unit: ProgramUnit) -> ProgramUnit {
+        walk_program_unit_fold(self, program_unit)
+    }
+
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        walk_statement_fold(self, statement)
+    }
+
+    fn fold_initializer(&mut self, initializer: Initializer) -> Initializer {
+        walk_initializer_fold(self, initializer)
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        walk_expr_fold(self, expr)
+    }
+
+    fn fold_arith_expr(&mut self, arith_expr: ArithExpr) -> ArithExpr {
+        walk_arith_expr_fold(self, arith_expr)
+    }
+
+    fn fold_factor(&mut self, factor: Factor) -> Factor {
+        walk_factor_fold(self, factor)
+    }
+
+    fn fold_unary(&mut self, unary: Unary) -> Unary {
+        walk_unary_fold(self, unary)
+    }
+
+    fn fold_atom(&mut self, atom: Atom) -> Atom {
+        walk_atom_fold(self, atom)
+    }
+}
+
+pub fn walk_program_fold<F: Fold>(folder: &mut F, program: Program) -> Program {
+    Program {
+        program_units: program
+            .program_units
+            .into_iter()
+            .map(|program_unit| folder.fold_program_unit(program_unit))
+            .collect(),
+    }
+}
+
+pub fn walk_program_unit_fold<F: Fold>(folder: &mut F, program_unit: ProgramUnit) -> ProgramUnit {
+    match program_unit {
+        ProgramUnit::FuncDef(return_type, name, parameters, variadic, body, storage_class) => {
+            ProgramUnit::FuncDef(
+                return_type,
+                name,
+                parameters,
+                variadic,
+                Box::new(folder.fold_statement(*body)),
+                storage_class,
+            )
+        }
+        ProgramUnit::Statement(statement) => {
+            ProgramUnit::Statement(Box::new(folder.fold_statement(*statement)))
+        }
+        program_unit => program_unit,
+    }
+}
+
+fn fold_boxed_expr<F: Fold>(folder: &mut F, expr: Expr) -> Box<Expr> {
+    Box::new(folder.fold_expr(expr))
+}
+
+fn fold_optional_statement<F: Fold>(
+    folder: &mut F,
+    statement: Option<Statement>,
+) -> Box<Option<Statement>> {
+    Box::new(statement.map(|statement| folder.fold_statement(statement)))
+}
+
+pub fn walk_statement_fold<F: Fold>(folder: &mut F, statement: Statement) -> Statement {
+    let kind = match statement.kind {
+        StatementKind::Expr(expr) => StatementKind::Expr(fold_boxed_expr(folder, *expr)),
+        StatementKind::Assign(name, expr) => {
+            StatementKind::Assign(name, fold_boxed_expr(folder, *expr))
+        }
+        StatementKind::AssignPointer(depth, name, expr) => {
+            StatementKind::AssignPointer(depth, name, fold_boxed_expr(folder, *expr))
+        }
+        StatementKind::AssignLval(atom, expr) => StatementKind::AssignLval(
+            Box::new(folder.fold_atom(*atom)),
+            fold_boxed_expr(folder, *expr),
+        ),
+        StatementKind::Return(expr) => StatementKind::Return(fold_boxed_expr(folder, *expr)),
+        StatementKind::If(condition, if_statement, else_statement) => StatementKind::If(
+            fold_boxed_expr(folder, *condition),
+            Box::new(folder.fold_statement(*if_statement)),
+            fold_optional_statement(folder, *else_statement),
+        ),
+        StatementKind::Block(statements) => StatementKind::Block(
+            statements
+                .into_iter()
+                .map(|statement| folder.fold_statement(statement))
+                .collect(),
+        ),
+        StatementKind::While(condition, body) => StatementKind::While(
+            fold_boxed_expr(folder, *condition),
+            Box::new(folder.fold_statement(*body)),
+        ),
+        StatementKind::For(init, condition, step, body) => StatementKind::For(
+            fold_optional_statement(folder, *init),
+            Box::new(condition.map(|condition| folder.fold_expr(condition))),
+            fold_optional_statement(folder, *step),
+            Box::new(folder.fold_statement(*body)),
+        ),
+        kind @ (StatementKind::Break | StatementKind::Error) => kind,
+        StatementKind::VarDef(data_type, name, initializer, storage_class) => {
+            StatementKind::VarDef(
+                data_type,
+                name,
+                initializer.map(|initializer| folder.fold_initializer(initializer)),
+                storage_class,
+            )
+        }
+    };
+    Statement {
+        kind,
+        span: statement.span,
+    }
+}
+
+pub fn walk_initializer_fold<F: Fold>(folder: &mut F, initializer: Initializer) -> Initializer {
+    match initializer {
+        Initializer::Expr(expr) => Initializer::Expr(fold_boxed_expr(folder, *expr)),
+        Initializer::List(items) => Initializer::List(
+            items
+                .into_iter()
+                .map(|(designators, initializer)| {
+                    (designators, folder.fold_initializer(initializer))
+                })
+                .collect(),
+        ),
+    }
+}
+
+pub fn walk_expr_fold<F: Fold>(folder: &mut F, expr: Expr) -> Expr {
+    let mut fold_operands = |left: Box<ArithExpr>, right: Box<ArithExpr>| {
+        (
+            Box::new(folder.fold_arith_expr(*left)),
+            Box::new(folder.fold_arith_expr(*right)),
+        )
+    };
+    let kind = match expr.kind {
+        ExprKind::ArithExpr(arith_expr) => {
+            ExprKind::ArithExpr(Box::new(folder.fold_arith_expr(*arith_expr)))
+        }
+        ExprKind::Equal(left, right) => {
+            let (left, right) = fold_operands(left, right);
+            ExprKind::Equal(left, right)
+        }
+        ExprKind::NotEqual(left, right) => {
+            let (left, right) = fold_operands(left, right);
+            ExprKind::NotEqual(left, right)
+        }
+        ExprKind::Less(left, right) => {
+            let (left, right) = fold_operands(left, right);
+            ExprKind::Less(left, right)
+        }
+        ExprKind::LessOrEqual(left, right) => {
+            let (left, right) = fold_operands(left, right);
+            ExprKind::LessOrEqual(left, right)
+        }
+    };
+    Expr {
+        kind,
+        span: expr.span,
+    }
+}
+
+pub fn walk_arith_expr_fold<F: Fold>(folder: &mut F, arith_expr: ArithExpr) -> ArithExpr {
+    match arith_expr {
+        ArithExpr::Factor(factor) => ArithExpr::Factor(Box::new(folder.fold_factor(*factor))),
+        ArithExpr::Add(left, right) => ArithExpr::Add(
+            Box::new(folder.fold_arith_expr(*left)),
+            Box::new(folder.fold_factor(*right)),
+        ),
+        ArithExpr::Sub(left, right) => ArithExpr::Sub(
+            Box::new(folder.fold_arith_expr(*left)),
+            Box::new(folder.fold_factor(*right)),
+        ),
+    }
+}
+
+pub fn walk_factor_fold<F: Fold>(folder: &mut F, factor: Factor) -> Factor {
+    match factor {
+        Factor::Unary(unary) => Factor::Unary(Box::new(folder.fold_unary(*unary))),
+        Factor::Mul(left, right) => Factor::Mul(
+            Box::new(folder.fold_factor(*left)),
+            Box::new(folder.fold_unary(*right)),
+        ),
+        Factor::Div(left, right) => Factor::Div(
+            Box::new(folder.fold_factor(*left)),
+            Box::new(folder.fold_unary(*right)),
+        ),
+    }
+}
+
+pub fn walk_unary_fold<F: Fold>(folder: &mut F, unary: Unary) -> Unary {
+    match unary {
+        Unary::Atom(atom) => Unary::Atom(Box::new(folder.fold_atom(*atom))),
+        Unary::Neg(atom) => Unary::Neg(Box::new(folder.fold_atom(*atom))),
+        Unary::PointerDeref(atom) => Unary::PointerDeref(Box::new(folder.fold_atom(*atom))),
+        Unary::AddressOf(atom) => Unary::AddressOf(Box::new(folder.fold_atom(*atom))),
+    }
+}
+
+pub fn walk_atom_fold<F: Fold>(folder: &mut F, atom: Atom) -> Atom {
+    let fold_arguments = |folder: &mut F, arguments: Vec<Expr>| -> Vec<Expr> {
+        arguments
+            .into_iter()
+            .map(|argument| folder.fold_expr(argument))
+            .collect()
+    };
+    let kind = match atom.kind {
+        kind @ (AtomKind::Number(_) | AtomKind::Variable(_) | AtomKind::StringLiteral(_)) => kind,
+        AtomKind::Expr(expr) => AtomKind::Expr(fold_boxed_expr(folder, *expr)),
+        AtomKind::FunctionCall(name, arguments) => {
+            AtomKind::FunctionCall(name, fold_arguments(folder, arguments))
+        }
+        AtomKind::IndirectCall(callee, arguments) => {
+            let callee = Box::new(folder.fold_atom(*callee));
+            AtomKind::IndirectCall(callee, fold_arguments(folder, arguments))
+        }
+        AtomKind::Index(inner, index) => AtomKind::Index(
+            Box::new(folder.fold_atom(*inner)),
+            fold_boxed_expr(folder, *index),
+        ),
+        AtomKind::Member(inner, member) => {
+            AtomKind::Member(Box::new(folder.fold_atom(*inner)), member)
+        }
+        AtomKind::PointerMember(inner, member) => {
+            AtomKind::PointerMember(Box::new(folder.fold_atom(*inner)), member)
+        }
+        AtomKind::VaStart(ap, last_parameter) => {
+            AtomKind::VaStart(fold_boxed_expr(folder, *ap), last_parameter)
+        }
+        AtomKind::VaArg(ap, data_type) => AtomKind::VaArg(fold_boxed_expr(folder, *ap), data_type),
+        AtomKind::VaEnd(ap) => AtomKind::VaEnd(fold_boxed_expr(folder, *ap)),
+        AtomKind::VaCopy(destination, source) => {
+            let destination = fold_boxed_expr(folder, *destination);
+            AtomKind::VaCopy(destination, fold_boxed_expr(folder, *source))
+        }
+    };
+    Atom {
+        kind,
+        span: atom.span,
+    }
+}
@@ -0,0 +1,300 @@
+use crate::ast::*;
+use crate::diagnostic::{Diagnostic, Label};
+use crate::visit::{walk_atom, walk_statement, Visitor};
+use std::collections::{HashMap, HashSet};
+
+/// A kind of warning, which is enabled with `-W<name>` and disabled with `-Wno-<name>`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Warning {
+    UnusedVariable,
+    UnusedParameter,
+    Shadow,
+    ReturnType,
+    ImplicitFunctionDeclaration,
+    IntConversion,
+    UnreachableCode,
+    // `#warning` in the source
+    Cpp,
+}
+
+const WARNINGS: &[Warning] = &[
+    Warning::UnusedVariable,
+    Warning::UnusedParameter,
+    Warning::Shadow,
+    Warning::ReturnType,
+    Warning::ImplicitFunctionDeclaration,
+    Warning::IntConversion,
+    Warning::UnreachableCode,
+    Warning::Cpp,
+];
+
+// Warnings enabled without any option
+const DEFAULT_WARNINGS: &[Warning] = &[Warning::ReturnType, Warning::IntConversion, Warning::Cpp];
+
+// Warnings enabled by -Wall
+const ALL_WARNINGS: &[Warning] = &[
+    Warning::UnusedVariable,
+    Warning::ReturnType,
+    Warning::ImplicitFunctionDeclaration,
+    Warning::IntConversion,
+];
+
+// Warnings enabled by -Wextra
+const EXTRA_WARNINGS: &[Warning] = &[Warning::UnusedParameter];
+
+impl Warning {
+    /// The option enabling the warning, which is shown with it
+    pub fn option(self) -> &'static str {
+        match self {
+            Warning::UnusedVariable => "-Wunused-variable",
+            Warning::UnusedParameter => "-Wunused-parameter",
+            Warning::Shadow => "-Wshadow",
+            Warning::ReturnType => "-Wreturn-type",
+            Warning::ImplicitFunctionDeclaration => "-Wimplicit-function-declaration",
+            Warning::IntConversion => "-Wint-conversion",
+            Warning::UnreachableCode => "-Wunreachable-code",
+            Warning::Cpp => "-Wcpp",
+        }
+    }
+
+    // The warning given by a name without the leading `-W`
+    fn from_name(name: &str) -> Option<Warning> {
+        WARNINGS
+            .iter()
+            .copied()
+            .find(|warning| &warning.option()[2..] == name)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Level {
+    Ignored,
+    Warning,
+    Error,
+}
+
+/// How each warning is reported, given by the command line
+/// and changed by `#pragma GCC diagnostic` in the source
+#[derive(Clone)]
+pub struct WarningOptions {
+    levels: HashMap<Warning, Level>,
+    // True with -Werror, which turns the warnings enabled on the command line into errors
+    warnings_as_errors: bool,
+    // The arguments of `#pragma GCC diagnostic` following `diagnostic`,
+    // with their offsets in the preprocessed source
+    pragmas: Vec<(usize, String)>,
+}
+
+impl Default for WarningOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WarningOptions {
+    pub fn new() -> WarningOptions {
+        WarningOptions {
+            levels: DEFAULT_WARNINGS
+                .iter()
+                .map(|warning| (*warning, Level::Warning))
+                .collect(),
+            warnings_as_errors: false,
+            pragmas: Vec::new(),
+        }
+    }
+
+    /// Apply an option given as `-W<option>`. Returns false if the option is unknown.
+    pub fn apply(&mut self, option: &str) -> bool {
+        match option {
+            "all" => self.enable(ALL_WARNINGS),
+            "extra" => self.enable(EXTRA_WARNINGS),
+            "error" => self.warnings_as_errors = true,
+            "no-error" => self.warnings_as_errors = false,
+            _ => {
+                let (name, level) = if let Some(name) = option.strip_prefix("error=") {
+                    (name, Level::Error)
+                } else if let Some(name) = option.strip_prefix("no-") {
+                    (name, Level::Ignored)
+                } else {
+                    (option, Level::Warning)
+                };
+                match Warning::from_name(name) {
+                    Some(warning) if level == Level::Warning => self.enable(&[warning]),
+                    Some(warning) => {
+                        self.levels.insert(warning, level);
+                    }
+                    None => return false,
+                }
+            }
+        }
+        true
+    }
+
+    // Enable the warnings, keeping those already turned into errors
+    fn enable(&mut self, warnings: &[Warning]) {
+        for warning in warnings.iter() {
+            let level = self.levels.entry(*warning).or_insert(Level::Ignored);
+            if *level == Level::Ignored {
+                *level = Level::Warning;
+            }
+        }
+    }
+
+    pub fn add_pragmas(&mut self, pragmas: &[(usize, String)]) {
+        self.pragmas.extend(pragmas.iter().cloned());
+    }
+
+    /// The level of a warning at an offset in the preprocessed source,
+    /// after the pragmas preceding it are applied
+    pub fn level_at(&self, warning: Warning, offset: usize) -> Level {
+        let level = match self.levels.get(&warning) {
+            Some(Level::Warning) if self.warnings_as_errors => Level::Error,
+            Some(level) => *level,
+            None => Level::Ignored,
+        };
+        let mut current = level;
+        let mut stack = Vec::new();
+        for (_, pragma) in self.pragmas.iter().take_while(|(o, _)| *o <= offset) {
+            let words: Vec<&str> = pragma.split_whitespace().collect();
+            match words[..] {
+                ["push"] => stack.push(current),
+                // A pop without a push restores the level given by the command line
+                ["pop"] => current = stack.pop().unwrap_or(level),
+                [kind, option] if option.trim_matches('"') == warning.option() => {
+                    current = match kind {
+                        "ignored" => Level::Ignored,
+                        "warning" => Level::Warning,
+                        "error" => Level::Error,
+                        _ => current,
+                    }
+                }
+                _ => (),
+            }
+        }
+        current
+    }
+
+    /// The diagnostic of a warning at a span, which is an error if the warning is turned into
+    /// one there, or None if the warning is ignored there
+    pub fn diagnostic(&self, warning: Warning, span: Span, message: String) -> Option<Diagnostic> {
+        let mut diagnostic = match self.level_at(warning, span.start) {
+            Level::Ignored => return None,
+            Level::Warning => Diagnostic::warning(warning.option(), message),
+            Level::Error => {
+                let mut diagnostic = Diagnostic::error(warning.option(), message);
+                diagnostic
+                    .notes
+                    .push(format!("`{}` is treated as an error", warning.option()));
+                diagnostic
+            }
+        };
+        diagnostic.primary = Some(Label {
+            span,
+            message: String::new(),
+        });
+        Some(diagnostic)
+    }
+}
+
+/// Returns false if control never reaches the end of the statement,
+/// because it returns or breaks, or it is a loop which never ends
+pub fn can_complete(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Return(_) | StatementKind::Break => false,
+        StatementKind::Block(statements) => statements.iter().all(can_complete),
+        StatementKind::If(_, if_statement, else_statement) => match **else_statement {
+            Some(ref else_statement) => can_complete(if_statement) || can_complete(else_statement),
+            None => true,
+        },
+        StatementKind::While(condition, body) => {
+            constant_value(condition).is_none_or(|value| value == 0) || contains_break(body)
+        }
+        StatementKind::For(_, condition, _, body) => match **condition {
+            Some(ref condition) => {
+                constant_value(condition).is_none_or(|value| value == 0) || contains_break(body)
+            }
+            None => contains_break(body),
+        },
+        _ => true,
+    }
+}
+
+// True if the statement contains a break out of the loop enclosing it
+fn contains_break(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Break => true,
+        StatementKind::Block(statements) => statements.iter().any(contains_break),
+        StatementKind::If(_, if_statement, else_statement) => {
+            contains_break(if_statement)
+                || else_statement.as_ref().as_ref().is_some_and(contains_break)
+        }
+        _ => false,
+    }
+}
+
+/// The value of an expression which is an integer constant, such as `0` or `(1)`
+pub fn constant_value(expr: &Expr) -> Option<i32> {
+    let ExprKind::ArithExpr(arith_expr) = &expr.kind else {
+        return None;
+    };
+    let ArithExpr::Factor(factor) = &**arith_expr else {
+        return None;
+    };
+    let Factor::Unary(unary) = &**factor else {
+        return None;
+    };
+    match &**unary {
+        Unary::Atom(atom) => match &atom.kind {
+            AtomKind::Number(value) => Some(*value),
+            AtomKind::Expr(expr) => constant_value(expr),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The names used in a function body and the local variables declared in it,
+/// which are compared to find unused variables and parameters
+#[derive(Default)]
+pub struct NameUsage<'a> {
+    pub used: HashSet<&'a str>,
+    // Variables declared with extern are not included
+    pub declared: Vec<(&'a str, Span)>,
+}
+
+impl<'a> NameUsage<'a> {
+    pub fn new(statement: &'a Statement) -> NameUsage<'a> {
+        let mut usage = NameUsage::default();
+        usage.visit_statement(statement);
+        usage
+    }
+}
+
+impl<'a> Visitor<'a> for NameUsage<'a> {
+    fn visit_statement(&mut self, statement: &'a Statement) {
+        match &statement.kind {
+            StatementKind::Assign(name, _) | StatementKind::AssignPointer(_, name, _) => {
+                self.used.insert(name);
+            }
+            StatementKind::VarDef(_, name, _, storage_class)
+                if *storage_class != Some(StorageClass::Extern) =>
+            {
+                self.declared.push((name, statement.span));
+            }
+            _ => (),
+        }
+        walk_statement(self, statement);
+    }
+
+    fn visit_atom(&mut self, atom: &'a Atom) {
+        match &atom.kind {
+            AtomKind::Variable(name)
+            | AtomKind::FunctionCall(name, _)
+            | AtomKind::VaStart(_, name) => {
+                self.used.insert(name);
+            }
+            _ => (),
+        }
+        walk_atom(self, atom);
+    }
+}
@@ -22,8 +22,9 @@ assert_program() {
     expected="$1"
     input="$2"
     link="$3"
+    flags="$4"
 
-    ${RUST_9CC} "$input" > tmp.s
+    printf '%s' "$input" | ${RUST_9CC} $flags -S -o - - > tmp.s
     cc -o tmp tmp.s $link
     ./tmp
     actual="$?"
@@ -39,7 +40,7 @@ assert_program() {
 assert_fail_compile() {
     input="$1"
 
-    ${RUST_9CC} "$input" > tmp.s
+    printf '%s' "$input" | ${RUST_9CC} -S -o - - > tmp.s
     if [ "$?" = "0" ]; then
       echo "should fail to compile, but succeeded"
       exit 1
@@ -47,6 +48,111 @@ assert_fail_compile() {
     true
 }
 
+assert_compile_error() {
+    expected="$1"
+    input="$2"
+    flags="$3"
+
+    actual=$(printf '%s' "$input" | ${RUST_9CC} $flags -S -o - - 2>&1 > tmp.s)
+    if [ "$?" = "0" ]; then
+      echo "should fail to compile, but succeeded"
+      exit 1
+    fi
+    if [[ "$actual" == *"$expected"* ]]; then
+      echo "$input => $expected"
+    else
+      echo "$input => $expected expected, but got $actual"
+      exit 1
+    fi
+}
+
+assert_compile_warning() {
+    expected="$1"
+    input="$2"
+    flags="$3"
+
+    actual=$(printf '%s' "$input" | ${RUST_9CC} $flags -S -o - - 2>&1 > tmp.s)
+    if [ "$?" != "0" ]; then
+      echo "$input => should compile, but failed with $actual"
+      exit 1
+    fi
+    if [[ "$actual" == *"$expected"* ]]; then
+      echo "$input => $expected"
+    else
+      echo "$input => $expected expected, but got $actual"
+      exit 1
+    fi
+}
+
+assert_no_warning() {
+    input="$1"
+    flags="$2"
+
+    actual=$(printf '%s' "$input" | ${RUST_9CC} $flags -S -o - - 2>&1 > tmp.s)
+    if [ "$?" != "0" ] || [ "$actual" != "" ]; then
+      echo "$input => no warning expected, but got $actual"
+      exit 1
+    fi
+    echo "$input => no warning"
+}
+
+assert_emit() {
+    expected="$1"
+    input="$2"
+    flags="$3"
+
+    actual=$(printf '%s' "$input" | ${RUST_9CC} $flags -)
+    if [ "$?" != "0" ]; then
+      echo "$input => should compile, but failed"
+      exit 1
+    fi
+    if [ "$actual" = "$expected" ]; then
+      echo "$input => $flags"
+    else
+      echo "$input => $expected expected, but got $actual"
+      exit 1
+    fi
+}
+
+assert_round_trip() {
+    input="$1"
+
+    formatted=$(printf '%s' "$input" | ${RUST_9CC} --emit=c -)
+    if [ "$?" != "0" ]; then
+      echo "$input => should be formatted, but failed"
+      exit 1
+    fi
+    expected=$(printf '%s' "$input" | ${RUST_9CC} --emit=ast -)
+    actual=$(printf '%s' "$formatted" | ${RUST_9CC} --emit=ast -)
+    if [ "$actual" != "$expected" ]; then
+      echo "$input => formatted as $formatted, which parses to $actual instead of $expected"
+      exit 1
+    fi
+    reformatted=$(printf '%s' "$formatted" | ${RUST_9CC} --emit=c -)
+    if [ "$reformatted" != "$formatted" ]; then
+      echo "$input => formatted as $formatted, but reformatted as $reformatted"
+      exit 1
+    fi
+    echo "$input => round trip"
+}
+
+assert_driver_error() {
+    expected="$1"
+    args="${@:2}"
+
+    actual=$(${RUST_9CC} $args 2>&1 > /dev/null)
+    if [ "$?" = "0" ]; then
+      echo "$args => should fail, but succeeded"
+      exit 1
+    fi
+    if [[ "$actual" == *"$expected"* ]]; then
+      echo "$args => $expected"
+    else
+      echo "$args => $expected expected, but got $actual"
+      exit 1
+    fi
+}
+
 # test the format of command line arguments
 assert 1 ${RUST_9CC}
 assert 1 ${RUST_9CC} 'first argument' 'second argument'
@@ -90,6 +196,9 @@ assert_program 3 'int a;a=1;a+2;'
 assert_program 3 'int a;a=1;int b;b=2;a+b;'
 assert_program 9 'int aa;aa=1;int bbb;bbb=2;int cccc;cccc=aa+bbb;(aa+bbb)*cccc;'
 assert_program 9 'int aa; aa = 1; int bbb; bbb = 2; int cccc; cccc= aa + bbb; (aa + bbb) * cccc;'
+# reading an uninitialized variable compiles, whose value is indeterminate
+assert_program 3 'int f() { int x; if (x) { x = 0; } return x; } f(); 3;'
+assert_program 3 'int f() { int x; return x; } f(); 3;' '' '-O0'
 
 # test return statements
 assert_program 3 'return 1 + 2;'
@@ -103,6 +212,8 @@ assert_program 4 'int a; a = 1; int b; b = 2; if (b != 2) {a = a + 2;} else { a
 # test statement blocks
 assert_program 4 'int a; a = 1; int b; b = 2; if (b == 2) {a = a + 2; a = a + 1; } else { a = a + 3; } a;'
 assert_program 5 'int a; a = 1; int b; b = 2; if (b != 2) {a = a + 2;} else { a = a + 3;  a = a + 1; } a;'
+assert_program 123 'int f() { int r = 0; int x = 1; { int x = 2; { int x = 3; r = r + x; } r = r + x * 10; } return r + x * 100; } f();'
+assert_program 4 'int x = 4; { int x = 5; x = 6; } x;'
 
 # test while statements
 assert_program 10 'int a; a = 0; while (a < 10) { a = a + 1; } a;'
@@ -152,15 +263,23 @@ int min3(int x, int y, int z)
 int add(int x, int y) { x + y; }
 min3(add(5,1), add(2,4), add(3,2));
 '
+assert_program 5 'int x = 3; int y; int *p = &y; int main() { *p = 2; return x + y; }'
 
 # test pointer
 assert_program 123 'int a; a = 123; int b; b = &a; *b;'
+assert_program 3 'int f() { int a[4]; a[2] = 3; int *p = &a[2]; return *p; } f();'
+assert_program 8 'struct S { int a; int b; }; int f() { struct S s; s.b = 7; int *p = &s.b; *p = *p + 1; return s.b; } f();'
+assert_program 9 'struct S { int a; int b; }; int f(struct S *s) { int *p = &s->b; return *p; } struct S s; s.b = 9; f(&s);'
+assert_program 9 'int g[4]; int *q = &g[3]; struct T { int a; char c; int b; }; struct T t; int *r = &t.b; int f() { g[3] = 5; t.b = 4; return *q + *r; } f();'
+assert_program 2 'int a[3]; int f() { a[1] = 2; return *(&a[0] + 1); } f();'
 #assert_program 3 'int x; x = 3; int y; y = 5; int z; z = &y + 8; *z;'
 
 # test undefined variable
 assert_fail_compile 'a = 1; a;'
 assert_fail_compile 'a;'
 assert_fail_compile '&a;'
+assert_compile_error 'error[E0016]' '&1;'
+assert_compile_error 'error[E0016]: The expression is not an lvalue' 'int f() { return 1; } f = 2;'
 
 # test assign statement using pointers
 assert_program 143 'int x; int* y; y = &x; *y = 143; x;'
@@ -170,4 +289,728 @@ assert_program 143 'int x; int * y; int** z; int *** w; y = &x; z = &y; w = &z;
 # test pointer arithmetics
 assert_program 3 'int x; x = 3; int y; y = 5; int z; z = &y + 1; *z;'
 assert_program 5 'int x; x = 3; int y; y = 5; int z; z = &x - 1; *z;'
+
+# test function pointers
+assert_program 8 'int twice(int x) { return x * 2; } int (*fp)(int); fp = twice; fp(4);'
+assert_program 10 'int twice(int x) { return x * 2; } int (*fp)(int); fp = &twice; (*fp)(5);'
+assert_program 42 'int twice(int x) { return x * 2; } apply(twice, 21);' $C_FUNCTION_OBJ
+assert_program 7 'int add(int x, int y) { return x + y; } int call2(int (*f)(int, int), int a, int b) { return f(a, b); } call2(add, 3, 4);'
+assert_program 3 'int three(); (&three)();' $C_FUNCTION_OBJ
+assert_program 9 'int abs(int x); int (*fp)(int); fp = abs; fp(0 - 9);'
+assert_program 123 '
+void qsort(int* base, int n, int size, int (*compare)(int*, int*));
+int compare(int* a, int* b) { return *a - *b; }
+int x; int y; int z; x = 3; y = 1; z = 2;
+qsort(&z, 3, 8, compare);
+z * 100 + y * 10 + x;
+'
+assert_program 71 '
+int add(int a, int b) { return a + b; }
+int sub(int a, int b) { return a - b; }
+int mul(int a, int b) { return a * b; }
+int (*table[3])(int, int) = {add, sub, mul};
+int run(int op, int a, int b) { return table[op](a, b); }
+run(2, 6, 10) + run(0, 3, 4) + run(1, 5, 1);
+'
+assert_program 13 'int add(int a, int b) { return a + b; } int mul(int a, int b) { return a * b; }
+struct Calc { int (*op)(int, int); int (*ops[2])(int, int); };
+struct Calc c; struct Calc *p = &c; c.op = add; c.ops[0] = add; c.ops[1] = mul;
+c.op(1, 2) + p->ops[1](2, 3) + (p->op)(1, 1) + (*c.ops[0])(1, 1);'
+assert_program 5 'int add(int a, int b) { return a + b; } int (*t[2][2])(int, int); t[1][0] = add; t[1][0](2, 3);'
+assert_compile_error 'error[E0015]: Called object of type int is not a function' 'int a[2]; a[0](1);'
+assert_fail_compile 'int x; x(1);'
+
+# test variadic functions
+assert_program 60 '
+int sum(int n, ...) { va_list ap; va_start(ap, n); int r; r = vsum(n, ap); va_end(ap); return r; }
+sum(3, 10, 20, 30);
+' $C_FUNCTION_OBJ
+assert_program 36 '
+int sum(int n, ...) {
+  va_list ap; va_start(ap, n);
+  int r; r = 0;
+  int i; for (i = 0; i < n; i = i + 1) { r = r + va_arg(ap, int); }
+  va_end(ap);
+  return r;
+}
+call_variadic(sum);
+' $C_FUNCTION_OBJ
+assert_program 70 '
+int sum(int n, ...) {
+  va_list ap; va_list aq;
+  va_start(ap, n);
+  va_arg(ap, int);
+  va_copy(aq, ap);
+  int r; r = vsum(n - 1, aq);
+  va_end(aq);
+  r = r + va_arg(ap, int);
+  va_end(ap);
+  return r;
+}
+sum(3, 1, 20, 30);
+' $C_FUNCTION_OBJ
+assert_program 80 '
+int first(va_list ap) { va_list aq; va_copy(aq, ap); int x = va_arg(aq, int); va_end(aq); return x + va_arg(ap, int); }
+int sum(int n, ...) { va_list ap; va_start(ap, n); int r = first(ap); va_end(ap); return r; }
+sum(2, 40, 2);
+'
+assert_fail_compile 'int f(int n) { va_list ap; va_start(ap, n); return 0; } f(1);'
+
+# test structs and unions
+assert_program 12 'struct P { int x; int y; }; struct P p; p.x = 3; p.y = 4; p.x * p.y;'
+assert_program 3 'struct P { int x; int y; }; struct P p; struct P* q; q = &p; q->x = 5; q->y = 2; p.x - p.y;'
+assert_program 12 'struct P { int a; int b; }; struct P p; struct P q; p.a = 1; p.b = 2; q = p; p.a = 5; q.a * 10 + q.b;'
+assert_program 8 'struct Inner { int a; int b; }; struct Outer { int x; struct Inner in; }; struct Outer o; o.in.b = 7; o.x = 1; o.in.b + o.x;'
+assert_program 42 'union U { int x; int* p; }; union U u; u.x = 42; u.x;'
+assert_fail_compile 'struct Q q;'
+assert_fail_compile 'struct P { int x; }; struct P p; p.y;'
+
+# test passing more than 6 arguments
+assert_program 77 'int f(int a, int b, int c, int d, int e, int f, int g, int h) { return a + b + c + d + e + f + g * h; } f(1, 2, 3, 4, 5, 6, 7, 8);'
+
+# test passing and returning structs by value
+STRUCTS='struct Pair { int a; int b; }; struct Triple { int a; int b; int c; };'
+assert_program 5 "$STRUCTS struct Pair make_pair(int a, int b); struct Pair p; p = make_pair(9, 4); p.a - p.b;" $C_FUNCTION_OBJ
+assert_program 7 "$STRUCTS int pair_diff(struct Pair p); struct Pair p; p.a = 10; p.b = 3; pair_diff(p);" $C_FUNCTION_OBJ
+assert_program 123 "$STRUCTS struct Triple make_triple(int a, int b, int c); struct Triple t; t = make_triple(1, 2, 3); t.a * 100 + t.b * 10 + t.c;" $C_FUNCTION_OBJ
+assert_program 23 "$STRUCTS int triple_weighted(struct Triple t); struct Triple t; t.a = 1; t.b = 2; t.c = 3; triple_weighted(t) - 100;" $C_FUNCTION_OBJ
+assert_program 57 "$STRUCTS int pair_after_five(int a, int b, int c, int d, int e, struct Pair p); struct Pair p; p.a = 6; p.b = 7; pair_after_five(1, 2, 3, 4, 5, p);" $C_FUNCTION_OBJ
+assert_program 59 "$STRUCTS int f(struct Pair p, struct Triple t) { return p.a * p.b + t.a + t.b * t.c; } call_pair_callback(f);" $C_FUNCTION_OBJ
+assert_program 62 "$STRUCTS struct Triple f(int x) { struct Triple t; t.a = x; t.b = x * 10; t.c = x * 20; return t; } call_triple_maker(f);" $C_FUNCTION_OBJ
+assert_program 21 "$STRUCTS struct Pair swap(struct Pair p) { struct Pair q; q.a = p.b; q.b = p.a; return q; } struct Pair p; p.a = 1; p.b = 2; p = swap(p); p.a * 10 + p.b;"
+
+# test initializers
+assert_program 3 'int x = 3; x;'
+assert_program 6 'int a[3] = {1, 2, 3}; a[0] + a[1] + a[2];'
+assert_program 7 'int a[] = {1, 2, 4}; a[0] + a[1] + a[2];'
+assert_program 0 'int a[5] = {1, 2}; a[2] + a[3] + a[4];'
+assert_program 9 'int f() { int a[4] = {1, [3] = 8}; return a[0] + a[1] + a[3]; } f();'
+assert_program 7 'struct P { int a; int b; }; struct P p = {.b = 5, .a = 2}; p.a + p.b;'
+assert_program 12 'struct P { int x; char c; }; struct P ps[2] = {{1, 2}, {.c = 9}}; ps[0].x + ps[0].c + ps[1].c;'
+assert_program 104 'char s[] = "hi"; s[0];'
+assert_program 0 'char s[4] = "ab"; s[2] + s[3];'
+assert_program 105 'char* s = "hi"; s[1];'
+assert_program 2 'int a[3]; int* p = a; int* q = a + 2; q - p;'
+assert_program 5 'int g = 5; int f() { return g; } f();'
+assert_program 11 'int g; int f() { g = 11; return 0; } f(); g;'
+assert_program 10 'int f() { return 10; } int g = f(); g;'
+assert_program 8 'int x = 3; int* p = &x; int y = 5; *p + y;'
+assert_fail_compile 'int a[2] = {1, 2, 3};'
+assert_fail_compile 'struct P { int a; }; struct P p = {1, 2};'
+assert_fail_compile 'char s[2] = "abc";'
+
+# test static and extern storage classes
+assert_program 3 'int counter() { static int n = 0; n = n + 1; return n; } counter(); counter(); counter();'
+assert_program 9 'int f() { static int a[3] = {1, 2, 3}; a[0] = a[0] * 2; return a[0] + a[1] + a[2]; } f(); f();'
+assert_program 7 'static int s = 7; int f() { return s; } f();'
+assert_program 42 'extern int extern_value; extern_value;' $C_FUNCTION_OBJ
+assert_program 43 'int f() { extern int extern_value; extern_value = extern_value + 1; return 0; } f(); extern_value;' $C_FUNCTION_OBJ
+# a static function does not clash with a function of the same name in another object
+assert_program 4 'static int vsum() { return 4; } vsum();' $C_FUNCTION_OBJ
+assert_fail_compile 'int f(int x) { static int n = x; return n; } f(1);'
+
+# test comments and line splicing
+assert_program 3 '// comment
+int x = 3; /* block
+comment */ x; // trailing'
+assert_program 6 'int/**/a = 6; a;'
+assert_program 5 'int x = 10/*c*//2; x;'
+assert_program 47 'char* s = "//x"; s[0];'
+assert_program 7 'ret\
+urn 7;'
+assert_program 3 'int x = 3; // a comment continued by a backslash \
+x = 5;
+x;'
+assert_program 6 '#define X /* a block comment
+spanning lines */ 6
+int f() { return X; }
+f();'
+assert_compile_error '--> <stdin>:4:8' '#define X /* a block comment
+spanning lines */ 6
+int x = X;
+return y;'
+assert_fail_compile 'int x; /* never closed
+x;'
+
+# test the preprocessor
+assert_program 42 '#define ANSWER 42
+ANSWER;'
+assert_program 6 '#define A B + 1
+#define B 5
+A;'
+assert_program 3 '#define X 3
+int X2 = 2; X;'
+assert_program 2 '#define X 2
+#undef X
+int X = 2; X;'
+assert_program 5 '#define self self
+int self = 5; self;'
+assert_program 49 '#include "headers_for_test/constants.h"
+#include "headers_for_test/constants.h"
+#include "headers_for_test/nested.h"
+nested_value * NESTED;'
+assert_program 84 '#include "headers_for_test/constants.h"
+DOUBLE_ANSWER;'
+assert_program 9 '#include <search_path.h>
+FROM_SEARCH_PATH;' '' '-I headers_for_test/include'
+assert_program 9 '#include "search_path.h"
+FROM_SEARCH_PATH;' '' '-Iheaders_for_test/include'
+assert_fail_compile '#include "headers_for_test/missing.h"'
+assert_fail_compile '#unknown'
+
+# test function-like macros
+assert_program 7 '#define ADD(a, b) a + b
+ADD(3, 4);'
+assert_program 49 '#define SQUARE(x) ((x) * (x))
+SQUARE(3 + 4);'
+assert_program 2 '#define F(x) x
+#define G F(F(2))
+G;'
+assert_program 5 '#define F(a, b) a - b
+#define G(x) F(x, 2)
+G(7);'
+assert_program 3 '#define PICK(a, b) b
+PICK(
+  1,
+  3);'
+assert_program 1 '#define ONE() 1
+ONE();'
+assert_program 8 '#define ID(x) x
+ID(ID(4)) + ID((2)) + 2;'
+assert_program 6 '#define SUM(...) sum(__VA_ARGS__)
+int sum(int a, int b, int c) { return a + b + c; }
+SUM(1, 2, 3);'
+assert_program 6 '#define CALL(f, ...) f(0, ## __VA_ARGS__)
+int add_one(int a) { return a + 1; } int add_three(int a, int b) { return a + b + 3; }
+CALL(add_one) + CALL(add_three, 2);'
+assert_program 99 '#define STR(x) #x
+char* s = STR(c);
+s[0];'
+assert_program 34 '#define STR(x) #x
+char* s = STR("a");
+s[0];'
+assert_program 12 '#define CAT(a, b) a ## b
+int xy = 12; CAT(x, y);'
+assert_program 42 '#define CAT(a, b) a ## b
+CAT(4, 2);'
+# recursive macros terminate
+assert_program 4 'int f(int x) { return x; }
+#define f(x) x + f(x)
+f(2);'
+assert_program 7 'int A(int n) { return n; }
+#define A(x) B(x) + 1
+#define B(x) A(x) + x
+A(3);'
+assert_fail_compile '#define F(a, b) a
+F(1);'
+assert_fail_compile '#define F(a) #b'
+assert_fail_compile '#define F(a) a ## b
+F(+);'
+
+# test conditional compilation
+assert_program 1 '#if 1
+1;
+#else
+2;
+#endif'
+assert_program 2 '#if 0
+1;
+#elif 2 > 1 && defined(__STDC__)
+2;
+#else
+3;
+#endif'
+assert_program 3 '#ifdef FOO
+1;
+#endif
+#ifndef FOO
+3;
+#endif'
+assert_program 5 '#define V 3
+#if V * 2 == 6 && !defined BAR && (1 ? 5 : 0 / 0) == 5 && 0x10 == 16 && 010 == 8 && (1 << 3) == 8 && -1 < 0 && ~0 == -1
+5;
+#endif'
+assert_program 6 '#if -1 < 0u
+1;
+#elif -1 > 0u && 0xFFFFFFFFFFFFFFFF == -1 && (1 ? -1 : 0u) > 0 && -1u >> 63 == 1 && -1 >> 63 == -1 && 18446744073709551615 / 2 == 0x7FFFFFFFFFFFFFFF && -8 / 2 == -4 && 0u - 1 > 0 && !(0u < -1) == 0
+6;
+#endif'
+assert_program 4 '#if 0
+#if 1
+#error never
+#endif
+#garbage
+#else
+4;
+#endif'
+assert_program 0 "#if 'a' == 97 && UNDEFINED_NAME == 0
+0;
+#endif"
+assert_program 7 '#if __has_include("headers_for_test/nested.h") && !__has_include(<none.h>)
+7;
+#endif'
+assert_program 1 '#if __STDC_VERSION__ >= 201112L && __x86_64__ && defined(__LINE__)
+1;
+#endif'
+assert_program 5 '__LINE__ + 4;'
+assert_program 59 '
+#line 50
+__LINE__ + 9;'
+assert_program 60 'char* f = __FILE__; f[0];'
+assert_program 25 '#ifdef FOO
+SQ(BAR);
+#endif' '' '-DFOO -D BAR=5 -DSQ(x)=x*x'
+assert_program 1 '#ifdef FOO
+#error FOO is defined
+#endif
+1;' '' '-DFOO -UFOO'
+assert_fail_compile '#error custom message'
+assert_fail_compile '#if 1'
+assert_fail_compile '#endif'
+assert_fail_compile '#if 1
+#else
+#else
+#endif'
+assert_fail_compile '#if 1 / 0
+#endif'
+assert_fail_compile '#if 1 +
+#endif'
+assert_compile_error 'error[E0006]: Preprocess error: line number 99999999999999999999 out of range in #line' '#line 99999999999999999999'
+assert_compile_error 'error[E0006]: Preprocess error: line number 2147483648 out of range in #line
+ --> <stdin>:1:7' '#line 2147483648'
+assert_compile_error '2147483647 | missing;' '#line 2147483647
+missing;'
+
+# test source locations of compile errors
+assert_compile_error '--> <stdin>:1:8' 'int x; y;'
+assert_compile_error '--> <stdin>:2:18' 'int x;
+int f() { return z + 1; }'
+assert_compile_error '--> <stdin>:2:3' 'int x;
+  yy = 1;'
+assert_compile_error '--> <stdin>:2:1' 'struct P { int x; }; struct P p;
+p.y = 3;'
+assert_compile_error '--> <stdin>:3:9' '#define USE(x) (x)
+int a;
+a + USE(missing);'
+assert_compile_error '3 | a + TWICE(1) + missing;
+  |                ^^^^^^^' '#define TWICE(x) ((x) + (x))
+int a;
+a + TWICE(1) + missing;'
+assert_compile_error '--> headers_for_test/undefined.h:2:11' '#include "headers_for_test/undefined.h"'
+
+# test the rendering of diagnostics
+assert_compile_error 'error[E0002]: Undefined variable: y
+ --> <stdin>:1:8
+  |
+1 | int x; y;
+  |        ^
+  |
+  = help: declare `y` before using it' 'int x; y;'
+assert_compile_error 'error[E0007]: Called object n is not a function
+  --> <stdin>:10:12
+   |
+10 |     return n(1);
+   |            ^^^^
+  ::: <stdin>:9:5
+   |
+ 9 |     int n = 3;
+   |     ---------- `n` declared here
+   |
+   = note: `n` has type int' '
+
+
+
+
+
+
+int f() {
+    int n = 3;
+    return n(1);
+}'
+assert_compile_error 'error[E0001]: unexpected token `;`' 'int x = ;'
+assert_compile_error 'error[E0001]: the number 99999999999 is too large
+ --> <stdin>:1:7' 'int a[99999999999];'
+assert_compile_error 'error[E0001]: the number 99999999999 is too large' 'int a[2] = {[99999999999] = 1};'
+assert_compile_error 'error[E0001]: the number 99999999999 is too large' 'int x = 99999999999;'
+
+# test the kinds of type errors
+assert_compile_error 'error[E0003]: Mismatched types: left is int*, right is int' 'int x; int* p; (p * x)[0];'
+assert_compile_error 'error[E0009]: Invalid operand of -: int*' 'int* p; -p + 1;'
+assert_compile_error 'error[E0010]: int cannot be dereferenced' 'int x; *x + 1;'
+assert_compile_error 'error[E0011]: int is not an array or a pointer' 'int x; x[0];'
+assert_compile_error 'error[E0012]: int is not a struct or union' 'int x; x.a;'
+assert_compile_error 'error[E0013]: int is not a pointer to a struct or union' 'int x; x->a;'
+assert_compile_error 'error[E0014]: struct P has no member y' 'struct P { int x; }; struct P p; p.y;'
+assert_compile_error 'error[E0015]: Called object of type int is not a function' '(1)(2);'
+assert_compile_error 'error[E0017]: Wrong number of arguments: expected 2, found 1' 'int f(int a, int b) { return a + b; } f(1);'
+assert_compile_error 'error[E0017]: Wrong number of arguments: expected 2, found 3' 'int f(int a, int b) { return a + b; } int (*g)(int, int) = f; g(1, 2, 3);'
+assert_compile_error 'error[E0017]: Wrong number of arguments: expected 1 or more, found 0' 'int printf(char* format, ...); printf();'
+assert_compile_error 'error[E0018]: struct Q is an incomplete type' 'struct Q q;'
+assert_compile_error 'error[E0019]: va_start used in function with fixed arguments' 'int f(int n) { va_list ap; va_start(ap, n); return 0; } f(1);'
+assert_compile_error 'error[E0018]: void is an incomplete type' 'void v;'
+assert_compile_error 'error[E0025]: int is not a va_list' 'int f(int n, ...) { int x; va_start(x, n); return 0; }'
+assert_compile_error 'error[E0025]: int is not a va_list' 'int x; int y; va_copy(x, y);'
+assert_compile_error 'error[E0026]: va_arg of struct P is not supported, which only fetches integers and pointers' 'struct P { int x; }; int f(int n, ...) { va_list ap; va_start(ap, n); struct P p = va_arg(ap, struct P); return p.x; }'
+assert_compile_error 'error[E0027]: int[1000000000] is too large' 'int a[1000000000];'
+assert_compile_error 'error[E0027]: int[1000000000] is too large' 'int f() { int a[1000000000]; return 0; }'
+assert_compile_error 'error[E0027]: struct P is too large' 'struct P { char a[4000000000]; char b[4000000000]; };'
+assert_compile_error 'error[E0028]: Top level statement in a program defining main
+ --> <stdin>:1:26' 'int main() { return 0; } 1 + 2;'
+assert_compile_error 'error[E0004]: Invalid initializer: initializer of global variable g is not constant' 'int f() { return 1; } int g = f(); int main() { return g; }'
+assert_compile_error 'error[E0009]: Invalid operand of *: struct P' 'struct P { int x; }; struct P q; struct P r; q * r;'
+assert_compile_error 'error[E0023]: Incompatible types: int cannot be converted to struct P' 'struct P { int x; }; struct P q; q = 5;'
+assert_compile_error 'error[E0023]: Incompatible types: struct P cannot be converted to int' 'struct P { int x; }; struct P q; int x; x = q;'
+assert_compile_error 'error[E0023]: Incompatible types: struct Q cannot be converted to struct P' 'struct P { int x; }; struct Q { int x; }; struct P q; struct Q r; q = r;'
+assert_compile_error 'error[E0023]: Incompatible types: int* cannot be converted to int[3]' 'int a[3]; int b[3]; a = b;'
+assert_compile_error 'error[E0023]: Incompatible types: char* cannot be converted to char[3]' 'char s[3]; s = "ab";'
+assert_compile_error 'error[E0023]: Incompatible types: struct P cannot be converted to int' 'struct P { int x; }; struct P q; int f() { return q; }'
+assert_compile_error 'error[E0023]: Incompatible types: struct P cannot be converted to int' 'struct P { int x; }; struct P q; int f(int x) { return x; } f(q);'
+assert_compile_error 'error[E0024]: struct P is used where a scalar is required' 'struct P { int x; }; struct P q; if (q) { 1; }'
+assert_compile_error 'error[E0024]: struct P is used where a scalar is required' 'struct P { int x; }; struct P q; struct P r; q == r;'
+assert_compile_error '= note: expected one of "&", "(", "*", "+", "-", "va_arg", "va_copy", "va_end", "va_start", "{", string literal, character literal, number, identifier' 'int x = ;'
+assert_compile_error 'error[E0001]: unexpected end of input' '1 + 2'
+assert_compile_error 'error[E0005]: Unterminated comment
+ --> <stdin>:1:8' 'int x; /* never closed'
+assert_compile_error 'error[E0006]: Preprocess error: macro name missing in #define
+ --> <command line>:1:1' '1;' '-D1'
+assert_compile_error $'\e[1;31merror[E0002]\e[0m\e[1m: Undefined variable: y\e[0m' 'y;' '-fdiagnostics-color=always'
+assert_compile_error 'error[E0002]: Undefined variable: y' 'y;' '-fdiagnostics-color=never'
+assert 1 ${RUST_9CC} -fdiagnostics-color=sometimes '1;'
+
+# test reporting multiple errors
+assert_compile_error 'error: aborting due to 4 previous errors' 'int x = ;
+int y = 3 +;
+{ 1 + ; 2; }
+int f() { int a; a = ; return a; }
+x;'
+assert_compile_error '--> <stdin>:2:16' 'int f() { return 1 }
+int g() { 1 +  }
+x;'
+assert_compile_error 'error: aborting due to 3 previous errors' 'int f() { return 1 }
+int g() { 1 +  }
+x;'
+assert_compile_error 'error: aborting due to 4 previous errors' 'int f() {
+  int x = 1
+  x = 2;
+  return a;
+}
+int g() { b; return c; }'
+assert_compile_error 'error[E0002]: Undefined variable: c' 'int f() {
+  int x = 1
+  x = 2;
+  return a;
+}
+int g() { b; return c; }'
+assert_compile_error 'error: aborting due to 2 previous errors' 'int x = 1 +;
+int main() { return x + z; }'
+assert_compile_error '--> <stdin>:5:1' 'int f() { return a; }
+int g() { b; c; return 0; }
+d;
+struct Q q;
+e;'
+assert_compile_error 'error: aborting due to 6 previous errors' 'int f() { return a; }
+int g() { b; c; return 0; }
+d;
+struct Q q;
+e;'
+assert_compile_error 'error: too many errors emitted, stopping now [-ferror-limit=2]' 'a; b; c;' '-ferror-limit=2'
+assert_compile_error 'error: aborting due to 3 previous errors' 'a; b; c;' '-ferror-limit=0'
+assert 1 ${RUST_9CC} -ferror-limit=many '1;'
+
+# test warnings
+assert_compile_warning 'warning[-Wunused-variable]: Unused variable: y' 'int f(int x) { int y; return x; } f(1);' '-Wunused-variable'
+assert_compile_warning 'warning[-Wunused-variable]: Unused variable: y' 'int f(int x) { int y = x; return x; } f(1);' '-Wall'
+assert_compile_warning 'warning[-Wunused-parameter]: Unused parameter: y' 'int f(int x, int y) { return x; } f(1, 2);' '-Wextra'
+assert_compile_warning ' --> <stdin>:1:14' 'int f(int x, int y) { return x; } f(1, 2);' '-Wunused-parameter'
+assert_compile_warning 'warning[-Wshadow]: Declaration of x shadows a parameter' 'int f(int x) { if (x) { int x; x = 2; return x; } return 0; } f(1);' '-Wshadow'
+assert_compile_warning 'warning[-Wshadow]: Declaration of g shadows a global variable' 'int g; int f() { int g; g = 1; return g; } f();' '-Wshadow'
+assert_compile_warning '- shadowed declaration is here' 'int g; int f() { int g; g = 1; return g; } f();' '-Wshadow'
+assert_compile_warning 'warning[-Wshadow]: Declaration of x shadows a local variable
+ --> <stdin>:1:24' 'int f() { int x = 1; { int x = 2; return x; } return x; } f();' '-Wshadow'
+assert_compile_warning 'warning[-Wshadow]: Declaration of y shadows a local variable' 'int f() { int y = 1; while (y) { if (y) { int y = 0; return y; } } return 0; } f();' '-Wshadow'
+assert_compile_warning 'warning[-Wreturn-type]: Control reaches the end of non-void function f' 'int f(int x) { if (x) { return 1; } } f(1);'
+assert_compile_warning ' --> <stdin>:1:37' 'int f(int x) { if (x) { return 1; } } f(1);'
+assert_compile_warning 'warning[-Wimplicit-function-declaration]: Implicit declaration of function foo' 'foo();' '-Wall'
+assert_compile_warning 'warning[-Wint-conversion]: Incompatible integer to pointer conversion from int to int*' 'int *p = 5;'
+assert_compile_warning 'warning[-Wint-conversion]: Incompatible pointer to integer conversion from char* to int' 'int f(int x) { return x; } f("a");'
+assert_compile_warning 'warning[-Wint-conversion]: Incompatible pointer to integer conversion from int* to int' 'int x; int f() { return &x; } f();'
+assert_compile_warning 'warning[-Wunreachable-code]: Unreachable code' 'int f() { return 1; f(); } f();' '-Wunreachable-code'
+assert_compile_warning 'warning[-Wunreachable-code]: Unreachable code' 'int f() { while (1) { f(); } return 0; } 1;' '-Wunreachable-code'
+assert_compile_warning 'warning: 3 warnings emitted' 'int f(int x, int y) { int z; return 0; } f(1, 2);' '-Wall -Wextra'
+assert_compile_warning 'warning[-Wcpp]: #warning not yet done
+ --> <stdin>:2:2' '1;
+#warning not  yet done
+2;'
+assert_no_warning 'int f(int x) { int y; y = x; while (1) { if (y) { break; } } for (;;) { return y; } } int *p = 0; f(1);' '-Wall -Wextra -Wshadow -Wunreachable-code'
+assert_no_warning 'int f(int x) { if (x) { return 1; } else { return 0; } } f(1);' '-Wall'
+assert_no_warning 'int f(int x, ...) { va_list ap; va_start(ap, x); va_end(ap); return 0; } f(1);' '-Wall -Wextra'
+assert_no_warning 'int f(int x) { int y; return x; } f(1);'
+assert_no_warning 'int *p = 5;' '-Wno-int-conversion'
+assert_no_warning 'int f(int x) { int y; return x; } f(1);' '-Wall -Wno-unused-variable'
+assert_no_warning '#warning not yet done' '-Wno-cpp'
+assert_compile_error 'error[-Wreturn-type]: Control reaches the end of non-void function f' 'int f() { 1; } f();' '-Werror'
+assert_compile_error '= note: `-Wreturn-type` is treated as an error' 'int f() { 1; } f();' '-Werror'
+assert_compile_error 'error[-Wunused-variable]: Unused variable: y' 'int f(int x) { int y; return x; } f(1);' '-Werror=unused-variable'
+assert_compile_warning 'warning[-Wreturn-type]' 'int f() { 1; } f();' '-Werror -Wno-error'
+assert_compile_error 'error[-Wcpp]: #warning not yet done' '#warning not yet done' '-Werror'
+assert 1 ${RUST_9CC} -Wno-such-warning '1;'
+
+# test controlling warnings with pragmas
+assert_no_warning '#pragma GCC diagnostic ignored "-Wreturn-type"
+int f() { 1; } f();'
+assert_compile_warning ' --> <stdin>:5:14' '#pragma GCC diagnostic push
+#pragma GCC diagnostic ignored "-Wreturn-type"
+int f() { 1; }
+#pragma GCC diagnostic pop
+int g() { 1; } f() + g();'
+assert_compile_warning 'warning: 1 warning emitted' '#pragma clang diagnostic push
+#pragma clang diagnostic ignored "-Wreturn-type"
+int f() { 1; }
+#pragma clang diagnostic pop
+int g() { 1; } f() + g();'
+assert_compile_warning 'warning[-Wunused-parameter]' '#pragma GCC diagnostic warning "-Wunused-parameter"
+int f(int x) { return 0; } f(1);'
+assert_compile_error 'error[-Wunused-parameter]' '#pragma GCC diagnostic error "-Wunused-parameter"
+int f(int x) { return 0; } f(1);'
+assert_no_warning '#pragma GCC diagnostic ignored "-Wcpp"
+#warning not yet done'
+
+# test machine-readable diagnostics
+assert_compile_error '{"severity":"error","code":"E0002","message":"Undefined variable: y","spans":[{"file":"<stdin>","start":{"line":1,"column":1},"end":{"line":1,"column":2},"primary":true,"label":null}],"notes":[],"help":["declare `y` before using it"],"fixits":[]}' 'y;' '--diagnostics-format=json'
+assert_compile_error '"fixits":[{"span":{"file":"<stdin>","start":{"line":1,"column":20},"end":{"line":1,"column":20}},"replacement":";","message":"insert `;`"}]' 'int f() { return 1 }' '--diagnostics-format=json'
+assert_compile_error '"label":"`g` declared here"' 'int g; g(1);' '--diagnostics-format=json'
+assert_compile_warning '{"severity":"warning","code":"-Wint-conversion","message":"Incompatible integer to pointer conversion from int to int*"' 'int *p = 5;' '--diagnostics-format=json'
+assert_compile_warning '{"severity":"warning","code":"-Wcpp","message":"#warning not yet done","spans":[{"file":"<stdin>","start":{"line":1,"column":2}' '#warning not yet done' '--diagnostics-format=json'
+assert_compile_error '{"severity":"error","code":"E0020","message":"Unknown warning option '"'"'-Wfoo'"'"'","spans":[]' '1;' '-Wfoo --diagnostics-format=json'
+assert_compile_error '{"severity":"error","code":"E0006","message":"Preprocess error: #error oops","spans":[{"file":"<stdin>","start":{"line":2,"column":2},"end":{"line":2,"column":2},"primary":true,"label":null}]' 'int a;
+#error oops' '--diagnostics-format=json'
+assert_compile_error '"message":{"text":"Unterminated comment"},"locations":[{"physicalLocation":{"artifactLocation":{"uri":"<stdin>"},"region":{"startLine":1,"startColumn":3,"endLine":1,"endColumn":3}}}]' '1 /* x' '--diagnostics-format=sarif'
+assert_compile_error '"version":"2.1.0","runs":[{"tool":{"driver":{"name":"rust-9cc"' 'y;' '--diagnostics-format=sarif'
+assert_compile_error '"results":[{"ruleId":"E0002","level":"error","message":{"text":"Undefined variable: y"},"locations":[{"physicalLocation":{"artifactLocation":{"uri":"<stdin>"},"region":{"startLine":1,"startColumn":1,"endLine":1,"endColumn":2}}}]' 'y;' '--diagnostics-format=sarif'
+assert_compile_error '"fixes":[{"description":{"text":"insert `;`"},"artifactChanges":[{"artifactLocation":{"uri":"<stdin>"},"replacements":[{"deletedRegion":{"startLine":1,"startColumn":20,"endLine":1,"endColumn":20},"insertedContent":{"text":";"}}]}]}]' 'int f() { return 1 }' '--diagnostics-format=sarif'
+assert_compile_error '"relatedLocations":[{"id":0,"physicalLocation"' 'int g; g(1);' '--diagnostics-format=sarif'
+assert_compile_error '= help: insert `;`' 'int f() { return 1 }'
+assert_compile_error 'error[E0020]: Invalid argument '"'"'xml'"'"' to --diagnostics-format' '1;' '--diagnostics-format=xml'
+
+# test the compiler driver
+driver_dir=$(mktemp -d)
+echo 'int add(int a, int b) { return a + b; }' > "$driver_dir/add.c"
+echo 'int add(int a, int b); add(sub(7, 2), 1);' > "$driver_dir/main.c"
+assert 0 ${RUST_9CC} -S -o "$driver_dir/add.s" "$driver_dir/add.c"
+assert 0 grep -q '^add:' "$driver_dir/add.s"
+assert 1 grep -q '^main:' "$driver_dir/add.s"
+echo 'static int x; int y = 2;' > "$driver_dir/globals.c"
+assert 0 ${RUST_9CC} -S -o "$driver_dir/globals.s" "$driver_dir/globals.c"
+assert 1 grep -q '^main:' "$driver_dir/globals.s"
+assert 0 ${RUST_9CC} -c -o "$driver_dir/add.o" "$driver_dir/add.c"
+assert 0 ${RUST_9CC} -o "$driver_dir/a.out" "$driver_dir/main.c" "$driver_dir/add.o" $C_FUNCTION_OBJ
+assert 6 "$driver_dir/a.out"
+assert 0 ${RUST_9CC} -o "$driver_dir/f.out" "$driver_dir/main.c" "$driver_dir/add.o" "$driver_dir/globals.c" $C_FUNCTION_OBJ
+assert 6 "$driver_dir/f.out"
+assert 0 ${RUST_9CC} -o "$driver_dir/b.out" "$driver_dir/main.c" "$driver_dir/add.s" -L. -lc $C_FUNCTION_OBJ
+assert 6 "$driver_dir/b.out"
+assert 0 ${RUST_9CC} -o "$driver_dir/e.out" "$driver_dir/main.c" "$driver_dir/add.o" $C_FUNCTION_OBJ -Wl,-rpath,$driver_dir/lib,-z,now
+assert 6 "$driver_dir/e.out"
+readelf -d "$driver_dir/e.out" > "$driver_dir/dynamic.txt"
+assert 0 grep -q "$driver_dir/lib" "$driver_dir/dynamic.txt"
+assert 0 grep -q BIND_NOW "$driver_dir/dynamic.txt"
+rust_9cc_path=$(realpath ${RUST_9CC})
+(cd "$driver_dir" && $rust_9cc_path -S main.c add.c) || exit 1
+assert 0 test -f "$driver_dir/main.s"
+assert 0 test -f "$driver_dir/add.s"
+echo 'int main() { return 4; }' | ${RUST_9CC} -o "$driver_dir/c.out" -
+assert 4 "$driver_dir/c.out"
+assert_driver_error 'error[E0020]: No input files'
+assert_compile_error 'error[E0020]: Unknown option '"'"'-fno-such-option'"'"'' '1;' '-fno-such-option'
+assert_compile_error 'error[E0020]: Cannot specify -o with -c or -S with multiple files' '1;' "-c $driver_dir/main.c"
+assert_driver_error 'error[E0021]: no_such_file.c: No such file or directory' -S no_such_file.c
+echo 'y;' > "$driver_dir/y.c"
+echo 'z;' > "$driver_dir/z.c"
+${RUST_9CC} --diagnostics-format=sarif -S "$driver_dir/y.c" "$driver_dir/z.c" 2> "$driver_dir/sarif.txt"
+sarif_logs=$(grep -o '"version":"2.1.0"' "$driver_dir/sarif.txt" | wc -l)
+sarif_results=$(grep -o '"ruleId":"E0002"' "$driver_dir/sarif.txt" | wc -l)
+assert 0 test "$sarif_logs" = 1
+assert 0 test "$sarif_results" = 2
+assert 1 ${RUST_9CC} -o "$driver_dir/d.out" "$driver_dir/add.c"
+rm -rf "$driver_dir"
+
+# test register allocation, where values live across calls are kept and spilled if needed
+assert_program 90 'int id(int x) { return x; }
+int f() {
+  int a = id(1); int b = id(2); int c = id(3); int d = id(4); int e = id(5); int g = id(6);
+  int h = id(7); int i = id(8); int j = id(9); int k = id(10); int l = id(11); int m = id(12);
+  return a + b + c + d + e + g + h + i + j + k + l + m + id(a * m);
+}
+f();'
+assert_program 22 'int sub(int a, int b) { return a - b; } int f(int a, int b, int c) { return sub(c, a) * 10 + sub(b, c); } f(1, 5, 3);'
+
+# test constant folding and propagation, which wrap around and leave traps to run time
+assert_program 7 'int x = 65536; x * x * x * x * x + 7;'
+assert_program 7 'int x = 65536; x * x * x * x * x + 7;' '' '-O0'
+assert_program 4 'int f() { int n = 100; char c = n + n; return c + 60; } f();'
+assert_program 136 '1 / 0;'
+assert_program 136 'int f() { int m = 65536 * 65536 * 65536 * 32768; return m / -1; } f();'
+assert_program 5 'int f() { int x = 0; int y = 5; if (x) { y = 1 / x; } return y; } f();'
+assert_program 7 'int f() { int i = 0; int n = 3; while (1) { if (i == n) { return i * 2 + 1; } i = i + 1; } } f();'
+assert_program 3 'int f() { int x = 3; while (0) { x = 1; } return x; } f();'
+
+# test dumping intermediate results
+assert_emit 'Identifier "int" <stdin>:2:1
+Identifier "x" <stdin>:2:5
+Punctuator "=" <stdin>:2:7
+Number "2" <stdin>:2:9
+Punctuator ";" <stdin>:2:12' '#define TWO 2
+int x = TWO;' '--emit=tokens'
+assert_emit 'FuncDef f: int (int, char*)
+  Parameter a: int
+  Parameter s: char*
+  Block
+    Return
+      Sub
+        Variable a
+        Deref
+          Variable s
+ExprStatement
+  Call f
+    Number 1
+    StringLiteral "a\n"' 'int f(int a, char *s) { return a - *s; } f(1, "a\n");' '--emit=ast'
+assert_emit 'VarDef a: int[2]
+  InitializerList
+    Number 1
+    Designated [1]
+      Number 2
+For
+  Empty
+  Less
+    Number 0
+    Number 1
+  Empty
+  Block
+    Break' 'int a[2] = {1, [1] = 2}; for (; 0 < 1;) { break; }' '-fdump-ast'
+assert_emit 'VarDef p #0: char*
+Assign
+  Variable p #0 : char*
+  ImplicitConversion ArrayToPointer : char*
+    StringLiteral "ab" : char[3]
+ExprStatement
+  Add : char*
+    Variable p #0 : char*
+    Number 1 : int' 'char *p; p = "ab"; p + 1;' '--emit=typed-ast'
+assert_emit 'FuncDef f #0: int (int, ...)
+  Parameter a #1: int
+  Block
+    Return
+      Variable a #1 : int
+VarDef c #2: char
+  Initialize +0: char
+    ImplicitConversion Assignment : char
+      Number 1 : int
+ExprStatement
+  Call : int
+    Variable f #0 : int (int, ...)
+    ImplicitConversion Assignment : int
+      Variable c #2 : char
+    ImplicitConversion ArrayToPointer : char*
+      StringLiteral "x" : char[2]
+    AddressOf : char*
+      Variable c #2 : char' 'int f(int a, ...) { return a; } char c = 1; f(c, "x", &c);' '--emit=typed-ast'
+assert_emit "$(printf '1;' | ${RUST_9CC} -S -o - -)" '1;' '--emit=asm -o -'
+assert_emit '@c = global 1, align 1 [01]
+
+define i64 @main() {
+  registers %0: ptr, %1: i8, %2: i64, %3: i64
+bb0:
+  %0 = global @c
+  %1 = load %0
+  %2 = cast %1
+  %3 = call @f(%2)
+  ret %3
+}
+
+define i64 @f(i64 %0) {
+  registers %0: i64, %1: i64, %2: i64, %3: i64
+bb0:
+  %2 = mul %0, 2
+  %1 = copy %2
+  %3 = lt %1, 10
+  br %3, bb1, bb2
+bb1:
+  ret %1
+bb2:
+  ret 0
+}' 'int f(int x) { int y = x * 2; if (y < 10) { return y; } return 0; } char c = 1; f(c);' '--emit=ir'
+assert_emit '#0 = string "a"
+
+define i64 @f() {
+  $0 = slot 16, align 8 ; p
+  registers %0: ptr, %1: ptr, %2: ptr, %3: ptr
+bb0:
+  %0 = slot $0
+  store i64 %0, 3
+  %1 = slot $0
+  %2 = add %1, 8
+  %3 = string #0
+  store ptr %2, %3
+  ret 0
+}' 'struct P { int x; char *s; }; int f() { struct P p; p.x = 3; p.s = "a"; return 0; }' '--emit=ir'
+assert_emit 'digraph "f" {
+  node [shape=box, fontname=monospace];
+  bb0 [label="bb0:\l%1 = copy 0\ljmp bb1\l"];
+  bb1 [label="bb1: loop bb1, bb2, bb5 frontier bb1\l%2 = lt 0, %0\lbr %2, bb2, bb3\l"];
+  bb2 [label="bb2: frontier bb1, bb3\l%3 = eq %0, 2\lbr %3, bb4, bb5\l"];
+  bb3 [label="bb3:\lret %1\l"];
+  bb4 [label="bb4: frontier bb3\ljmp bb3\l"];
+  bb5 [label="bb5: frontier bb1\l%4 = add %1, %0\l%1 = copy %4\l%5 = sub %0, 1\l%0 = copy %5\ljmp bb1\l"];
+  bb0 -> bb1;
+  bb1 -> bb2 [label="true"];
+  bb1 -> bb3 [label="false"];
+  bb2 -> bb4 [label="true"];
+  bb2 -> bb5 [label="false"];
+  bb4 -> bb3;
+  bb5 -> bb1 [style=dashed];
+}' 'int f(int n) { int s = 0; while (n > 0) { if (n == 2) { break; } s = s + n; n = n - 1; } return s; }' '-fdump-cfg-dot'
+assert_emit 'define i64 @f(i64 %0) {
+  registers %0: i64, %1: i64, %2: i64, %3: i64, %4: i64, %5: i64, %6: i64
+bb0:
+  %6 = add %0, 17
+  ret %6
+}' 'int f(int a) { int x = 5 + 20 - 4 * 2; while (0) { x = 1; } if (x > 3) { return a + x; } return 0; }' '--emit=ir'
+assert_emit 'define i64 @f() {
+  registers %0: i64, %1: i64, %2: i64
+bb0:
+  %0 = add 5, 20
+  %1 = mul 4, 2
+  %2 = sub %0, %1
+  ret %2
+}' 'int f() { return 5 + 20 - 4 * 2; }' '--emit=ir -O0'
+assert_compile_error 'error[E0020]: Invalid argument '"'"'llvm'"'"' to --emit' '1;' '--emit=llvm'
+assert_compile_error 'error[E0020]: Invalid argument '"'"'cfg'"'"' to -fdump' '1;' '-fdump-cfg'
+assert_compile_error 'error[E0002]: Undefined variable: y' 'y;' '--emit=typed-ast'
+
+# test printing the AST back as C
+assert_emit 'int add(int a, int b) {
+    return a + b * (a - -b);
+}
+
+int x = add(1, 2);
+if (x == 3) {
+    x = 0;
+} else {
+    x = 1;
+}' 'int add(int a,int b){return a+b*(a- -b);} int x=add(1,2); if(x==3){x=0;}else{x=1;}' '--emit=c'
+assert_emit 'struct P {
+    int x;
+    char *s;
+    int (*f)(int, char*);
+};
+
+extern int g(int p0, char **p1, ...);
+struct P p = { 1, .s = "a\"b\\\n\001", [0] = 2 };' 'struct P { int x; char *s; int (*f)(int, char *); }; extern int g(int a, char **b, ...); struct P p = {1, .s = "a\"b\\\n\1", [0] = 2,};' '--emit=c'
+assert_round_trip 'struct S { int m; }; struct S s; int a[2]; int *p = &a[1]; int *q = &s.m; &(*p);'
+assert_round_trip 'int a[2][3]; int *p = &a; **q = 1; char c = '"'"'\n'"'"'; a[1][2] = *p / 2 - 3;'
+assert_round_trip 'int f(int x, ...) { va_list ap; va_start(ap, x); int y = va_arg(ap, int); va_end(ap); return y; }'
+assert_round_trip 'static int (*fp)(int) = 0; int h(int a[], char *s) { for (;;) { break; } for (a = 0; a < 1; a = a + 1) {} while (1 >= 0) { return (fp)(1); } }'
+assert_round_trip 'int (*t[2])(int); struct S { int (*f)(int); }; struct S o; t[0](1); o.f(2); (*t[1])(3); t[0](1) + o.f(2);'
+assert_round_trip 'union U { int i; char c; }; struct S { union U u; struct S *next; }; struct S s; s.u.i = 1; s.next->u.c = 2; {} {{ 1 != 2; 2 <= 3; }}'
+assert_round_trip 'int g(); char *h(void *p) { return "x" "y"; } int k[] = {}; va_list a; va_list b; va_copy(a, b); f(g(), h(0), 3 > 2);'
 echo OK